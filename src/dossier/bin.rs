@@ -1,4 +1,4 @@
-use glamour::App;
+use glamour::{App, GBufLayout, StreamMode};
 
 mod dossier_layer;
 use dossier_layer::DossierLayer;
@@ -34,6 +34,10 @@ pub struct TestOutput {
     time: u128,
     warmup: u32,
     length: u32,
+    #[serde(default)]
+    compact_g_buf: bool,
+    #[serde(default)]
+    stream_mode: String,
     data: Vec<TestRunResult>,
 }
 
@@ -42,6 +46,23 @@ pub struct TestSpec {
     resolution_index: usize,
     warmup: u32,
     length: u32,
+    #[serde(default)]
+    headless: bool,
+    #[serde(default)]
+    screenshots: bool,
+    /// Render the deferred runs with `GBufLayout::Compact`, to compare against the full layout.
+    #[serde(default)]
+    compact_g_buf: bool,
+    /// How instances are uploaded each frame, by `StreamMode`'s display name, e.g. "orphan".
+    #[serde(default)]
+    stream_mode: Option<String>,
+    /// Only run with this many cubes, e.g. the sandbox's 50_000, instead of stepping up to
+    /// `MAX_CUBES`.
+    #[serde(default)]
+    cubes: Option<u32>,
+    /// Only run with this many lights, instead of stepping up to `MAX_LIGHTS`.
+    #[serde(default)]
+    lights: Option<u32>,
 }
 
 /*
@@ -119,11 +140,11 @@ fn _all_test_run_set() -> Vec<TestRun> {
         .collect()
 }
 
-fn test_run_set_from_res(resolution: (u32, u32)) -> Vec<TestRun> {
+fn test_run_set_from_res(resolution: (u32, u32), lights: &[u32], cubes: &[u32]) -> Vec<TestRun> {
     DEFERRED
         .iter()
-        .flat_map(|r| LIGHTS.iter().map(|l| (r, l)).collect::<Vec<_>>())
-        .flat_map(|r| CUBES.iter().map(|c| (r.0, r.1, c)).collect::<Vec<_>>())
+        .flat_map(|r| lights.iter().map(|l| (r, l)).collect::<Vec<_>>())
+        .flat_map(|r| cubes.iter().map(|c| (r.0, r.1, c)).collect::<Vec<_>>())
         .map(|r| TestRun {
             resolution,
             deferred: *r.0,
@@ -141,15 +162,40 @@ fn main() {
     let spec: TestSpec = serde_json::from_reader(reader).unwrap();
 
     let resolution = RESOLUTIONS[spec.resolution_index];
-    let run_set = test_run_set_from_res(resolution);
-    let mut app = App::new("Glamour Dossier", resolution.0, resolution.1);
-    let dossier_layer = DossierLayer::new(
+    let lights = spec
+        .lights
+        .map_or_else(|| LIGHTS.to_vec(), |lights| vec![lights]);
+    let cubes = spec
+        .cubes
+        .map_or_else(|| CUBES.to_vec(), |cubes| vec![cubes]);
+    let run_set = test_run_set_from_res(resolution, &lights, &cubes);
+    let mut app = if spec.headless {
+        App::new_headless(resolution.0, resolution.1)
+    } else {
+        App::new("Glamour Dossier", resolution.0, resolution.1)
+    };
+    let mut dossier_layer = DossierLayer::new(
         "DossierLayer",
         resolution,
         run_set,
         spec.warmup,
         spec.length,
     );
+    if spec.screenshots {
+        dossier_layer.enable_screenshots();
+    }
+    if spec.compact_g_buf {
+        dossier_layer
+            .set_g_buf_layout(GBufLayout::Compact)
+            .expect("failed to create compact G-buffer");
+    }
+    if let Some(name) = &spec.stream_mode {
+        let mode = StreamMode::ALL
+            .iter()
+            .find(|mode| mode.to_string() == *name)
+            .unwrap_or_else(|| panic!("unknown stream mode \"{}\"", name));
+        dossier_layer.set_stream_mode(*mode);
+    }
     app.push_layer(Box::new(dossier_layer));
     app.run();
 }
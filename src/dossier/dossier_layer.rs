@@ -1,6 +1,9 @@
 use crate::{TestOutput, TestResult, TestRun, TestRunResult};
 use bracket_noise::prelude::*;
-use glamour::{glm, Camera, Layer, Renderer, Transform};
+use glamour::{
+    glm, Camera, FramebufferError, GBufLayout, Layer, PngSequence, PointLight, Renderer,
+    StreamMode, Transform,
+};
 use rand::{Rng, SeedableRng};
 use rayon::prelude::*;
 
@@ -13,7 +16,7 @@ pub struct DossierLayer {
     cube_distribution: rand::distributions::Uniform<f32>,
     cube_transforms: Vec<Transform>,
     light_count: usize,
-    light_transforms: Vec<Transform>,
+    lights: Vec<PointLight>,
     rng: rand_chacha::ChaCha8Rng,
     noise: FastNoise,
     test_run_set: Vec<TestRun>,
@@ -23,6 +26,8 @@ pub struct DossierLayer {
     test_run_index: usize,
     test_run_fps_timings: Vec<u128>,
     test_run_output: TestOutput,
+    test_run_screenshots: Option<PngSequence>,
+    test_run_captured: bool,
 }
 
 impl DossierLayer {
@@ -56,7 +61,7 @@ impl DossierLayer {
             cube_distribution: rand::distributions::Uniform::from(-100.0..100.0),
             cube_transforms: Vec::new(),
             light_count: test_run.lights as usize,
-            light_transforms: Vec::new(),
+            lights: Vec::new(),
             rng,
             noise,
             test_run_set,
@@ -69,10 +74,34 @@ impl DossierLayer {
                 time: 0,
                 warmup,
                 length,
+                compact_g_buf: false,
+                stream_mode: String::new(),
                 data: Vec::new(),
             },
+            test_run_screenshots: None,
+            test_run_captured: false,
         }
     }
+    /// Save a screenshot of each test run once it has warmed up, in a directory named by the
+    /// start time.
+    pub fn enable_screenshots(&mut self) {
+        let id = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_millis();
+        let dir = std::path::PathBuf::from(format!("{}-screenshots", id));
+        self.test_run_screenshots = Some(PngSequence::new(&dir, "run").expect("create failed"));
+    }
+    pub fn set_g_buf_layout(&mut self, layout: GBufLayout) -> Result<(), FramebufferError> {
+        self.renderer.set_g_buf_layout(layout)?;
+        self.test_run_output.compact_g_buf = layout == GBufLayout::Compact;
+        Ok(())
+    }
+    /// Falls back to orphaning if the context doesn't support `mode`, the output records which.
+    pub fn set_stream_mode(&mut self, mode: StreamMode) {
+        self.renderer.set_stream_mode(mode);
+        self.test_run_output.stream_mode = self.renderer.stream_mode().to_string();
+    }
     fn test_run(&self) -> TestRun {
         self.test_run_set[self.test_run_index]
     }
@@ -87,7 +116,7 @@ impl DossierLayer {
             .iter()
             .max()
             .unwrap_or(&self.test_run_length.as_nanos());
-        let avg = if self.test_run_fps_timings.len() != 0 {
+        let avg = if !self.test_run_fps_timings.is_empty() {
             self.test_run_fps_timings.iter().sum::<u128>() / self.test_run_fps_timings.len() as u128
         } else {
             self.test_run_length.as_nanos()
@@ -122,7 +151,7 @@ impl DossierLayer {
 
 impl Layer for DossierLayer {
     fn init(&mut self, app_context: &mut glamour::AppContext) {
-        let size = app_context.windowed_context().window().inner_size();
+        let size = app_context.size();
         self.renderer.resize(size.width, size.height);
         self.camera.aspect = size.width as f32 / size.height as f32;
     }
@@ -131,6 +160,7 @@ impl Layer for DossierLayer {
             self.collect_timing_results();
             self.test_run_timer = std::time::Instant::now();
             self.test_run_index += 1;
+            self.test_run_captured = false;
             if self.test_run_index < self.test_run_set.len() {
                 let test_run = self.test_run();
                 self.cube_count = test_run.cubes as _;
@@ -172,8 +202,8 @@ impl Layer for DossierLayer {
             ))
         });
 
-        self.light_transforms.resize_with(self.light_count, || {
-            Transform::from_pos(glm::vec3(
+        self.lights.resize_with(self.light_count, || {
+            PointLight::from_pos(glm::vec3(
                 rng.sample(range),
                 rng.sample(range),
                 rng.sample(range),
@@ -196,20 +226,30 @@ impl Layer for DossierLayer {
 
         let distance = 50.0;
         let noise = &self.noise;
-        self.light_transforms
+        self.lights
             .par_iter_mut()
             .enumerate()
-            .for_each(|(index, transform)| {
+            .for_each(|(index, light)| {
                 let offset = index as f32;
-                transform.position = glm::vec3(
+                light.position = glm::vec3(
                     noise.get_noise3d(time + offset, 0.0, 0.0),
                     noise.get_noise3d(0.0, time + offset, 0.0),
                     noise.get_noise3d(0.0, 0.0, time + offset),
                 ) * distance
             });
-        self.renderer.set_lights(&self.light_transforms);
+        self.renderer.set_lights(&self.lights);
 
         self.renderer.end_draw();
+
+        if let Some(screenshots) = &mut self.test_run_screenshots {
+            if !self.test_run_captured
+                && self.test_run_timer.elapsed() > self.test_run_warmup_length
+            {
+                let image = self.renderer.capture();
+                screenshots.save(&image).expect("write failed");
+                self.test_run_captured = true;
+            }
+        }
     }
     fn name(&self) -> &String {
         &self.name
@@ -1,4 +1,4 @@
-use crate::{perf_metrics_layer, Layer};
+use crate::{perf_metrics_layer, HeadlessContext, Layer};
 use glutin::{
     dpi,
     event::Event,
@@ -7,22 +7,66 @@ use glutin::{
 use perf_metrics_layer::PerfMetricsLayer;
 use std::time::{Duration, Instant};
 
+enum GlContext {
+    Windowed(glutin::ContextWrapper<glutin::PossiblyCurrent, glutin::window::Window>),
+    Headless(Box<HeadlessContext>),
+}
+
+impl GlContext {
+    /// Only for use within the event loop, which never runs headless.
+    fn windowed(&self) -> &glutin::ContextWrapper<glutin::PossiblyCurrent, glutin::window::Window> {
+        match self {
+            GlContext::Windowed(windowed_context) => windowed_context,
+            GlContext::Headless(_) => unreachable!("headless apps have no event loop"),
+        }
+    }
+    fn size(&self) -> dpi::PhysicalSize<u32> {
+        match self {
+            GlContext::Windowed(windowed_context) => windowed_context.window().inner_size(),
+            GlContext::Headless(headless_context) => headless_context.size(),
+        }
+    }
+}
+
 pub struct AppContext {
     fixed_timestep: Duration,
     max_frame_rate: f32,
     min_frame_timestep: Duration,
-    windowed_context: glutin::ContextWrapper<glutin::PossiblyCurrent, glutin::window::Window>,
+    gl_context: GlContext,
     imgui_platform: imgui_winit_support::WinitPlatform,
     event_poll_time: Duration,
     delta_time: Duration,
+    frame_count: u64,
     exit_requested: bool,
 }
 
 impl AppContext {
+    /// The windowed context. Panics when running headless; see `try_windowed_context`.
     pub fn windowed_context(
         &self,
     ) -> &glutin::ContextWrapper<glutin::PossiblyCurrent, glutin::window::Window> {
-        &self.windowed_context
+        self.try_windowed_context()
+            .expect("headless apps have no windowed context")
+    }
+    /// The windowed context, or `None` when running headless.
+    pub fn try_windowed_context(
+        &self,
+    ) -> Option<&glutin::ContextWrapper<glutin::PossiblyCurrent, glutin::window::Window>> {
+        match &self.gl_context {
+            GlContext::Windowed(windowed_context) => Some(windowed_context),
+            GlContext::Headless(_) => None,
+        }
+    }
+    pub fn is_headless(&self) -> bool {
+        self.try_windowed_context().is_none()
+    }
+    /// Size of the window, or of the offscreen framebuffer when headless.
+    pub fn size(&self) -> dpi::PhysicalSize<u32> {
+        self.gl_context.size()
+    }
+    /// Number of frames rendered so far.
+    pub fn frame_count(&self) -> u64 {
+        self.frame_count
     }
     pub fn delta_time(&self) -> Duration {
         self.delta_time
@@ -48,13 +92,16 @@ impl AppContext {
     }
 }
 
+// fields are dropped in order, so the layers' and imgui's GL objects go before the context if
+// the app is dropped without being run
 pub struct App {
-    event_loop: EventLoop<()>,
-    windowed_context: glutin::ContextWrapper<glutin::PossiblyCurrent, glutin::window::Window>,
+    layers: Vec<Box<dyn Layer>>,
+    imgui_renderer: imgui_opengl_renderer::Renderer,
     imgui: imgui::Context,
     imgui_platform: imgui_winit_support::WinitPlatform,
-    imgui_renderer: imgui_opengl_renderer::Renderer,
-    layers: Vec<Box<dyn Layer>>,
+    gl_context: GlContext,
+    event_loop: Option<EventLoop<()>>,
+    frame_limit: Option<u64>,
 }
 
 impl App {
@@ -67,6 +114,8 @@ impl App {
 
         let windowed_context = glutin::ContextBuilder::new()
             .with_vsync(false)
+            // the renderer multisamples offscreen instead, since the deferred path blits its
+            // single-sampled depth in here, see `Renderer::set_msaa_samples`
             .with_multisampling(0)
             .with_double_buffer(Some(true))
             .build_windowed(wb, &event_loop)
@@ -115,23 +164,62 @@ impl App {
         layers.push(Box::new(perf_metrics_layer));
 
         App {
-            event_loop,
-            windowed_context,
+            event_loop: Some(event_loop),
+            gl_context: GlContext::Windowed(windowed_context),
             imgui,
             imgui_platform,
             imgui_renderer,
             layers,
+            frame_limit: None,
         }
     }
 
+    /// Creates an app without a window, which renders into an offscreen framebuffer.
+    /// Layers receive no events and imgui is never drawn.
+    ///
+    /// Combine with `set_frame_limit` to drive the layers for a fixed number of frames.
+    pub fn new_headless(width: u32, height: u32) -> Self {
+        let headless_context = HeadlessContext::new(width, height);
+
+        let mut imgui = imgui::Context::create();
+        // never attached to a window, so the hidpi factor stays at 1.0
+        let imgui_platform = imgui_winit_support::WinitPlatform::init(&mut imgui);
+        imgui.fonts().build_rgba32_texture();
+        let imgui_renderer = imgui_opengl_renderer::Renderer::new(&mut imgui, |s| {
+            headless_context.get_proc_address(s) as _
+        });
+
+        gl_call!(gl::Viewport(0, 0, width as i32, height as i32));
+
+        let mut layers: Vec<Box<dyn Layer>> = Vec::new();
+        let perf_metrics_layer = PerfMetricsLayer::new();
+        layers.push(Box::new(perf_metrics_layer));
+
+        App {
+            event_loop: None,
+            gl_context: GlContext::Headless(Box::new(headless_context)),
+            imgui,
+            imgui_platform,
+            imgui_renderer,
+            layers,
+            frame_limit: None,
+        }
+    }
+
+    /// Exit after rendering `frames` frames, or never if `None`.
+    pub fn set_frame_limit(&mut self, frames: Option<u64>) {
+        self.frame_limit = frames;
+    }
+
     pub fn run(self) {
         let App {
-            windowed_context,
+            event_loop,
+            gl_context,
             mut imgui,
             imgui_platform,
             imgui_renderer,
             mut layers,
-            ..
+            frame_limit,
         } = self;
 
         let mut last_event_poll = Instant::now();
@@ -146,10 +234,11 @@ impl App {
             fixed_timestep: Duration::from_secs_f64(1.0 / fixed_update_rate),
             max_frame_rate,
             min_frame_timestep,
-            windowed_context,
+            gl_context,
             imgui_platform,
             event_poll_time: Duration::from_secs(0),
             delta_time: Duration::from_secs(0),
+            frame_count: 0,
             exit_requested: false,
         };
 
@@ -160,7 +249,46 @@ impl App {
             layer.init(&mut app_context);
         }
 
-        self.event_loop.run(
+        let event_loop = match event_loop {
+            Some(event_loop) => event_loop,
+            None => {
+                // headless, there are no events to poll, so just loop until asked to exit
+                while !app_context.exit_requested {
+                    let now = Instant::now();
+                    if now >= next_fixed_update {
+                        next_fixed_update += app_context.fixed_timestep;
+                        for layer in &mut layers {
+                            layer.on_fixed_update(&mut app_context);
+                        }
+                    }
+
+                    let now = Instant::now();
+                    if now < next_frame_update {
+                        std::thread::sleep(next_frame_update - now);
+                    }
+                    let now = Instant::now();
+                    next_frame_update = now + app_context.min_frame_timestep;
+                    app_context.delta_time = now - last_frame_update;
+                    last_frame_update = now;
+
+                    for layer in &mut layers {
+                        layer.on_frame_update(&mut app_context);
+                    }
+                    gl_call!(gl::Finish());
+
+                    app_context.frame_count += 1;
+                    if frame_limit.is_some_and(|limit| app_context.frame_count >= limit) {
+                        app_context.exit();
+                    }
+                }
+                // GL objects owned by layers must go before the context does
+                drop(layers);
+                drop(imgui_renderer);
+                return;
+            }
+        };
+
+        event_loop.run(
             move |event: Event<()>,
                   _: &EventLoopWindowTarget<()>,
                   control_flow: &mut ControlFlow| {
@@ -184,7 +312,7 @@ impl App {
                         let now = Instant::now();
                         if now >= next_fixed_update {
                             // fixed update
-                            next_fixed_update = next_fixed_update + app_context.fixed_timestep;
+                            next_fixed_update += app_context.fixed_timestep;
 
                             for layer in &mut layers {
                                 layer.on_fixed_update(&mut app_context);
@@ -195,7 +323,7 @@ impl App {
                         if now >= next_frame_update {
                             // frame update (with render)
                             next_frame_update = now + app_context.min_frame_timestep;
-                            app_context.windowed_context.window().request_redraw();
+                            app_context.gl_context.windowed().window().request_redraw();
                         }
                     }
                     Event::LoopDestroyed => (),
@@ -213,7 +341,10 @@ impl App {
                         // construct the UI
                         app_context
                             .imgui_platform
-                            .prepare_frame(imgui.io_mut(), app_context.windowed_context.window())
+                            .prepare_frame(
+                                imgui.io_mut(),
+                                app_context.gl_context.windowed().window(),
+                            )
                             .expect("Failed to prepare frame");
                         let ui = imgui.frame();
                         for layer in &mut layers {
@@ -223,17 +354,22 @@ impl App {
                         // ui.show_demo_window(&mut true);
                         app_context
                             .imgui_platform
-                            .prepare_render(&ui, app_context.windowed_context.window());
+                            .prepare_render(&ui, app_context.gl_context.windowed().window());
                         imgui_renderer.render(ui);
 
                         // application-specific rendering *over the UI*
 
-                        app_context.windowed_context.swap_buffers().unwrap();
+                        app_context.gl_context.windowed().swap_buffers().unwrap();
+
+                        app_context.frame_count += 1;
+                        if frame_limit.is_some_and(|limit| app_context.frame_count >= limit) {
+                            app_context.exit();
+                        }
                     }
                     event => {
                         app_context.imgui_platform.handle_event(
                             imgui.io_mut(),
-                            app_context.windowed_context.window(),
+                            app_context.gl_context.windowed().window(),
                             &event,
                         );
 
@@ -241,10 +377,10 @@ impl App {
                         use glutin::event::{
                             KeyboardInput, ModifiersState, VirtualKeyCode, WindowEvent,
                         };
-                        match &event {
-                            Event::WindowEvent { event, .. } => match event {
+                        if let Event::WindowEvent { event, .. } = &event {
+                            match event {
                                 WindowEvent::Resized(physical_size) => {
-                                    app_context.windowed_context.resize(*physical_size);
+                                    app_context.gl_context.windowed().resize(*physical_size);
                                     // FIXME: this probably is unsafe... maybe
                                     unsafe {
                                         gl::Viewport(
@@ -268,15 +404,15 @@ impl App {
                                             ..
                                         },
                                     ..
-                                } => match (virtual_code, state, *modifiers) {
-                                    (VirtualKeyCode::Q, _, ModifiersState::LOGO) => {
+                                } => {
+                                    if let (VirtualKeyCode::Q, _, ModifiersState::LOGO) =
+                                        (virtual_code, state, *modifiers)
+                                    {
                                         *control_flow = ControlFlow::Exit
                                     }
-                                    _ => (),
-                                },
+                                }
                                 _ => (),
-                            },
-                            _ => (),
+                            }
                         }
 
                         for layer in &mut layers {
@@ -5,3 +5,13 @@ pub fn assets_path() -> std::path::PathBuf {
         .unwrap()
         .join("assets")
 }
+
+/// Resolve a path relative to `assets_path()`, absolute paths are left as they are.
+pub fn resolve_asset_path<P: AsRef<std::path::Path>>(path: P) -> std::path::PathBuf {
+    let path = path.as_ref();
+    if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        assets_path().join(path)
+    }
+}
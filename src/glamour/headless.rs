@@ -0,0 +1,175 @@
+use glutin::dpi;
+
+/// An OpenGL context without a visible window, whose default framebuffer is an offscreen
+/// surface, so rendering and reading it back work the same as with a window.
+///
+/// On Linux this is a pbuffer on an EGL surfaceless display (e.g. Mesa llvmpipe), so no display
+/// server or GPU is required. Elsewhere it falls back to a glutin context on a hidden window.
+pub struct HeadlessContext {
+    #[cfg(target_os = "linux")]
+    egl: khronos_egl::DynamicInstance<khronos_egl::EGL1_5>,
+    #[cfg(target_os = "linux")]
+    display: khronos_egl::Display,
+    #[cfg(target_os = "linux")]
+    surface: khronos_egl::Surface,
+    #[cfg(target_os = "linux")]
+    context: khronos_egl::Context,
+    #[cfg(not(target_os = "linux"))]
+    context: glutin::ContextWrapper<glutin::PossiblyCurrent, glutin::window::Window>,
+    #[cfg(not(target_os = "linux"))]
+    _event_loop: glutin::event_loop::EventLoop<()>,
+    size: dpi::PhysicalSize<u32>,
+}
+
+#[cfg(target_os = "linux")]
+const EGL_PLATFORM_SURFACELESS_MESA: khronos_egl::Enum = 0x31DD;
+
+impl HeadlessContext {
+    /// Creates a context with a `width` by `height` surface, makes it current and loads OpenGL.
+    ///
+    /// # Panics
+    /// If no headless OpenGL context can be created, this function will `panic!`.
+    #[cfg(target_os = "linux")]
+    pub fn new(width: u32, height: u32) -> Self {
+        use khronos_egl as egl;
+        let egl = unsafe { egl::DynamicInstance::<egl::EGL1_5>::load_required() }
+            .expect("failed to load libEGL");
+        let display = unsafe {
+            egl.get_platform_display(
+                EGL_PLATFORM_SURFACELESS_MESA,
+                egl::DEFAULT_DISPLAY,
+                &[egl::ATTRIB_NONE],
+            )
+        }
+        .expect("failed to get surfaceless EGL display");
+        egl.initialize(display)
+            .expect("failed to initialise EGL display");
+        egl.bind_api(egl::OPENGL_API)
+            .expect("failed to bind OpenGL API");
+        // the same colour, depth and stencil formats as a window's default framebuffer
+        let config = egl
+            .choose_first_config(
+                display,
+                &[
+                    egl::SURFACE_TYPE,
+                    egl::PBUFFER_BIT,
+                    egl::RENDERABLE_TYPE,
+                    egl::OPENGL_BIT,
+                    egl::RED_SIZE,
+                    8,
+                    egl::GREEN_SIZE,
+                    8,
+                    egl::BLUE_SIZE,
+                    8,
+                    egl::ALPHA_SIZE,
+                    8,
+                    egl::DEPTH_SIZE,
+                    24,
+                    egl::STENCIL_SIZE,
+                    8,
+                    egl::NONE,
+                ],
+            )
+            .expect("failed to choose EGL config")
+            .expect("no EGL config with an RGBA8 pbuffer");
+        let surface = egl
+            .create_pbuffer_surface(
+                display,
+                config,
+                &[
+                    egl::WIDTH,
+                    width as i32,
+                    egl::HEIGHT,
+                    height as i32,
+                    egl::NONE,
+                ],
+            )
+            .expect("failed to create EGL pbuffer surface");
+        let context = egl
+            .create_context(
+                display,
+                config,
+                None,
+                &[
+                    egl::CONTEXT_MAJOR_VERSION,
+                    4,
+                    egl::CONTEXT_MINOR_VERSION,
+                    1,
+                    egl::CONTEXT_OPENGL_PROFILE_MASK,
+                    egl::CONTEXT_OPENGL_CORE_PROFILE_BIT,
+                    egl::NONE,
+                ],
+            )
+            .expect("failed to create EGL context");
+        egl.make_current(display, Some(surface), Some(surface), Some(context))
+            .expect("failed to make EGL context current");
+
+        gl::load_with(|s| {
+            egl.get_proc_address(s)
+                .map_or(std::ptr::null(), |f| f as *const _)
+        });
+
+        HeadlessContext {
+            egl,
+            display,
+            surface,
+            context,
+            size: dpi::PhysicalSize { width, height },
+        }
+    }
+
+    /// Creates a context with a `width` by `height` surface, makes it current and loads OpenGL.
+    ///
+    /// # Panics
+    /// If no headless OpenGL context can be created, this function will `panic!`.
+    #[cfg(not(target_os = "linux"))]
+    pub fn new(width: u32, height: u32) -> Self {
+        let event_loop = glutin::event_loop::EventLoop::new();
+        // glutin's own headless contexts don't always have a default framebuffer
+        let wb = glutin::window::WindowBuilder::new()
+            .with_visible(false)
+            .with_inner_size(dpi::PhysicalSize { width, height });
+        let context = glutin::ContextBuilder::new()
+            .with_gl(glutin::GlRequest::Specific(glutin::Api::OpenGl, (4, 1)))
+            .with_gl_profile(glutin::GlProfile::Core)
+            .build_windowed(wb, &event_loop)
+            .expect("failed to create headless context");
+        let context = unsafe { context.make_current().unwrap() };
+
+        gl::load_with(|s| context.get_proc_address(s) as *const _);
+
+        HeadlessContext {
+            context,
+            _event_loop: event_loop,
+            size: dpi::PhysicalSize { width, height },
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    pub fn get_proc_address(&self, addr: &str) -> *const std::ffi::c_void {
+        self.egl
+            .get_proc_address(addr)
+            .map_or(std::ptr::null(), |f| f as *const _)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn get_proc_address(&self, addr: &str) -> *const std::ffi::c_void {
+        self.context.get_proc_address(addr)
+    }
+
+    pub fn size(&self) -> dpi::PhysicalSize<u32> {
+        self.size
+    }
+}
+
+impl Drop for HeadlessContext {
+    fn drop(&mut self) {
+        #[cfg(target_os = "linux")]
+        {
+            self.egl.make_current(self.display, None, None, None).ok();
+            self.egl.destroy_surface(self.display, self.surface).ok();
+            self.egl.destroy_context(self.display, self.context).ok();
+            self.egl.terminate(self.display).ok();
+        }
+    }
+}
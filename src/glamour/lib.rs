@@ -6,17 +6,37 @@ pub mod gl_call;
 mod app;
 pub use app::*;
 
+mod headless;
+pub use headless::*;
+
+// this module shadows its own `renderer` submodule, whose contents are re-exported below
+#[allow(hidden_glob_reexports)]
 mod renderer;
 pub use renderer::*;
 
 pub use camera::*;
+pub use capture::*;
+pub use framebuffer::*;
+pub use frustum::*;
 pub use g_buf::*;
+pub use gpu_cull::*;
+pub use light::*;
+pub use material::*;
+pub use mesh::*;
+pub use model::*;
+pub use post_process::*;
+pub use renderer::meshes;
 pub use renderer::renderer::*;
 pub use shader::*;
+pub use shadow_map::*;
+pub use ssao::*;
+pub use tex_buf::*;
 pub use texture::*;
+pub use tone_map::*;
 pub use transform::*;
 pub use vert_array::*;
 pub use vert_basic::*;
+pub use vert_tangent::*;
 pub use vert_trans::*;
 
 mod layer;
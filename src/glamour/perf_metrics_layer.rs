@@ -59,7 +59,7 @@ impl Layer for PerfMetricsLayer {
             .position_pivot([1.0, 0.0])
             .position(
                 [
-                    app_context.windowed_context().window().inner_size().width as f32
+                    app_context.size().width as f32
                         / app_context.imgui_platform().hidpi_factor() as f32,
                     0.0,
                 ],
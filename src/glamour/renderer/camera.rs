@@ -1,4 +1,4 @@
-use crate::glm;
+use crate::{glm, Frustum};
 use glutin::event::{Event, WindowEvent};
 
 pub struct Camera {
@@ -31,6 +31,10 @@ impl Camera {
     pub fn view_projection_matrix(&self) -> glm::Mat4 {
         self.projection_matrix() * self.view_matrix()
     }
+    /// The camera's view frustum in world space, for culling what it can't see.
+    pub fn frustum(&self) -> Frustum {
+        Frustum::from_matrix(&self.view_projection_matrix())
+    }
     pub fn handle_event(&mut self, event: &Event<()>) {
         match event {
             Event::WindowEvent {
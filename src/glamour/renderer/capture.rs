@@ -0,0 +1,104 @@
+use std::path::{Path, PathBuf};
+
+/// Read a colour buffer of a framebuffer back into an image.
+///
+/// OpenGL's origin is the bottom left, so the rows are flipped to put the origin at the top left.
+/// Floating-point buffers are clamped to `[0, 1]` by OpenGL during the read.
+pub fn read_pixels(
+    framebuffer_id: u32,
+    read_buffer: gl::types::GLenum,
+    width: u32,
+    height: u32,
+) -> image::RgbaImage {
+    let mut pixels = vec![0u8; width as usize * height as usize * 4];
+    let mut pack_alignment = 0;
+    gl_call!(gl::GetIntegerv(gl::PACK_ALIGNMENT, &mut pack_alignment));
+    gl_call!(gl::BindFramebuffer(gl::READ_FRAMEBUFFER, framebuffer_id));
+    gl_call!(gl::ReadBuffer(read_buffer));
+    gl_call!(gl::PixelStorei(gl::PACK_ALIGNMENT, 1));
+    gl_call!(gl::ReadPixels(
+        0,
+        0,
+        width as i32,
+        height as i32,
+        gl::RGBA,
+        gl::UNSIGNED_BYTE,
+        pixels.as_mut_ptr() as *mut gl::types::GLvoid
+    ));
+    gl_call!(gl::PixelStorei(gl::PACK_ALIGNMENT, pack_alignment));
+    gl_call!(gl::BindFramebuffer(gl::READ_FRAMEBUFFER, 0));
+    let mut image = image::RgbaImage::from_raw(width, height, pixels).unwrap();
+    image::imageops::flip_vertical_in_place(&mut image);
+    image
+}
+
+/// Read the default framebuffer back into an image, from the buffer it's drawn into: the back
+/// buffer of a window, or the only buffer of a single-buffered headless surface.
+pub fn read_default_framebuffer(width: u32, height: u32) -> image::RgbaImage {
+    let mut read_buffer = 0;
+    gl_call!(gl::BindFramebuffer(gl::READ_FRAMEBUFFER, 0));
+    gl_call!(gl::GetIntegerv(gl::READ_BUFFER, &mut read_buffer));
+    read_pixels(0, read_buffer as gl::types::GLenum, width, height)
+}
+
+/// Saves images as a numbered sequence of PNG files, e.g. `frame_00000.png`, `frame_00001.png`.
+pub struct PngSequence {
+    dir: PathBuf,
+    prefix: String,
+    next_index: u32,
+}
+
+impl PngSequence {
+    /// Creates `dir` (and its parents) if it doesn't exist yet.
+    pub fn new(dir: &Path, prefix: &str) -> std::io::Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        Ok(PngSequence {
+            dir: dir.to_path_buf(),
+            prefix: prefix.to_string(),
+            next_index: 0,
+        })
+    }
+
+    /// The path the next image will be saved to.
+    pub fn next_path(&self) -> PathBuf {
+        self.dir
+            .join(format!("{}_{:05}.png", self.prefix, self.next_index))
+    }
+
+    /// Saves the image as the next PNG in the sequence and returns its path.
+    pub fn save(&mut self, image: &image::RgbaImage) -> image::ImageResult<PathBuf> {
+        let path = self.next_path();
+        image.save_with_format(&path, image::ImageFormat::Png)?;
+        self.next_index += 1;
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn png_sequence_numbers_frames_in_order() {
+        let dir = std::env::temp_dir().join(format!("glamour_png_sequence_{}", std::process::id()));
+        let mut sequence = PngSequence::new(&dir.join("frames"), "capture").unwrap();
+        assert_eq!(sequence.next_path(), dir.join("frames/capture_00000.png"));
+
+        let image = image::RgbaImage::from_pixel(2, 2, image::Rgba([255, 0, 0, 255]));
+        let paths: Vec<PathBuf> = (0..3).map(|_| sequence.save(&image).unwrap()).collect();
+        assert_eq!(
+            paths,
+            vec![
+                dir.join("frames/capture_00000.png"),
+                dir.join("frames/capture_00001.png"),
+                dir.join("frames/capture_00002.png"),
+            ]
+        );
+        assert_eq!(sequence.next_path(), dir.join("frames/capture_00003.png"));
+        for path in &paths {
+            assert_eq!(image::open(path).unwrap().to_rgba(), image);
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
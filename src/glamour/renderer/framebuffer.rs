@@ -0,0 +1,580 @@
+use crate::{Texture, TextureBuilder, TextureError, TextureFilter, TextureFormat, TextureWrap};
+
+/// The format of a framebuffer's depth attachment, optionally with a stencil buffer.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DepthFormat {
+    Depth24,
+    Depth32F,
+    /// The same format as the default framebuffer, so depth can be blitted between them.
+    Depth24Stencil8,
+    Depth32FStencil8,
+}
+
+impl DepthFormat {
+    fn gl_internal_format(&self) -> gl::types::GLenum {
+        match self {
+            DepthFormat::Depth24 => gl::DEPTH_COMPONENT24,
+            DepthFormat::Depth32F => gl::DEPTH_COMPONENT32F,
+            DepthFormat::Depth24Stencil8 => gl::DEPTH24_STENCIL8,
+            DepthFormat::Depth32FStencil8 => gl::DEPTH32F_STENCIL8,
+        }
+    }
+
+    fn gl_format(&self) -> gl::types::GLenum {
+        if self.has_stencil() {
+            gl::DEPTH_STENCIL
+        } else {
+            gl::DEPTH_COMPONENT
+        }
+    }
+
+    fn gl_type(&self) -> gl::types::GLenum {
+        match self {
+            DepthFormat::Depth24 => gl::UNSIGNED_INT,
+            DepthFormat::Depth32F => gl::FLOAT,
+            DepthFormat::Depth24Stencil8 => gl::UNSIGNED_INT_24_8,
+            DepthFormat::Depth32FStencil8 => gl::FLOAT_32_UNSIGNED_INT_24_8_REV,
+        }
+    }
+
+    fn gl_attachment(&self) -> gl::types::GLenum {
+        if self.has_stencil() {
+            gl::DEPTH_STENCIL_ATTACHMENT
+        } else {
+            gl::DEPTH_ATTACHMENT
+        }
+    }
+
+    pub fn has_stencil(&self) -> bool {
+        matches!(
+            self,
+            DepthFormat::Depth24Stencil8 | DepthFormat::Depth32FStencil8
+        )
+    }
+}
+
+#[derive(Debug)]
+pub enum FramebufferError {
+    /// A colour attachment couldn't be created, e.g. because it's too big.
+    Texture(TextureError),
+    /// More colour attachments than `GL_MAX_COLOR_ATTACHMENTS` or `GL_MAX_DRAW_BUFFERS`.
+    ColorAttachments { count: usize, max: usize },
+    /// More samples than `GL_MAX_SAMPLES`.
+    Samples { count: u32, max: u32 },
+    /// A depth texture was requested with multisampling, which only supports renderbuffers.
+    MultisampledDepthTexture,
+    /// The status from `glCheckFramebufferStatus`, e.g. for a format that can't be rendered to.
+    Incomplete { status: gl::types::GLenum },
+}
+
+impl std::fmt::Display for FramebufferError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FramebufferError::Texture(err) => {
+                write!(f, "failed to create colour attachment: {}", err)
+            }
+            FramebufferError::ColorAttachments { count, max } => write!(
+                f,
+                "{} colour attachments, only {} are supported",
+                count, max
+            ),
+            FramebufferError::Samples { count, max } => {
+                write!(f, "{} samples, only {} are supported", count, max)
+            }
+            FramebufferError::MultisampledDepthTexture => {
+                write!(f, "multisampled depth textures aren't supported")
+            }
+            FramebufferError::Incomplete { status } => {
+                let reason = match *status {
+                    gl::FRAMEBUFFER_UNDEFINED => "undefined",
+                    gl::FRAMEBUFFER_INCOMPLETE_ATTACHMENT => "incomplete attachment",
+                    gl::FRAMEBUFFER_INCOMPLETE_MISSING_ATTACHMENT => "missing attachment",
+                    gl::FRAMEBUFFER_INCOMPLETE_DRAW_BUFFER => "incomplete draw buffer",
+                    gl::FRAMEBUFFER_INCOMPLETE_READ_BUFFER => "incomplete read buffer",
+                    gl::FRAMEBUFFER_UNSUPPORTED => "unsupported combination of formats",
+                    gl::FRAMEBUFFER_INCOMPLETE_MULTISAMPLE => "mismatched sample counts",
+                    gl::FRAMEBUFFER_INCOMPLETE_LAYER_TARGETS => "mismatched layer targets",
+                    _ => "unknown status",
+                };
+                write!(f, "framebuffer not complete: {} (0x{:X})", reason, status)
+            }
+        }
+    }
+}
+
+impl std::error::Error for FramebufferError {}
+
+impl From<TextureError> for FramebufferError {
+    fn from(err: TextureError) -> Self {
+        FramebufferError::Texture(err)
+    }
+}
+
+/// Creates `Framebuffer`s with any number of colour textures and an optional depth (and
+/// stencil) attachment.
+///
+/// Colour attachments are clamped to the edge and use nearest filtering by default, since
+/// they're usually read back a texel per fragment.
+#[derive(Debug, Clone)]
+pub struct FramebufferBuilder {
+    color_formats: Vec<TextureFormat>,
+    filter: TextureFilter,
+    depth: Option<(DepthFormat, bool)>,
+    samples: u32,
+}
+
+impl FramebufferBuilder {
+    pub fn new() -> Self {
+        FramebufferBuilder {
+            color_formats: Vec::new(),
+            filter: TextureFilter::Nearest,
+            depth: None,
+            samples: 0,
+        }
+    }
+
+    /// Add a colour attachment, they're attached (and written to by fragment shader outputs)
+    /// in the order they're added.
+    pub fn with_color(mut self, format: TextureFormat) -> Self {
+        self.color_formats.push(format);
+        self
+    }
+
+    /// The filter every colour attachment is sampled with.
+    pub fn with_filter(mut self, filter: TextureFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Add a depth renderbuffer, which can be depth tested and blitted but not sampled.
+    pub fn with_depth(mut self, format: DepthFormat) -> Self {
+        self.depth = Some((format, false));
+        self
+    }
+
+    /// Add a depth texture, which can also be sampled in shaders with `bind_depth`.
+    pub fn with_depth_texture(mut self, format: DepthFormat) -> Self {
+        self.depth = Some((format, true));
+        self
+    }
+
+    /// Multisample every attachment with `samples` samples per pixel, or `0` (the default) for
+    /// none. Multisampled colour attachments are renderbuffers, so they can't be bound or
+    /// captured, only resolved into another framebuffer with `Framebuffer::resolve_to`.
+    pub fn with_samples(mut self, samples: u32) -> Self {
+        self.samples = samples;
+        self
+    }
+
+    pub fn build(&self, width: u32, height: u32) -> Result<Framebuffer, FramebufferError> {
+        let max = max_color_attachments();
+        if self.color_formats.len() > max {
+            return Err(FramebufferError::ColorAttachments {
+                count: self.color_formats.len(),
+                max,
+            });
+        }
+        if self.samples > 0 {
+            let max = max_samples();
+            if self.samples > max {
+                return Err(FramebufferError::Samples {
+                    count: self.samples,
+                    max,
+                });
+            }
+            if let Some((_, true)) = self.depth {
+                return Err(FramebufferError::MultisampledDepthTexture);
+            }
+        }
+        let colors = if self.samples > 0 {
+            Vec::new()
+        } else {
+            self.color_formats
+                .iter()
+                .map(|&format| {
+                    TextureBuilder::new()
+                        .with_format(format)
+                        .with_wrap(TextureWrap::ClampToEdge)
+                        .with_filter(self.filter, self.filter)
+                        .build_empty(width, height)
+                })
+                .collect::<Result<Vec<Texture>, TextureError>>()?
+        };
+
+        let mut id: u32 = 0;
+        gl_call!(gl::GenFramebuffers(1, &mut id));
+        let mut framebuffer = Framebuffer {
+            id,
+            colors,
+            multisampled_colors: Vec::new(),
+            depth: None,
+            width,
+            height,
+            samples: self.samples,
+        };
+        gl_call!(gl::BindFramebuffer(gl::FRAMEBUFFER, id));
+        for (i, color) in framebuffer.colors.iter().enumerate() {
+            gl_call!(gl::FramebufferTexture2D(
+                gl::FRAMEBUFFER,
+                gl::COLOR_ATTACHMENT0 + i as u32,
+                gl::TEXTURE_2D,
+                color.id(),
+                0
+            ));
+        }
+        if self.samples > 0 {
+            for (i, &format) in self.color_formats.iter().enumerate() {
+                let mut color_id: u32 = 0;
+                gl_call!(gl::GenRenderbuffers(1, &mut color_id));
+                let color = MultisampledColor {
+                    id: color_id,
+                    format,
+                };
+                color.specify(width, height, self.samples);
+                gl_call!(gl::FramebufferRenderbuffer(
+                    gl::FRAMEBUFFER,
+                    gl::COLOR_ATTACHMENT0 + i as u32,
+                    gl::RENDERBUFFER,
+                    color_id
+                ));
+                framebuffer.multisampled_colors.push(color);
+            }
+        }
+        if self.color_formats.is_empty() {
+            gl_call!(gl::DrawBuffer(gl::NONE));
+            gl_call!(gl::ReadBuffer(gl::NONE));
+        } else {
+            let attachments: Vec<u32> = (0..self.color_formats.len() as u32)
+                .map(|i| gl::COLOR_ATTACHMENT0 + i)
+                .collect();
+            gl_call!(gl::DrawBuffers(
+                attachments.len() as i32,
+                attachments.as_ptr()
+            ));
+        }
+
+        if let Some((format, is_texture)) = self.depth {
+            let mut depth_id: u32 = 0;
+            if is_texture {
+                gl_call!(gl::GenTextures(1, &mut depth_id));
+            } else {
+                gl_call!(gl::GenRenderbuffers(1, &mut depth_id));
+            }
+            let depth = DepthAttachment {
+                id: depth_id,
+                format,
+                is_texture,
+                samples: self.samples,
+            };
+            depth.specify(width, height);
+            if is_texture {
+                gl_call!(gl::FramebufferTexture2D(
+                    gl::FRAMEBUFFER,
+                    format.gl_attachment(),
+                    gl::TEXTURE_2D,
+                    depth_id,
+                    0
+                ));
+            } else {
+                gl_call!(gl::FramebufferRenderbuffer(
+                    gl::FRAMEBUFFER,
+                    format.gl_attachment(),
+                    gl::RENDERBUFFER,
+                    depth_id
+                ));
+            }
+            framebuffer.depth = Some(depth);
+        }
+
+        let status = gl_call!(gl::CheckFramebufferStatus(gl::FRAMEBUFFER));
+        framebuffer.unbind();
+        // dropping the framebuffer cleans up everything created so far
+        if status != gl::FRAMEBUFFER_COMPLETE {
+            return Err(FramebufferError::Incomplete { status });
+        }
+        Ok(framebuffer)
+    }
+}
+
+impl Default for FramebufferBuilder {
+    fn default() -> Self {
+        FramebufferBuilder::new()
+    }
+}
+
+fn max_color_attachments() -> usize {
+    let mut max_attachments = 0;
+    let mut max_draw_buffers = 0;
+    gl_call!(gl::GetIntegerv(
+        gl::MAX_COLOR_ATTACHMENTS,
+        &mut max_attachments
+    ));
+    gl_call!(gl::GetIntegerv(gl::MAX_DRAW_BUFFERS, &mut max_draw_buffers));
+    max_attachments.min(max_draw_buffers) as usize
+}
+
+/// The most samples a multisampled framebuffer can have, `GL_MAX_SAMPLES`.
+pub fn max_samples() -> u32 {
+    let mut max_samples = 0;
+    gl_call!(gl::GetIntegerv(gl::MAX_SAMPLES, &mut max_samples));
+    max_samples as u32
+}
+
+#[derive(Debug)]
+struct MultisampledColor {
+    id: u32,
+    format: TextureFormat,
+}
+
+impl MultisampledColor {
+    fn specify(&self, width: u32, height: u32, samples: u32) {
+        gl_call!(gl::BindRenderbuffer(gl::RENDERBUFFER, self.id));
+        gl_call!(gl::RenderbufferStorageMultisample(
+            gl::RENDERBUFFER,
+            samples as i32,
+            self.format.gl_internal_format(),
+            width as i32,
+            height as i32
+        ));
+        gl_call!(gl::BindRenderbuffer(gl::RENDERBUFFER, 0));
+    }
+}
+
+#[derive(Debug)]
+struct DepthAttachment {
+    id: u32,
+    format: DepthFormat,
+    is_texture: bool,
+    samples: u32,
+}
+
+impl DepthAttachment {
+    /// (Re)allocate the storage of the texture or renderbuffer.
+    fn specify(&self, width: u32, height: u32) {
+        if self.is_texture {
+            gl_call!(gl::BindTexture(gl::TEXTURE_2D, self.id));
+            gl_call!(gl::TexImage2D(
+                gl::TEXTURE_2D,
+                0,
+                self.format.gl_internal_format() as i32,
+                width as i32,
+                height as i32,
+                0,
+                self.format.gl_format(),
+                self.format.gl_type(),
+                std::ptr::null()
+            ));
+            crate::set_filter(
+                gl::TEXTURE_2D,
+                TextureFilter::Nearest,
+                TextureFilter::Nearest,
+                None,
+            );
+            crate::set_wrap(
+                gl::TEXTURE_2D,
+                TextureWrap::ClampToEdge,
+                TextureWrap::ClampToEdge,
+            );
+            gl_call!(gl::BindTexture(gl::TEXTURE_2D, 0));
+        } else {
+            gl_call!(gl::BindRenderbuffer(gl::RENDERBUFFER, self.id));
+            gl_call!(gl::RenderbufferStorageMultisample(
+                gl::RENDERBUFFER,
+                self.samples as i32,
+                self.format.gl_internal_format(),
+                width as i32,
+                height as i32
+            ));
+            gl_call!(gl::BindRenderbuffer(gl::RENDERBUFFER, 0));
+        }
+    }
+}
+
+/// An offscreen render target, created with a `FramebufferBuilder`. Every attachment is the
+/// same size, and is reallocated by `resize`.
+#[derive(Debug)]
+pub struct Framebuffer {
+    id: u32,
+    colors: Vec<Texture>,
+    multisampled_colors: Vec<MultisampledColor>,
+    depth: Option<DepthAttachment>,
+    width: u32,
+    height: u32,
+    samples: u32,
+}
+
+impl Framebuffer {
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Samples per pixel, `0` if the framebuffer isn't multisampled.
+    pub fn samples(&self) -> u32 {
+        self.samples
+    }
+
+    /// The colour attachments, in the order they were added to the builder. Empty if the
+    /// framebuffer is multisampled.
+    pub fn colors(&self) -> &[Texture] {
+        &self.colors
+    }
+
+    pub fn color(&self, index: usize) -> &Texture {
+        &self.colors[index]
+    }
+
+    pub fn depth_format(&self) -> Option<DepthFormat> {
+        self.depth.as_ref().map(|depth| depth.format)
+    }
+
+    /// Bind the framebuffer to draw into, the viewport is left for the caller to set.
+    pub fn bind(&self) {
+        gl_call!(gl::BindFramebuffer(gl::FRAMEBUFFER, self.id));
+    }
+
+    /// Bind the default framebuffer.
+    pub fn unbind(&self) {
+        gl_call!(gl::BindFramebuffer(gl::FRAMEBUFFER, 0));
+    }
+
+    /// Reallocate every attachment at a new size, discarding their contents. Does nothing if
+    /// the size hasn't changed.
+    pub fn resize(&mut self, width: u32, height: u32) {
+        if (width, height) == (self.width, self.height) {
+            return;
+        }
+        self.width = width;
+        self.height = height;
+        for color in &mut self.colors {
+            color.resize(width, height);
+        }
+        for color in &self.multisampled_colors {
+            color.specify(width, height, self.samples);
+        }
+        if let Some(depth) = &self.depth {
+            depth.specify(width, height);
+        }
+    }
+
+    /// Bind the colour attachments to consecutive texture units, starting at `first_unit`.
+    pub fn bind_colors(&self, first_unit: u32) {
+        for (i, color) in self.colors.iter().enumerate() {
+            color.bind(first_unit + i as u32);
+        }
+    }
+
+    pub fn unbind_colors(&self, first_unit: u32) {
+        for (i, color) in self.colors.iter().enumerate() {
+            color.unbind(first_unit + i as u32);
+        }
+    }
+
+    /// Bind the depth texture to texture unit `unit`, e.g. `1` for `GL_TEXTURE1`.
+    ///
+    /// # Panics
+    /// If the framebuffer was built without `with_depth_texture`.
+    pub fn bind_depth(&self, unit: u32) {
+        let depth = self
+            .depth
+            .as_ref()
+            .filter(|depth| depth.is_texture)
+            .expect("framebuffer has no depth texture");
+        gl_call!(gl::ActiveTexture(gl::TEXTURE0 + unit));
+        gl_call!(gl::BindTexture(gl::TEXTURE_2D, depth.id));
+        gl_call!(gl::ActiveTexture(gl::TEXTURE0));
+    }
+
+    pub fn unbind_depth(&self, unit: u32) {
+        gl_call!(gl::ActiveTexture(gl::TEXTURE0 + unit));
+        gl_call!(gl::BindTexture(gl::TEXTURE_2D, 0));
+        gl_call!(gl::ActiveTexture(gl::TEXTURE0));
+    }
+
+    /// Copy the depth buffer into the default framebuffer, which must be the same size and
+    /// format, e.g. to draw forward-rendered geometry over a deferred pass.
+    pub fn blit_depth(&self) {
+        self.blit_depth_to(None);
+    }
+
+    /// Copy the depth buffer into another framebuffer, or the default one if `None`, and leave
+    /// it bound. They must be the same size and depth format.
+    pub fn blit_depth_to(&self, target: Option<&Framebuffer>) {
+        let target_id = target.map_or(0, |target| target.id);
+        gl_call!(gl::BindFramebuffer(gl::READ_FRAMEBUFFER, self.id));
+        gl_call!(gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, target_id));
+        gl_call!(gl::BlitFramebuffer(
+            0,
+            0,
+            self.width as i32,
+            self.height as i32,
+            0,
+            0,
+            self.width as i32,
+            self.height as i32,
+            gl::DEPTH_BUFFER_BIT,
+            gl::NEAREST
+        ));
+        gl_call!(gl::BindFramebuffer(gl::FRAMEBUFFER, target_id));
+    }
+
+    /// Average a multisampled framebuffer's samples into a single-sampled framebuffer, and
+    /// leave it bound. The first colour attachment and the depth buffer are resolved, so both
+    /// must be the same size and formats.
+    pub fn resolve_to(&self, target: &Framebuffer) {
+        let target_id = target.id;
+        let mut mask = gl::COLOR_BUFFER_BIT;
+        if self.depth.is_some() {
+            mask |= gl::DEPTH_BUFFER_BIT;
+        }
+        gl_call!(gl::BindFramebuffer(gl::READ_FRAMEBUFFER, self.id));
+        gl_call!(gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, target_id));
+        gl_call!(gl::BlitFramebuffer(
+            0,
+            0,
+            self.width as i32,
+            self.height as i32,
+            0,
+            0,
+            self.width as i32,
+            self.height as i32,
+            mask,
+            gl::NEAREST
+        ));
+        gl_call!(gl::BindFramebuffer(gl::FRAMEBUFFER, target_id));
+    }
+
+    /// Read a colour attachment back into an image, float attachments are clamped to `[0, 1]`.
+    pub fn capture(&self, index: usize) -> image::RgbaImage {
+        assert!(index < self.colors.len(), "no colour attachment {}", index);
+        crate::read_pixels(
+            self.id,
+            gl::COLOR_ATTACHMENT0 + index as u32,
+            self.width,
+            self.height,
+        )
+    }
+}
+
+impl Drop for Framebuffer {
+    fn drop(&mut self) {
+        for color in &self.multisampled_colors {
+            gl_call!(gl::DeleteRenderbuffers(1, &color.id));
+        }
+        if let Some(depth) = &self.depth {
+            if depth.is_texture {
+                gl_call!(gl::DeleteTextures(1, &depth.id));
+            } else {
+                gl_call!(gl::DeleteRenderbuffers(1, &depth.id));
+            }
+        }
+        gl_call!(gl::DeleteFramebuffers(1, &self.id));
+    }
+}
@@ -0,0 +1,134 @@
+use crate::{glm, Transform};
+use rayon::prelude::*;
+
+/// The six planes bounding what a camera can see, facing inwards, as `(normal, distance)` with
+/// a normalized normal. Extracted from a view-projection matrix, see `Camera::frustum`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Frustum {
+    planes: [glm::Vec4; 6],
+}
+
+impl Frustum {
+    /// The left, right, bottom, top, near and far planes of a view-projection matrix's clip
+    /// space, in world space.
+    pub fn from_matrix(view_projection: &glm::Mat4) -> Self {
+        let row = |i: usize| -> glm::Vec4 { view_projection.row(i).transpose() };
+        let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+        let mut planes = [w + x, w - x, w + y, w - y, w + z, w - z];
+        for plane in &mut planes {
+            *plane /= glm::length(&plane.xyz());
+        }
+        Frustum { planes }
+    }
+
+    pub fn planes(&self) -> &[glm::Vec4; 6] {
+        &self.planes
+    }
+
+    /// Whether any part of a sphere could be inside the frustum. Spheres near its corners can
+    /// pass without actually touching it, which only costs drawing something off-screen.
+    ///
+    /// ```
+    /// # use glamour::{glm, Camera};
+    /// let mut camera = Camera::new();
+    /// camera.position = glm::vec3(0.0, 0.0, 5.0);
+    /// let frustum = camera.frustum();
+    /// assert!(frustum.contains_sphere(&glm::vec3(0.0, 0.0, 0.0), 1.0));
+    /// assert!(!frustum.contains_sphere(&glm::vec3(0.0, 0.0, 10.0), 1.0));
+    /// // partly inside the left plane
+    /// assert!(frustum.contains_sphere(&glm::vec3(-5.5, 0.0, 0.0), 1.0));
+    /// ```
+    pub fn contains_sphere(&self, center: &glm::Vec3, radius: f32) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| glm::dot(&plane.xyz(), center) + plane.w >= -radius)
+    }
+
+    /// Indices of the instances whose bounding spheres are in the frustum, in order. Each
+    /// sphere is `radius` around the instance's position, scaled by its largest scale.
+    pub fn cull(&self, transforms: &[Transform], radius: f32) -> Vec<usize> {
+        transforms
+            .par_iter()
+            .enumerate()
+            .filter(|(_, t)| {
+                let scale = t.scale.abs().max();
+                self.contains_sphere(&t.position, radius * scale)
+            })
+            .map(|(i, _)| i)
+            .collect()
+    }
+}
+
+/// How many instances were queued up for drawing this frame, and how many of them were
+/// frustum culled instead.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct CullStats {
+    pub drawn: usize,
+    pub culled: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Camera;
+
+    // a 90 degree frustum looking down -z from z = 5, so it's 5 units either side of the
+    // origin, with its near plane at z = 4.9 and far plane at z = -395
+    fn frustum() -> Frustum {
+        let mut camera = Camera::new();
+        camera.position = glm::vec3(0.0, 0.0, 5.0);
+        camera.frustum()
+    }
+
+    #[test]
+    fn sphere_inside() {
+        let frustum = frustum();
+        assert!(frustum.contains_sphere(&glm::vec3(0.0, 0.0, 0.0), 1.0));
+        assert!(frustum.contains_sphere(&glm::vec3(2.0, -2.0, -50.0), 0.1));
+        // a point at the centre still counts
+        assert!(frustum.contains_sphere(&glm::vec3(0.0, 0.0, 0.0), 0.0));
+    }
+
+    #[test]
+    fn sphere_intersecting() {
+        let frustum = frustum();
+        // centre 0.35 outside the left plane
+        assert!(frustum.contains_sphere(&glm::vec3(-5.5, 0.0, 0.0), 1.0));
+        assert!(frustum.contains_sphere(&glm::vec3(0.0, 5.5, 0.0), 1.0));
+        // centre just past the far plane
+        assert!(frustum.contains_sphere(&glm::vec3(0.0, 0.0, -395.5), 1.0));
+    }
+
+    #[test]
+    fn sphere_outside() {
+        let frustum = frustum();
+        assert!(!frustum.contains_sphere(&glm::vec3(-5.5, 0.0, 0.0), 0.3));
+        assert!(!frustum.contains_sphere(&glm::vec3(20.0, 0.0, 0.0), 1.0));
+        assert!(!frustum.contains_sphere(&glm::vec3(0.0, -20.0, 0.0), 1.0));
+        assert!(!frustum.contains_sphere(&glm::vec3(0.0, 0.0, -400.0), 1.0));
+    }
+
+    #[test]
+    fn sphere_behind_camera() {
+        let frustum = frustum();
+        assert!(!frustum.contains_sphere(&glm::vec3(0.0, 0.0, 10.0), 1.0));
+        // 0.6 behind the near plane
+        assert!(!frustum.contains_sphere(&glm::vec3(0.0, 0.0, 5.5), 0.3));
+        // big enough to reach back through the near plane
+        assert!(frustum.contains_sphere(&glm::vec3(0.0, 0.0, 5.5), 1.0));
+    }
+
+    #[test]
+    fn cull_scales_radius_by_largest_scale() {
+        let frustum = frustum();
+        let mut scaled = Transform::from_pos(glm::vec3(-5.5, 0.0, 0.0));
+        scaled.scale = glm::vec3(0.1, -4.0, 0.1);
+        let transforms = [
+            Transform::from_pos(glm::vec3(0.0, 0.0, 0.0)),
+            Transform::from_pos(glm::vec3(-5.5, 0.0, 0.0)),
+            scaled,
+            Transform::from_pos(glm::vec3(0.0, 0.0, 10.0)),
+        ];
+        assert_eq!(frustum.cull(&transforms, 0.3), vec![0, 2]);
+    }
+}
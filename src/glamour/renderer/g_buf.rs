@@ -1,226 +1,172 @@
+use crate::{
+    DepthFormat, Framebuffer, FramebufferBuilder, FramebufferError, ShaderProgram, TextureFormat,
+};
+
+// texture unit of the depth texture in the compact layout, after the colour attachments
+const DEPTH_UNIT: u32 = 3;
+
+/// How the G-buffer stores its surfaces, which trades memory bandwidth for precision.
+/// Both have an RGBA8 albedo + specular strength attachment and a 24 bit depth + 8 bit stencil
+/// buffer, which are counted in the bytes per pixel.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum GBufLayout {
+    /// World position + reflectivity and normal + shininess in two RGBA32F attachments, 40
+    /// bytes per pixel.
+    #[default]
+    Full,
+    /// An octahedral normal, shininess and reflectivity packed into one RGBA16F attachment,
+    /// 16 bytes per pixel. Position is reconstructed from the depth texture.
+    ///
+    /// The normal is half float rather than RG16 unorm so shininess, an unbounded exponent,
+    /// fits in the same attachment unscaled. Over the octahedron's `[-1, 1]` range, half floats
+    /// are off by at most 2^-11, a fraction of a degree.
+    Compact,
+}
+
+impl GBufLayout {
+    pub const ALL: [GBufLayout; 2] = [GBufLayout::Full, GBufLayout::Compact];
+}
+
+impl std::fmt::Display for GBufLayout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            GBufLayout::Full => "full",
+            GBufLayout::Compact => "compact",
+        };
+        f.write_str(name)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum GBufAttachment {
+    Position,
+    Normal,
+    AlbedoSpecular,
+}
+
+impl GBufAttachment {
+    /// The attachment's colour index in `layout`, if it's stored at all.
+    fn index(&self, layout: GBufLayout) -> Option<usize> {
+        match (layout, self) {
+            (GBufLayout::Full, GBufAttachment::Position) => Some(0),
+            (GBufLayout::Full, GBufAttachment::Normal) => Some(1),
+            (GBufLayout::Full, GBufAttachment::AlbedoSpecular) => Some(2),
+            (GBufLayout::Compact, GBufAttachment::Position) => None,
+            (GBufLayout::Compact, GBufAttachment::Normal) => Some(0),
+            (GBufLayout::Compact, GBufAttachment::AlbedoSpecular) => Some(1),
+        }
+    }
+}
+
+/// The deferred renderer's geometry buffer, laid out as described by `GBufLayout`. Its colour
+/// attachments are bound to texture units from 0 for the lighting pass, and the compact layout
+/// also binds its depth texture to unit 3. Shaders read it through `g_buf.glsl`.
 pub struct GBuf {
-    id: u32,
-    pos_id: u32,
-    norm_id: u32,
-    alb_spec_id: u32,
-    depth_id: u32,
-    width: u32,
-    height: u32,
+    framebuffer: Framebuffer,
+    layout: GBufLayout,
 }
 
 impl GBuf {
-    pub fn new(width: u32, height: u32) -> Self {
-        let mut id: u32 = 0;
-        gl_call!(gl::GenFramebuffers(1, &mut id));
-        gl_call!(gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, id));
-
-        // position color buffer
-        let mut pos_id: u32 = 0;
-        gl_call!(gl::GenTextures(1, &mut pos_id));
-        gl_call!(gl::BindTexture(gl::TEXTURE_2D, pos_id));
-        GBuf::specify_texture(gl::RGBA32F, gl::FLOAT, width, height);
-        gl_call!(gl::TexParameteri(
-            gl::TEXTURE_2D,
-            gl::TEXTURE_MIN_FILTER,
-            gl::NEAREST as i32
-        ));
-        gl_call!(gl::TexParameteri(
-            gl::TEXTURE_2D,
-            gl::TEXTURE_MAG_FILTER,
-            gl::NEAREST as i32
-        ));
-        gl_call!(gl::FramebufferTexture2D(
-            gl::FRAMEBUFFER,
-            gl::COLOR_ATTACHMENT0,
-            gl::TEXTURE_2D,
-            pos_id,
-            0
-        ));
-
-        // normal color buffer
-        let mut norm_id: u32 = 0;
-        gl_call!(gl::GenTextures(1, &mut norm_id));
-        gl_call!(gl::BindTexture(gl::TEXTURE_2D, norm_id));
-        GBuf::specify_texture(gl::RGBA32F, gl::FLOAT, width, height);
-        gl_call!(gl::TexParameteri(
-            gl::TEXTURE_2D,
-            gl::TEXTURE_MIN_FILTER,
-            gl::NEAREST as i32
-        ));
-        gl_call!(gl::TexParameteri(
-            gl::TEXTURE_2D,
-            gl::TEXTURE_MAG_FILTER,
-            gl::NEAREST as i32
-        ));
-        gl_call!(gl::FramebufferTexture2D(
-            gl::FRAMEBUFFER,
-            gl::COLOR_ATTACHMENT1,
-            gl::TEXTURE_2D,
-            norm_id,
-            0
-        ));
-
-        // albedo + specular color buffer
-        let mut alb_spec_id: u32 = 0;
-        gl_call!(gl::GenTextures(1, &mut alb_spec_id));
-        gl_call!(gl::BindTexture(gl::TEXTURE_2D, alb_spec_id));
-        GBuf::specify_texture(gl::RGBA, gl::UNSIGNED_BYTE, width, height);
-        gl_call!(gl::TexParameteri(
-            gl::TEXTURE_2D,
-            gl::TEXTURE_MIN_FILTER,
-            gl::NEAREST as i32
-        ));
-        gl_call!(gl::TexParameteri(
-            gl::TEXTURE_2D,
-            gl::TEXTURE_MAG_FILTER,
-            gl::NEAREST as i32
-        ));
-        gl_call!(gl::FramebufferTexture2D(
-            gl::FRAMEBUFFER,
-            gl::COLOR_ATTACHMENT2,
-            gl::TEXTURE_2D,
-            alb_spec_id,
-            0
-        ));
-
-        // tell OpenGL which color attachments we'll use (of this framebuffer) for rendering
-        let attachments: [u32; 3] = [
-            gl::COLOR_ATTACHMENT0,
-            gl::COLOR_ATTACHMENT1,
-            gl::COLOR_ATTACHMENT2,
-        ];
-        gl_call!(gl::DrawBuffers(3, attachments.as_ptr()));
-
-        // create and attach depth buffer (renderbuffer)
-        let mut depth_id: u32 = 0;
-        gl_call!(gl::GenRenderbuffers(1, &mut depth_id));
-        gl_call!(gl::BindRenderbuffer(gl::RENDERBUFFER, depth_id));
-        gl_call!(gl::RenderbufferStorage(
-            gl::RENDERBUFFER,
-            gl::DEPTH_COMPONENT,
-            width as i32,
-            height as i32
-        ));
-        gl_call!(gl::FramebufferRenderbuffer(
-            gl::FRAMEBUFFER,
-            gl::DEPTH_ATTACHMENT,
-            gl::RENDERBUFFER,
-            depth_id
-        ));
-        gl_call!(gl::BindRenderbuffer(gl::RENDERBUFFER, 0));
-
-        // finally check if framebuffer is complete
-        if gl_call!(gl::CheckFramebufferStatus(gl::FRAMEBUFFER) != gl::FRAMEBUFFER_COMPLETE) {
-            println!("Framebuffer not complete!");
-        }
-        gl_call!(gl::BindFramebuffer(gl::FRAMEBUFFER, 0));
-
-        GBuf {
-            id,
-            pos_id,
-            norm_id,
-            alb_spec_id,
-            depth_id,
-            width,
-            height,
-        }
+    pub fn new(width: u32, height: u32, layout: GBufLayout) -> Result<Self, FramebufferError> {
+        let builder = match layout {
+            GBufLayout::Full => FramebufferBuilder::new()
+                .with_color(TextureFormat::Rgba32F)
+                .with_color(TextureFormat::Rgba32F)
+                .with_color(TextureFormat::Rgba8)
+                // same format as the default framebuffer, so depth can be blitted across
+                .with_depth(DepthFormat::Depth24Stencil8),
+            GBufLayout::Compact => FramebufferBuilder::new()
+                .with_color(TextureFormat::Rgba16F)
+                .with_color(TextureFormat::Rgba8)
+                .with_depth_texture(DepthFormat::Depth24Stencil8),
+        };
+        Ok(GBuf {
+            framebuffer: builder.build(width, height)?,
+            layout,
+        })
     }
 
-    pub fn bind(&self) {
-        gl_call!(gl::BindFramebuffer(gl::FRAMEBUFFER, self.id));
+    pub fn layout(&self) -> GBufLayout {
+        self.layout
     }
 
-    pub fn unbind(&self) {
-        gl_call!(gl::BindFramebuffer(gl::FRAMEBUFFER, 0));
+    /// Point a shader that reads the G-buffer through `g_buf.glsl` at the texture units
+    /// `bind_bufs` uses, and tell it which layout to read.
+    pub fn set_shader_uniforms(&self, shader: &ShaderProgram) {
+        let (pos, norm, alb_spec) = match self.layout {
+            GBufLayout::Full => (0, 1, 2),
+            // position isn't stored, so it's pointed at a unit nothing else uses
+            GBufLayout::Compact => (2, 0, 1),
+        };
+        shader.set_int("u_tex_pos", pos);
+        shader.set_int("u_tex_norm", norm);
+        shader.set_int("u_tex_alb_spec", alb_spec);
+        shader.set_int("u_tex_depth", DEPTH_UNIT as i32);
+        self.set_layout_uniform(shader);
     }
 
-    pub fn blit_depth(&self) {
-        gl_call!(gl::BindFramebuffer(gl::READ_FRAMEBUFFER, self.id));
-        gl_call!(gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, 0));
-        gl_call!(gl::BlitFramebuffer(
-            0,
-            0,
-            self.width as i32,
-            self.height as i32,
-            0,
-            0,
-            self.width as i32,
-            self.height as i32,
-            gl::DEPTH_BUFFER_BIT,
-            gl::NEAREST
-        ));
-        gl_call!(gl::BindFramebuffer(gl::FRAMEBUFFER, 0));
-    }
-
-    fn specify_texture(
-        internal_format: gl::types::GLenum,
-        data_type: gl::types::GLenum,
-        width: u32,
-        height: u32,
-    ) {
-        gl_call!(gl::TexImage2D(
-            gl::TEXTURE_2D,
-            0,
-            internal_format as i32,
-            width as i32,
-            height as i32,
-            0,
-            gl::RGBA,
-            data_type,
-            std::ptr::null()
-        ));
+    /// Tell a shader that writes the G-buffer which layout to write, leaving its samplers alone.
+    pub fn set_layout_uniform(&self, shader: &ShaderProgram) {
+        shader.set_int(
+            "u_compact_g_buf",
+            (self.layout == GBufLayout::Compact) as i32,
+        );
     }
 
-    pub fn resize(&mut self, width: u32, height: u32) {
-        self.width = width;
-        self.height = height;
+    pub fn framebuffer(&self) -> &Framebuffer {
+        &self.framebuffer
+    }
 
-        gl_call!(gl::ActiveTexture(gl::TEXTURE0));
-        gl_call!(gl::BindTexture(gl::TEXTURE_2D, self.pos_id));
-        GBuf::specify_texture(gl::RGBA32F, gl::FLOAT, width, height);
-        gl_call!(gl::BindTexture(gl::TEXTURE_2D, 0));
+    pub fn width(&self) -> u32 {
+        self.framebuffer.width()
+    }
 
-        gl_call!(gl::ActiveTexture(gl::TEXTURE1));
-        gl_call!(gl::BindTexture(gl::TEXTURE_2D, self.norm_id));
-        GBuf::specify_texture(gl::RGBA32F, gl::FLOAT, width, height);
-        gl_call!(gl::BindTexture(gl::TEXTURE_2D, 0));
+    pub fn height(&self) -> u32 {
+        self.framebuffer.height()
+    }
 
-        gl_call!(gl::ActiveTexture(gl::TEXTURE2));
-        gl_call!(gl::BindTexture(gl::TEXTURE_2D, self.alb_spec_id));
-        GBuf::specify_texture(gl::RGBA, gl::UNSIGNED_BYTE, width, height);
-        gl_call!(gl::BindTexture(gl::TEXTURE_2D, 0));
+    pub fn bind(&self) {
+        self.framebuffer.bind();
+    }
 
-        gl_call!(gl::ActiveTexture(gl::TEXTURE0));
+    pub fn unbind(&self) {
+        self.framebuffer.unbind();
+    }
 
-        gl_call!(gl::BindRenderbuffer(gl::RENDERBUFFER, self.depth_id));
-        gl_call!(gl::RenderbufferStorage(
-            gl::RENDERBUFFER,
-            gl::DEPTH_COMPONENT,
-            width as i32,
-            height as i32
-        ));
-        gl_call!(gl::BindRenderbuffer(gl::RENDERBUFFER, 0));
+    pub fn blit_depth(&self) {
+        self.framebuffer.blit_depth();
     }
 
-    pub fn bind_bufs(&self) {
-        gl_call!(gl::ActiveTexture(gl::TEXTURE0));
-        gl_call!(gl::BindTexture(gl::TEXTURE_2D, self.pos_id));
-        gl_call!(gl::ActiveTexture(gl::TEXTURE1));
-        gl_call!(gl::BindTexture(gl::TEXTURE_2D, self.norm_id));
-        gl_call!(gl::ActiveTexture(gl::TEXTURE2));
-        gl_call!(gl::BindTexture(gl::TEXTURE_2D, self.alb_spec_id));
+    /// Copy the depth buffer into another framebuffer, or the default one if `None`, and leave
+    /// it bound.
+    pub fn blit_depth_to(&self, target: Option<&Framebuffer>) {
+        self.framebuffer.blit_depth_to(target);
+    }
 
-        gl_call!(gl::ActiveTexture(gl::TEXTURE0));
+    /// Read an attachment back into an image, float attachments are clamped to `[0, 1]`.
+    /// The compact layout doesn't store position, so it's `None`, and normal is the packed
+    /// octahedral normal, shininess and reflectivity.
+    pub fn capture(&self, attachment: GBufAttachment) -> Option<image::RgbaImage> {
+        attachment
+            .index(self.layout)
+            .map(|index| self.framebuffer.capture(index))
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.framebuffer.resize(width, height);
+    }
+
+    pub fn bind_bufs(&self) {
+        self.framebuffer.bind_colors(0);
+        if self.layout == GBufLayout::Compact {
+            self.framebuffer.bind_depth(DEPTH_UNIT);
+        }
     }
 
     pub fn unbind_bufs(&self) {
-        gl_call!(gl::ActiveTexture(gl::TEXTURE0));
-        gl_call!(gl::BindTexture(gl::TEXTURE_2D, 0));
-        gl_call!(gl::ActiveTexture(gl::TEXTURE1));
-        gl_call!(gl::BindTexture(gl::TEXTURE_2D, 0));
-        gl_call!(gl::ActiveTexture(gl::TEXTURE2));
-        gl_call!(gl::BindTexture(gl::TEXTURE_2D, 0));
-
-        gl_call!(gl::ActiveTexture(gl::TEXTURE0));
+        self.framebuffer.unbind_colors(0);
+        if self.layout == GBufLayout::Compact {
+            self.framebuffer.unbind_depth(DEPTH_UNIT);
+        }
     }
 }
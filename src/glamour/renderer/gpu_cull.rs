@@ -0,0 +1,286 @@
+use crate::{Frustum, ShaderProgram, VertBuf, VertTrans};
+use std::convert::TryFrom;
+
+// invocations per work group, matches `local_size_x` in the culling shader
+const LOCAL_SIZE: usize = 64;
+
+// u32s in a DrawElementsIndirectCommand and a DrawArraysIndirectCommand, both keep their
+// instanceCount second
+const ELEMENTS_COMMAND_SIZE: usize = 5;
+const ARRAYS_COMMAND_SIZE: usize = 4;
+const INSTANCE_COUNT_OFFSET: usize = 1;
+
+/// Whether the context is new enough to cull on the GPU: compute shaders and shader storage
+/// buffers need GL 4.3.
+pub fn gpu_culling_supported() -> bool {
+    let mut major = 0;
+    let mut minor = 0;
+    gl_call!(gl::GetIntegerv(gl::MAJOR_VERSION, &mut major));
+    gl_call!(gl::GetIntegerv(gl::MINOR_VERSION, &mut minor));
+    (major, minor) >= (4, 3)
+}
+
+/// Frustum culls instances in a compute shader, compacting the visible ones into a mesh's
+/// `IndirectInstances` to be drawn without the CPU knowing how many there are.
+pub(crate) struct GpuCull {
+    shader: ShaderProgram,
+}
+
+impl GpuCull {
+    /// # Panics
+    /// If the context doesn't support GL 4.3, see `gpu_culling_supported`.
+    pub fn new() -> Self {
+        GpuCull {
+            shader: ShaderProgram::new_compute(include_str!("shaders/cull.comp")),
+        }
+    }
+
+    pub fn set_frustum(&self, frustum: &Frustum) {
+        for (i, plane) in frustum.planes().iter().enumerate() {
+            self.shader.set_float4(&format!("u_planes[{}]", i), plane);
+        }
+    }
+
+    /// Cull each batch of `instances`, consecutive runs of `batch_sizes`, into the same range of
+    /// `indirect`'s instances and reset its draw commands to count them. `radius` is the
+    /// mesh's bounding radius and `count` its index count, or vertex count if it has no indices.
+    pub fn cull(
+        &self,
+        instances: &VertBuf<VertTrans>,
+        indirect: &mut IndirectInstances,
+        batch_sizes: &[usize],
+        radius: f32,
+        count: u32,
+    ) {
+        indirect.reset_commands(batch_sizes, count);
+        self.shader.set_float("u_radius", radius);
+        self.shader
+            .set_uint("u_source_offset", instances.first_vert() as u32);
+        gl_call!(gl::BindBufferBase(
+            gl::SHADER_STORAGE_BUFFER,
+            0,
+            instances.id()
+        ));
+        gl_call!(gl::BindBufferBase(
+            gl::SHADER_STORAGE_BUFFER,
+            1,
+            indirect.visible.id()
+        ));
+        gl_call!(gl::BindBufferBase(
+            gl::SHADER_STORAGE_BUFFER,
+            2,
+            indirect.commands_id
+        ));
+        let mut first_instance = 0;
+        for (batch, &size) in batch_sizes.iter().enumerate() {
+            self.shader
+                .set_uint("u_first_instance", first_instance as u32);
+            self.shader.set_uint("u_instance_count", size as u32);
+            self.shader.set_uint(
+                "u_instance_count_index",
+                (batch * indirect.command_size() + INSTANCE_COUNT_OFFSET) as u32,
+            );
+            self.shader.bind();
+            let groups = size / LOCAL_SIZE + usize::from(size % LOCAL_SIZE != 0);
+            gl_call!(gl::DispatchCompute(groups as u32, 1, 1));
+            self.shader.unbind();
+            first_instance += size;
+        }
+        for binding in 0..3 {
+            gl_call!(gl::BindBufferBase(gl::SHADER_STORAGE_BUFFER, binding, 0));
+        }
+        // the commands and instances are read by draws, and the counts copied for readback
+        gl_call!(gl::MemoryBarrier(
+            gl::COMMAND_BARRIER_BIT
+                | gl::VERTEX_ATTRIB_ARRAY_BARRIER_BIT
+                | gl::BUFFER_UPDATE_BARRIER_BIT
+        ));
+        indirect.queue_readback();
+    }
+}
+
+/// A mesh's instances that survived `GpuCull`, and an indirect draw command per batch that
+/// points at them with its `baseInstance`.
+pub(crate) struct IndirectInstances {
+    visible: VertBuf<VertTrans>,
+    commands_id: u32,
+    command_count: usize,
+    indexed: bool,
+    // a copy of the commands for `drawn_count`, read once `readback_fence` says it's done
+    readback_id: u32,
+    readback_capacity: usize,
+    readback_len: usize,
+    readback_fence: gl::types::GLsync,
+    drawn: usize,
+}
+
+impl IndirectInstances {
+    /// Room for `max_instances`, drawn with `glDrawElementsIndirect` if `indexed`, otherwise
+    /// `glDrawArraysIndirect`.
+    pub fn new(max_instances: usize, indexed: bool) -> Self {
+        let mut commands_id = 0;
+        gl_call!(gl::GenBuffers(1, &mut commands_id));
+        let mut readback_id = 0;
+        gl_call!(gl::GenBuffers(1, &mut readback_id));
+        IndirectInstances {
+            visible: VertBuf::new(Vec::with_capacity(max_instances)),
+            commands_id,
+            command_count: 0,
+            indexed,
+            readback_id,
+            readback_capacity: 0,
+            readback_len: 0,
+            readback_fence: std::ptr::null(),
+            drawn: 0,
+        }
+    }
+
+    /// Instances there's room for, `GpuCull::cull` can't be given more.
+    pub fn capacity(&self) -> usize {
+        self.visible.capacity()
+    }
+
+    /// The culled instances, for the mesh's vertex array to read from.
+    pub fn visible(&self) -> &VertBuf<VertTrans> {
+        &self.visible
+    }
+
+    fn command_size(&self) -> usize {
+        if self.indexed {
+            ELEMENTS_COMMAND_SIZE
+        } else {
+            ARRAYS_COMMAND_SIZE
+        }
+    }
+
+    /// A command per batch with no instances yet, starting at the batch's first instance.
+    fn reset_commands(&mut self, batch_sizes: &[usize], count: u32) {
+        let mut commands = Vec::with_capacity(batch_sizes.len() * self.command_size());
+        let mut first_instance = 0;
+        for &size in batch_sizes {
+            if self.indexed {
+                // count, instanceCount, firstIndex, baseVertex, baseInstance
+                commands.extend_from_slice(&[count, 0, 0, 0, first_instance as u32]);
+            } else {
+                // count, instanceCount, first, baseInstance
+                commands.extend_from_slice(&[count, 0, 0, first_instance as u32]);
+            }
+            first_instance += size;
+        }
+        self.command_count = batch_sizes.len();
+        let size =
+            gl::types::GLsizeiptr::try_from(commands.len() * std::mem::size_of::<u32>()).unwrap();
+        gl_call!(gl::BindBuffer(gl::DRAW_INDIRECT_BUFFER, self.commands_id));
+        gl_call!(gl::BufferData(
+            gl::DRAW_INDIRECT_BUFFER,
+            size,
+            commands.as_ptr() as *const gl::types::GLvoid,
+            gl::STREAM_DRAW,
+        ));
+        gl_call!(gl::BindBuffer(gl::DRAW_INDIRECT_BUFFER, 0));
+    }
+
+    /// Draw a batch's visible instances, with the mesh's vertex array bound.
+    pub fn draw(&self, batch: usize) {
+        let offset = batch * self.command_size() * std::mem::size_of::<u32>();
+        gl_call!(gl::BindBuffer(gl::DRAW_INDIRECT_BUFFER, self.commands_id));
+        if self.indexed {
+            gl_call!(gl::DrawElementsIndirect(
+                gl::TRIANGLES,
+                gl::UNSIGNED_INT,
+                offset as *const gl::types::GLvoid,
+            ));
+        } else {
+            gl_call!(gl::DrawArraysIndirect(
+                gl::TRIANGLES,
+                offset as *const gl::types::GLvoid,
+            ));
+        }
+        gl_call!(gl::BindBuffer(gl::DRAW_INDIRECT_BUFFER, 0));
+    }
+
+    /// Instances that survived a recent cull, as of the last readback the GPU had finished
+    /// by the time the mesh was culled again, so a frame or more behind. Never waits.
+    pub fn drawn_count(&self) -> usize {
+        self.drawn
+    }
+
+    // copy the counts the cull just wrote to be read back later, unless the last copy is
+    // still in flight
+    fn queue_readback(&mut self) {
+        self.poll_readback();
+        if !self.readback_fence.is_null() {
+            return;
+        }
+        let len = self.command_count * self.command_size();
+        if len == 0 {
+            self.drawn = 0;
+            return;
+        }
+        let size = gl::types::GLsizeiptr::try_from(len * std::mem::size_of::<u32>()).unwrap();
+        gl_call!(gl::BindBuffer(gl::COPY_READ_BUFFER, self.commands_id));
+        gl_call!(gl::BindBuffer(gl::COPY_WRITE_BUFFER, self.readback_id));
+        if len > self.readback_capacity {
+            gl_call!(gl::BufferData(
+                gl::COPY_WRITE_BUFFER,
+                size,
+                std::ptr::null(),
+                gl::STREAM_READ,
+            ));
+            self.readback_capacity = len;
+        }
+        gl_call!(gl::CopyBufferSubData(
+            gl::COPY_READ_BUFFER,
+            gl::COPY_WRITE_BUFFER,
+            0,
+            0,
+            size,
+        ));
+        gl_call!(gl::BindBuffer(gl::COPY_READ_BUFFER, 0));
+        gl_call!(gl::BindBuffer(gl::COPY_WRITE_BUFFER, 0));
+        self.readback_len = len;
+        self.readback_fence = gl_call!(gl::FenceSync(gl::SYNC_GPU_COMMANDS_COMPLETE, 0));
+    }
+
+    // read the queued copy back if the GPU has finished it, without waiting
+    fn poll_readback(&mut self) {
+        if self.readback_fence.is_null() {
+            return;
+        }
+        let status = gl_call!(gl::ClientWaitSync(
+            self.readback_fence,
+            gl::SYNC_FLUSH_COMMANDS_BIT,
+            0
+        ));
+        if status == gl::TIMEOUT_EXPIRED {
+            return;
+        }
+        gl_call!(gl::DeleteSync(self.readback_fence));
+        self.readback_fence = std::ptr::null();
+        let mut commands = vec![0u32; self.readback_len];
+        let size =
+            gl::types::GLsizeiptr::try_from(commands.len() * std::mem::size_of::<u32>()).unwrap();
+        gl_call!(gl::BindBuffer(gl::COPY_READ_BUFFER, self.readback_id));
+        gl_call!(gl::GetBufferSubData(
+            gl::COPY_READ_BUFFER,
+            0,
+            size,
+            commands.as_mut_ptr() as *mut gl::types::GLvoid,
+        ));
+        gl_call!(gl::BindBuffer(gl::COPY_READ_BUFFER, 0));
+        self.drawn = commands
+            .chunks(self.command_size())
+            .map(|command| command[INSTANCE_COUNT_OFFSET] as usize)
+            .sum();
+    }
+}
+
+impl Drop for IndirectInstances {
+    fn drop(&mut self) {
+        if !self.readback_fence.is_null() {
+            gl_call!(gl::DeleteSync(self.readback_fence));
+        }
+        gl_call!(gl::DeleteBuffers(1, &self.commands_id));
+        gl_call!(gl::DeleteBuffers(1, &self.readback_id));
+    }
+}
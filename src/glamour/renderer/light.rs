@@ -0,0 +1,381 @@
+use crate::{glm, Transform};
+
+/// Largest radius `Attenuation::radius` returns, for lights that never fade out.
+pub const MAX_LIGHT_RADIUS: f32 = 1.0e4;
+
+/// Coefficients of a light's falloff: `1 / (constant + linear * d + quadratic * d^2)`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Attenuation {
+    pub constant: f32,
+    pub linear: f32,
+    pub quadratic: f32,
+}
+
+impl Attenuation {
+    pub fn new(constant: f32, linear: f32, quadratic: f32) -> Self {
+        Attenuation {
+            constant,
+            linear,
+            quadratic,
+        }
+    }
+
+    /// Approximate coefficients for a light that should reach roughly `range` units.
+    ///
+    /// ```
+    /// # use glamour::Attenuation;
+    /// let atten = Attenuation::from_range(32.0);
+    /// assert!((atten.linear - 0.14).abs() < 0.01);
+    /// assert!((atten.quadratic - 0.07).abs() < 0.01);
+    /// ```
+    pub fn from_range(range: f32) -> Self {
+        Attenuation {
+            constant: 1.0,
+            linear: 4.5 / range,
+            quadratic: 75.0 / (range * range),
+        }
+    }
+
+    /// Distance at which a light of `brightness` attenuates below 5/256, where its contribution
+    /// is no longer visible in an 8-bit framebuffer.
+    ///
+    /// ```
+    /// # use glamour::Attenuation;
+    /// let radius = Attenuation::new(1.0, 0.14, 0.07).radius(1.0);
+    /// assert!((radius - 25.8).abs() < 0.1);
+    /// ```
+    ///
+    /// A light that never reaches the threshold has a radius of 0, and one that never falls
+    /// below it is capped at `MAX_LIGHT_RADIUS`.
+    pub fn radius(&self, brightness: f32) -> f32 {
+        let threshold = 5.0 / 256.0;
+        let c = self.constant - brightness / threshold;
+        if c.is_nan() || c >= 0.0 {
+            return 0.0;
+        }
+        let radius = if self.quadratic > 0.0 {
+            (-self.linear + (self.linear * self.linear - 4.0 * self.quadratic * c).sqrt())
+                / (2.0 * self.quadratic)
+        } else if self.linear > 0.0 {
+            -c / self.linear
+        } else {
+            MAX_LIGHT_RADIUS
+        };
+        radius.clamp(0.0, MAX_LIGHT_RADIUS)
+    }
+}
+
+impl Default for Attenuation {
+    fn default() -> Self {
+        Attenuation::new(1.0, 0.14, 0.07)
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct PointLight {
+    pub position: glm::Vec3,
+    pub color: glm::Vec3,
+    pub intensity: f32,
+    pub attenuation: Attenuation,
+    /// Only the first `Renderer::max_point_shadows` lights with this set get a shadow map.
+    pub cast_shadows: bool,
+}
+
+impl PointLight {
+    pub fn new() -> Self {
+        PointLight {
+            position: glm::vec3(0.0, 0.0, 0.0),
+            color: glm::vec3(1.0, 1.0, 1.0),
+            intensity: 1.0,
+            attenuation: Attenuation::default(),
+            cast_shadows: false,
+        }
+    }
+    pub fn from_pos(position: glm::Vec3) -> Self {
+        PointLight {
+            position,
+            ..PointLight::new()
+        }
+    }
+    /// Radius of the light's sphere of influence, based on its attenuation and brightest channel.
+    /// The deferred light-volume pass scales each light's sphere by this.
+    pub fn radius(&self) -> f32 {
+        let brightness = self.intensity * self.color.max();
+        self.attenuation.radius(brightness)
+    }
+    pub fn transform(&self) -> Transform {
+        Transform::from_pos(self.position)
+    }
+}
+
+impl Default for PointLight {
+    fn default() -> Self {
+        PointLight::new()
+    }
+}
+
+/// How a `PointLight` is laid out in the light buffer texture, one `vec4` per texel.
+#[derive(Debug, Copy, Clone)]
+#[repr(C)]
+pub(crate) struct PointLightTexels {
+    position_radius: glm::Vec4,
+    color_intensity: glm::Vec4,
+    attenuation_shadow: glm::Vec4,
+}
+
+impl PointLightTexels {
+    pub fn from_light(light: &PointLight) -> Self {
+        let atten = &light.attenuation;
+        PointLightTexels {
+            position_radius: glm::vec4(
+                light.position.x,
+                light.position.y,
+                light.position.z,
+                light.radius(),
+            ),
+            color_intensity: glm::vec4(
+                light.color.x,
+                light.color.y,
+                light.color.z,
+                light.intensity,
+            ),
+            attenuation_shadow: glm::vec4(atten.constant, atten.linear, atten.quadratic, -1.0),
+        }
+    }
+
+    /// Which cube of the shadow maps the light uses, or no shadows if negative.
+    pub fn set_shadow_index(&mut self, index: i32) {
+        self.attenuation_shadow.w = index as f32;
+    }
+}
+
+/// A light infinitely far away, shining the same direction everywhere, e.g. the sun.
+#[derive(Debug, Copy, Clone)]
+pub struct DirectionalLight {
+    /// The direction the light travels in, it doesn't need to be normalized.
+    pub direction: glm::Vec3,
+    pub color: glm::Vec3,
+    pub intensity: f32,
+    /// Only the first light with this set gets a shadow map.
+    pub cast_shadows: bool,
+}
+
+impl DirectionalLight {
+    pub fn new() -> Self {
+        DirectionalLight {
+            direction: glm::vec3(0.0, -1.0, 0.0),
+            color: glm::vec3(1.0, 1.0, 1.0),
+            intensity: 1.0,
+            cast_shadows: false,
+        }
+    }
+    pub fn from_dir(direction: glm::Vec3) -> Self {
+        DirectionalLight {
+            direction,
+            ..DirectionalLight::new()
+        }
+    }
+}
+
+impl Default for DirectionalLight {
+    fn default() -> Self {
+        DirectionalLight::new()
+    }
+}
+
+/// A point light restricted to a cone, fading out between the inner and outer angles.
+#[derive(Debug, Copy, Clone)]
+pub struct SpotLight {
+    pub position: glm::Vec3,
+    /// The direction the cone points in, it doesn't need to be normalized.
+    pub direction: glm::Vec3,
+    pub color: glm::Vec3,
+    pub intensity: f32,
+    pub attenuation: Attenuation,
+    /// Angle from the cone's axis to where the light starts fading, in radians.
+    pub inner_angle: f32,
+    /// Angle from the cone's axis to where the light is gone, in radians.
+    pub outer_angle: f32,
+}
+
+impl SpotLight {
+    pub fn new() -> Self {
+        SpotLight {
+            position: glm::vec3(0.0, 0.0, 0.0),
+            direction: glm::vec3(0.0, -1.0, 0.0),
+            color: glm::vec3(1.0, 1.0, 1.0),
+            intensity: 1.0,
+            attenuation: Attenuation::default(),
+            inner_angle: 12.5_f32.to_radians(),
+            outer_angle: 17.5_f32.to_radians(),
+        }
+    }
+    pub fn from_pos_dir(position: glm::Vec3, direction: glm::Vec3) -> Self {
+        SpotLight {
+            position,
+            direction,
+            ..SpotLight::new()
+        }
+    }
+    /// Radius of the sphere bounding the light's cone, the same as a `PointLight`'s radius.
+    pub fn radius(&self) -> f32 {
+        let brightness = self.intensity * self.color.max();
+        self.attenuation.radius(brightness)
+    }
+    pub fn transform(&self) -> Transform {
+        Transform::from_pos(self.position)
+    }
+}
+
+impl Default for SpotLight {
+    fn default() -> Self {
+        SpotLight::new()
+    }
+}
+
+/// Normalizes a light's direction, pointing straight down if it has no length.
+pub(crate) fn light_direction(direction: &glm::Vec3) -> glm::Vec3 {
+    let length = glm::length(direction);
+    if length > f32::EPSILON && length.is_finite() {
+        direction / length
+    } else {
+        glm::vec3(0.0, -1.0, 0.0)
+    }
+}
+
+/// How a `SpotLight` is laid out in the light buffer texture, one `vec4` per texel.
+#[derive(Debug, Copy, Clone)]
+#[repr(C)]
+pub(crate) struct SpotLightTexels {
+    position_radius: glm::Vec4,
+    color_intensity: glm::Vec4,
+    attenuation_inner: glm::Vec4,
+    direction_outer: glm::Vec4,
+}
+
+impl SpotLightTexels {
+    pub fn from_light(light: &SpotLight) -> Self {
+        let atten = &light.attenuation;
+        let direction = light_direction(&light.direction);
+        // the outer angle can't be inside of the inner angle
+        let outer_angle = light.outer_angle.max(light.inner_angle);
+        SpotLightTexels {
+            position_radius: glm::vec4(
+                light.position.x,
+                light.position.y,
+                light.position.z,
+                light.radius(),
+            ),
+            color_intensity: glm::vec4(
+                light.color.x,
+                light.color.y,
+                light.color.z,
+                light.intensity,
+            ),
+            attenuation_inner: glm::vec4(
+                atten.constant,
+                atten.linear,
+                atten.quadratic,
+                light.inner_angle.cos(),
+            ),
+            direction_outer: glm::vec4(direction.x, direction.y, direction.z, outer_angle.cos()),
+        }
+    }
+}
+
+/// How a `DirectionalLight` is laid out in the light buffer texture, one `vec4` per texel.
+#[derive(Debug, Copy, Clone)]
+#[repr(C)]
+pub(crate) struct DirectionalLightTexels {
+    direction_shadow: glm::Vec4,
+    color_intensity: glm::Vec4,
+}
+
+impl DirectionalLightTexels {
+    pub fn from_light(light: &DirectionalLight) -> Self {
+        let direction = light_direction(&light.direction);
+        DirectionalLightTexels {
+            direction_shadow: glm::vec4(direction.x, direction.y, direction.z, -1.0),
+            color_intensity: glm::vec4(
+                light.color.x,
+                light.color.y,
+                light.color.z,
+                light.intensity,
+            ),
+        }
+    }
+
+    /// Which shadow map the light uses, or no shadows if negative.
+    pub fn set_shadow_index(&mut self, index: i32) {
+        self.direction_shadow.w = index as f32;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn radius_is_zero_for_dark_lights() {
+        let mut light = PointLight::new();
+        light.intensity = 0.0;
+        assert_eq!(light.radius(), 0.0);
+
+        light.intensity = 1.0;
+        light.color = glm::vec3(0.0, 0.0, 0.0);
+        assert_eq!(light.radius(), 0.0);
+
+        light.color = glm::vec3(1.0, 1.0, 1.0);
+        light.intensity = -1.0;
+        assert_eq!(light.radius(), 0.0);
+    }
+
+    #[test]
+    fn radius_is_capped_without_falloff() {
+        let atten = Attenuation::new(1.0, 0.0, 0.0);
+        assert_eq!(atten.radius(1.0), MAX_LIGHT_RADIUS);
+        assert_eq!(
+            Attenuation::new(1.0, 1e-9, 0.0).radius(1.0),
+            MAX_LIGHT_RADIUS
+        );
+    }
+
+    #[test]
+    fn radius_grows_with_brightness() {
+        let atten = Attenuation::default();
+        let dim = atten.radius(0.5);
+        let bright = atten.radius(2.0);
+        assert!(dim > 0.0 && dim.is_finite());
+        assert!(bright > dim && bright.is_finite());
+        // linear-only falloff solves `constant + linear * d = brightness / threshold`
+        let linear = Attenuation::new(1.0, 0.5, 0.0).radius(1.0);
+        assert!((linear - (256.0 / 5.0 - 1.0) / 0.5).abs() < 1e-3);
+    }
+
+    #[test]
+    fn zero_direction_points_down() {
+        let down = glm::vec3(0.0, -1.0, 0.0);
+        let spot = SpotLight::from_pos_dir(glm::vec3(1.0, 2.0, 3.0), glm::vec3(0.0, 0.0, 0.0));
+        assert_eq!(
+            SpotLightTexels::from_light(&spot).direction_outer.xyz(),
+            down
+        );
+
+        let mut directional = DirectionalLight::new();
+        directional.direction = glm::vec3(0.0, 0.0, 0.0);
+        assert_eq!(
+            DirectionalLightTexels::from_light(&directional)
+                .direction_shadow
+                .xyz(),
+            down
+        );
+
+        directional.direction = glm::vec3(2.0, 0.0, 0.0);
+        assert_eq!(
+            DirectionalLightTexels::from_light(&directional)
+                .direction_shadow
+                .xyz(),
+            glm::vec3(1.0, 0.0, 0.0)
+        );
+    }
+}
@@ -0,0 +1,108 @@
+use crate::{
+    glm, ModelMaterial, Texture, TextureArray, TextureBuilder, TextureError, TextureFilter,
+    TextureFormat, TextureRef,
+};
+use std::rc::Rc;
+
+/// Handle to a material registered with `Renderer::register_material`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct MaterialHandle(pub(crate) usize);
+
+/// How a surface is shaded: its albedo, and the strength and shininess of its specular
+/// highlights. Textures are multiplied by their colour or strength, and are shared with `Rc` so
+/// several materials can use the same one.
+#[derive(Debug, Clone)]
+pub struct Material {
+    pub albedo_color: glm::Vec4,
+    /// Should be in an sRGB format, the `TextureBuilder` default, so it's decoded to linear
+    /// before it's lit. A linear format is lit as is and looks washed out once the output is
+    /// sRGB encoded, see `Renderer::set_srgb_output`.
+    pub albedo_texture: Option<Rc<Texture>>,
+    /// Used instead of `albedo_texture` if set, sampling the layer given for each instance.
+    /// The same colour space applies.
+    pub albedo_texture_array: Option<Rc<TextureArray>>,
+    pub specular_strength: f32,
+    /// Only the red channel is used, with linear rather than sRGB texels.
+    pub specular_texture: Option<Rc<Texture>>,
+    /// The Blinn-Phong exponent, higher is a smaller and sharper highlight.
+    pub shininess: f32,
+    /// Tangent space normals, with linear rather than sRGB texels.
+    pub normal_texture: Option<Rc<Texture>>,
+    /// How much of the renderer's environment map is reflected instead of lit, from `0` to
+    /// `1`. Ignored without an environment map, see `Renderer::set_environment_map`.
+    pub reflectivity: f32,
+    /// Alpha blended by the albedo's alpha instead of drawn opaque. Transparent instances are
+    /// sorted back to front and drawn forward after everything else, in either path, and don't
+    /// cast shadows.
+    pub transparent: bool,
+}
+
+impl Material {
+    pub fn new() -> Self {
+        Material {
+            albedo_color: glm::vec4(1.0, 1.0, 1.0, 1.0),
+            albedo_texture: None,
+            albedo_texture_array: None,
+            specular_strength: 0.5,
+            specular_texture: None,
+            shininess: 256.0,
+            normal_texture: None,
+            reflectivity: 0.0,
+            transparent: false,
+        }
+    }
+    pub fn from_color(albedo_color: glm::Vec4) -> Self {
+        Material {
+            albedo_color,
+            ..Material::new()
+        }
+    }
+    pub fn from_texture(albedo_texture: Rc<Texture>) -> Self {
+        Material {
+            albedo_texture: Some(albedo_texture),
+            ..Material::new()
+        }
+    }
+    pub fn from_texture_array(albedo_texture_array: Rc<TextureArray>) -> Self {
+        Material {
+            albedo_texture_array: Some(albedo_texture_array),
+            ..Material::new()
+        }
+    }
+    /// Create a material from a loaded model's, loading any textures it references with
+    /// trilinear filtering. The albedo texture is loaded as sRGB and the others as linear.
+    /// It's transparent if its albedo colour is.
+    pub fn from_model_material(material: &ModelMaterial) -> Result<Self, TextureError> {
+        let albedo_builder = TextureBuilder::new().with_mipmaps(TextureFilter::Linear);
+        let linear_builder = albedo_builder.clone().with_format(TextureFormat::Rgba8);
+        let texture = |builder: &TextureBuilder,
+                       texture: &Option<TextureRef>|
+         -> Result<Option<Rc<Texture>>, TextureError> {
+            let texture = match texture {
+                Some(TextureRef::Path(path)) => builder.build_from_file(path)?,
+                Some(TextureRef::Image(image)) => {
+                    builder.build_from_image(&image::DynamicImage::ImageRgba8(image.clone()))?
+                }
+                None => return Ok(None),
+            };
+            Ok(Some(Rc::new(texture)))
+        };
+        Ok(Material {
+            albedo_color: material.albedo_color,
+            albedo_texture: texture(&albedo_builder, &material.albedo_texture)?,
+            albedo_texture_array: None,
+            specular_strength: material.specular_strength,
+            specular_texture: texture(&linear_builder, &material.specular_texture)?,
+            shininess: material.shininess,
+            normal_texture: texture(&linear_builder, &material.normal_texture)?,
+            reflectivity: 0.0,
+            transparent: material.albedo_color.w < 1.0,
+        })
+    }
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Material::new()
+    }
+}
@@ -0,0 +1,413 @@
+use crate::{
+    generate_tangents, glm, GpuCull, IndexBuf, IndirectInstances, MaterialHandle, StreamMode,
+    Transform, VertArray, VertBasic, VertBuf, VertTangent, VertTrans,
+};
+use rayon::prelude::*;
+
+/// Handle to a mesh registered with `Renderer::register_mesh`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct MeshHandle(pub(crate) usize);
+
+/// A registered mesh, and the instances of it queued up to be drawn this frame.
+pub(crate) struct Mesh {
+    vao: VertArray,
+    vbo: VertBuf<VertBasic>,
+    // instances stay resident between frames, `set_data` only uploads them again if they change
+    trans_vbo: VertBuf<VertTrans>,
+    // what each resident instance was converted from
+    transforms: Vec<Transform>,
+    // instances pushed since `clear_instances`, the resident ones past this are last frame's
+    instance_count: usize,
+    // whether the pushed instances differ from the uploaded ones
+    instances_dirty: bool,
+    // instances with transparent materials, in the order they're drawn
+    transparent_vbo: VertBuf<VertTrans>,
+    // every instance pushed since `clear_instances` when frustum culling on the CPU dropped
+    // some, since casters off-screen can still shadow what's on-screen
+    casters: Vec<Transform>,
+    caster_vbo: VertBuf<VertTrans>,
+    // whether the shadow passes draw `caster_vbo` this frame, rather than the instances
+    casters_uploaded: bool,
+    // kept alive for the vao, after the instances so their attribute locations don't move
+    _tangent_vbo: VertBuf<VertTangent>,
+    bounding_radius: f32,
+    material: MaterialHandle,
+    // consecutive runs of instances with the same material, as (material, instance count)
+    batches: Vec<(MaterialHandle, usize)>,
+    // created the first time the mesh is culled on the GPU, and again when the instances outgrow it
+    indirect: Option<IndirectInstances>,
+    // whether this frame's instances were culled on the GPU, and the vao reads `indirect`'s
+    gpu_culled: bool,
+}
+
+impl Mesh {
+    /// Meshes without indices (an empty `IndexBuf`) are drawn as plain triangles.
+    /// Tangents for normal mapping are generated from the vertices.
+    pub fn new(
+        vbo: VertBuf<VertBasic>,
+        ibo: IndexBuf,
+        max_instances: usize,
+        material: MaterialHandle,
+    ) -> Self {
+        let tangent_vbo = VertBuf::new(generate_tangents(vbo.vertices(), ibo.indices()));
+        Mesh::with_tangents(vbo, tangent_vbo, ibo, max_instances, material)
+    }
+
+    /// One tangent per vertex in `vbo`.
+    pub fn with_tangents(
+        vbo: VertBuf<VertBasic>,
+        tangent_vbo: VertBuf<VertTangent>,
+        ibo: IndexBuf,
+        max_instances: usize,
+        material: MaterialHandle,
+    ) -> Self {
+        assert_eq!(
+            tangent_vbo.vertices().len(),
+            vbo.vertices().len(),
+            "one tangent per vertex"
+        );
+        let trans_vbo = VertBuf::<VertTrans>::new(Vec::with_capacity(max_instances));
+        let vao = VertArray::new(&[&vbo, &trans_vbo, &tangent_vbo], ibo);
+        let bounding_radius = vbo
+            .vertices()
+            .iter()
+            .map(|v| glm::length(&v.position))
+            .fold(0.0, f32::max);
+        Mesh {
+            vao,
+            vbo,
+            trans_vbo,
+            transforms: Vec::with_capacity(max_instances),
+            instance_count: 0,
+            instances_dirty: false,
+            transparent_vbo: VertBuf::new(Vec::new()),
+            casters: Vec::new(),
+            caster_vbo: VertBuf::new(Vec::new()),
+            casters_uploaded: false,
+            _tangent_vbo: tangent_vbo,
+            bounding_radius,
+            material,
+            batches: Vec::new(),
+            indirect: None,
+            gpu_culled: false,
+        }
+    }
+
+    /// The material instances are drawn with, unless they're pushed with their own.
+    pub fn material(&self) -> MaterialHandle {
+        self.material
+    }
+
+    pub fn set_material(&mut self, material: MaterialHandle) {
+        self.material = material;
+    }
+
+    /// Distance from the mesh's origin to its furthest vertex.
+    pub fn bounding_radius(&self) -> f32 {
+        self.bounding_radius
+    }
+
+    pub fn instances(&self) -> &[VertTrans] {
+        &self.trans_vbo.vertices()[..self.instance_count]
+    }
+
+    /// Every instance `draw_all` draws, frustum culled or not.
+    pub fn shadow_casters(&self) -> &[VertTrans] {
+        if self.casters_uploaded {
+            self.caster_vbo.vertices()
+        } else {
+            self.instances()
+        }
+    }
+
+    /// Queue up instances with the mesh's own material, or `material` if given.
+    /// Texture array layers are all 0 unless `layers` are given, one for each transform.
+    pub fn push_instances(
+        &mut self,
+        transforms: &[Transform],
+        layers: Option<&[u32]>,
+        material: Option<MaterialHandle>,
+    ) {
+        let material = material.unwrap_or(self.material);
+        match self.batches.last_mut() {
+            Some((last, count)) if *last == material => *count += transforms.len(),
+            _ => self.batches.push((material, transforms.len())),
+        }
+        let start = self.instance_count;
+        let end = start + transforms.len();
+        self.instance_count = end;
+        let vertices = self.trans_vbo.vertices_mut();
+        if vertices.len() < end {
+            vertices.resize_with(end, std::default::Default::default);
+            self.transforms.resize_with(end, Transform::new);
+            // nothing resident to compare against
+            self.instances_dirty = true;
+            vertices[start..]
+                .par_iter_mut()
+                .zip(self.transforms[start..].par_iter_mut())
+                .zip(transforms.par_iter())
+                .for_each(|((v, resident), t)| {
+                    v.set(t);
+                    *resident = *t;
+                });
+        } else {
+            // only convert the transforms that moved since they were last pushed here
+            let changed = vertices[start..end]
+                .par_iter_mut()
+                .zip(self.transforms[start..end].par_iter_mut())
+                .zip(transforms.par_iter())
+                .map(|((v, resident), t)| {
+                    if resident == t {
+                        return false;
+                    }
+                    v.set(t);
+                    *resident = *t;
+                    true
+                })
+                .reduce(|| false, |a, b| a || b);
+            self.instances_dirty |= changed;
+        }
+        let layer_of = |i: usize| layers.map_or(0.0, |layers| layers[i] as f32);
+        if let Some(layers) = layers {
+            assert_eq!(layers.len(), transforms.len(), "one layer per transform");
+        }
+        let changed = vertices[start..end]
+            .par_iter_mut()
+            .enumerate()
+            .map(|(i, v)| {
+                let layer = layer_of(i);
+                if v.layer == layer {
+                    return false;
+                }
+                v.layer = layer;
+                true
+            })
+            .reduce(|| false, |a, b| a || b);
+        self.instances_dirty |= changed;
+    }
+
+    /// Recreate the instance buffers to upload the way `mode` says, keeping queued instances.
+    pub fn set_stream_mode(&mut self, mode: StreamMode) {
+        if self.trans_vbo.stream_mode() == mode {
+            return;
+        }
+        self.trans_vbo = Mesh::restream(&mut self.trans_vbo, mode);
+        self.transparent_vbo = Mesh::restream(&mut self.transparent_vbo, mode);
+        self.caster_vbo = Mesh::restream(&mut self.caster_vbo, mode);
+        self.instances_dirty = true;
+        if !self.gpu_culled {
+            self.vao.set_buf_offset(1, &self.trans_vbo, 0);
+        }
+    }
+
+    fn restream(vbo: &mut VertBuf<VertTrans>, mode: StreamMode) -> VertBuf<VertTrans> {
+        let mut vertices = Vec::with_capacity(vbo.capacity());
+        vertices.append(vbo.vertices_mut());
+        VertBuf::with_stream_mode(vertices, mode)
+    }
+
+    /// Start a new frame's instances. The last frame's stay resident until `set_data`, so
+    /// unchanged ones aren't converted or uploaded again.
+    pub fn clear_instances(&mut self) {
+        self.instance_count = 0;
+        self.transparent_vbo.vertices_mut().clear();
+        self.casters.clear();
+        self.batches.clear();
+    }
+
+    /// Keep every transform pushed this frame, for instances that are then culled on the CPU.
+    pub fn push_casters(&mut self, transforms: &[Transform]) {
+        self.casters.extend_from_slice(transforms);
+    }
+
+    /// Upload the casters kept with `push_casters`, if there are any and `shadows` are drawn.
+    pub fn set_caster_data(&mut self, shadows: bool) {
+        self.casters_uploaded = shadows && !self.casters.is_empty();
+        if !self.casters_uploaded {
+            return;
+        }
+        let vertices = self.caster_vbo.vertices_mut();
+        vertices.clear();
+        vertices.par_extend(self.casters.par_iter().map(VertTrans::from_transform));
+        self.caster_vbo.set_data();
+    }
+
+    /// Queue up a transparent instance, returning its index for `draw_transparent`.
+    pub fn push_transparent(&mut self, instance: VertTrans) -> usize {
+        let vertices = self.transparent_vbo.vertices_mut();
+        vertices.push(instance);
+        vertices.len() - 1
+    }
+
+    /// Upload the transparent instances, once they've all been pushed.
+    pub fn set_transparent_data(&mut self) {
+        if !self.transparent_vbo.vertices().is_empty() {
+            self.transparent_vbo.set_data();
+        }
+    }
+
+    /// Draw `count` transparent instances from `first` on, with whatever shader and textures
+    /// are bound.
+    pub fn draw_transparent(&self, first: usize, count: usize) {
+        let first_vert = self.transparent_vbo.first_vert();
+        self.vao
+            .set_buf_offset(1, &self.transparent_vbo, first_vert + first);
+        self.draw_instances(count);
+        self.bind_opaque_instances();
+    }
+
+    /// Point the vao back at this frame's opaque instances.
+    fn bind_opaque_instances(&self) {
+        match self.indirect.as_ref().filter(|_| self.gpu_culled) {
+            Some(indirect) => self.vao.set_buf_offset(1, indirect.visible(), 0),
+            None => {
+                let first_vert = self.trans_vbo.first_vert();
+                self.vao.set_buf_offset(1, &self.trans_vbo, first_vert);
+            }
+        }
+    }
+
+    /// Upload this frame's instances, before any draws, unless they're the same as last frame's.
+    /// With `gpu_cull`, they're then culled on the GPU and drawn indirectly.
+    pub fn set_data(&mut self, gpu_cull: Option<&GpuCull>) {
+        if self.trans_vbo.vertices().len() != self.instance_count {
+            self.trans_vbo.vertices_mut().truncate(self.instance_count);
+            self.transforms.truncate(self.instance_count);
+            self.instances_dirty = true;
+        }
+        if self.instances_dirty {
+            self.trans_vbo.set_data();
+            self.instances_dirty = false;
+        }
+        match gpu_cull {
+            Some(gpu_cull) => {
+                let indexed = !self.vao.index_buf().is_empty();
+                let capacity = self.trans_vbo.capacity();
+                if !matches!(&self.indirect, Some(i) if i.capacity() >= capacity) {
+                    self.indirect = Some(IndirectInstances::new(capacity, indexed));
+                    // point the vao at the new buffer
+                    self.gpu_culled = false;
+                }
+                let indirect = self.indirect.as_mut().unwrap();
+                let batch_sizes: Vec<usize> = self.batches.iter().map(|(_, size)| *size).collect();
+                let count = if indexed {
+                    self.vao.index_buf().len()
+                } else {
+                    self.vbo.vertices().len()
+                };
+                gpu_cull.cull(
+                    &self.trans_vbo,
+                    indirect,
+                    &batch_sizes,
+                    self.bounding_radius,
+                    count as u32,
+                );
+                if !self.gpu_culled {
+                    self.vao.set_buf_offset(1, indirect.visible(), 0);
+                    self.gpu_culled = true;
+                }
+            }
+            None => {
+                // a ring's instances are somewhere new every frame
+                let ring = self.trans_vbo.stream_mode() == StreamMode::PersistentRing;
+                if self.gpu_culled || ring {
+                    let first_vert = self.trans_vbo.first_vert();
+                    self.vao.set_buf_offset(1, &self.trans_vbo, first_vert);
+                    self.gpu_culled = false;
+                }
+            }
+        }
+    }
+
+    /// How many instances survived culling on the GPU, if they were culled there this frame.
+    /// Read back without waiting, so a frame or more behind.
+    pub fn gpu_drawn_count(&self) -> Option<usize> {
+        match &self.indirect {
+            Some(indirect) if self.gpu_culled => Some(indirect.drawn_count()),
+            _ => None,
+        }
+    }
+
+    /// Draw every instance, or only the ones that survived culling on the GPU, with whatever
+    /// shader and textures are bound.
+    pub fn draw(&self) {
+        if let Some(indirect) = self.indirect.as_ref().filter(|_| self.gpu_culled) {
+            self.vao.bind();
+            for batch in 0..self.batches.len() {
+                indirect.draw(batch);
+            }
+            self.vao.unbind();
+            return;
+        }
+        self.draw_instances(self.trans_vbo.vertices().len());
+    }
+
+    /// Draw every instance pushed this frame, including the ones frustum culling dropped, for
+    /// the shadow passes.
+    pub fn draw_all(&self) {
+        // culled on the GPU, every instance is still resident, only the vao reads the survivors
+        let vbo = if self.casters_uploaded {
+            &self.caster_vbo
+        } else if self.gpu_culled {
+            &self.trans_vbo
+        } else {
+            self.draw();
+            return;
+        };
+        self.vao.set_buf_offset(1, vbo, vbo.first_vert());
+        self.draw_instances(vbo.vertices().len());
+        self.bind_opaque_instances();
+    }
+
+    /// Draw every instance a batch at a time, calling `bind_material` before each batch.
+    pub fn draw_batches<F: FnMut(MaterialHandle)>(&self, mut bind_material: F) {
+        if let Some(indirect) = self.indirect.as_ref().filter(|_| self.gpu_culled) {
+            for (batch, &(material, _)) in self.batches.iter().enumerate() {
+                bind_material(material);
+                self.vao.bind();
+                indirect.draw(batch);
+                self.vao.unbind();
+            }
+            return;
+        }
+        let first_vert = self.trans_vbo.first_vert();
+        let mut first_instance = 0;
+        for &(material, count) in &self.batches {
+            bind_material(material);
+            if first_instance > 0 {
+                self.vao
+                    .set_buf_offset(1, &self.trans_vbo, first_vert + first_instance);
+            }
+            self.draw_instances(count);
+            first_instance += count;
+        }
+        if self.batches.len() > 1 {
+            self.vao.set_buf_offset(1, &self.trans_vbo, first_vert);
+        }
+    }
+
+    fn draw_instances(&self, instance_count: usize) {
+        if instance_count == 0 {
+            return;
+        }
+        let instance_count = instance_count as i32;
+        self.vao.bind();
+        if self.vao.index_buf().is_empty() {
+            gl_call!(gl::DrawArraysInstanced(
+                gl::TRIANGLES,
+                0,
+                self.vbo.vertices().len() as i32,
+                instance_count,
+            ));
+        } else {
+            gl_call!(gl::DrawElementsInstanced(
+                gl::TRIANGLES,
+                self.vao.index_buf().len() as i32,
+                gl::UNSIGNED_INT,
+                std::ptr::null(),
+                instance_count,
+            ));
+        }
+        self.vao.unbind();
+    }
+}
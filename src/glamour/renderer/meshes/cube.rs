@@ -1,4 +1,4 @@
-use crate::{glm, VertBasic};
+use crate::{generate_tangents, glm, VertBasic, VertTangent};
 
 pub fn tex_cube_verts() -> Vec<VertBasic> {
     vec![
@@ -153,3 +153,7 @@ pub fn tex_cube_inds() -> Vec<u32> {
         22, 23, 20, //
     ]
 }
+
+pub fn tex_cube_tangents() -> Vec<VertTangent> {
+    generate_tangents(&tex_cube_verts(), &tex_cube_inds())
+}
@@ -1,2 +1,4 @@
 pub mod cube;
 pub mod quad;
+pub mod sphere;
+pub mod triangle;
@@ -24,3 +24,34 @@ pub fn ndc_quad_verts() -> Vec<VertBasic> {
         },
     ]
 }
+
+/// A unit quad in the xy plane, facing +z.
+pub fn quad_verts() -> Vec<VertBasic> {
+    let normal = glm::vec3(0.0, 0.0, 1.0);
+    vec![
+        VertBasic {
+            position: glm::vec3(-0.5, -0.5, 0.0),
+            normal,
+            tex_coords: glm::vec2(0.0, 0.0),
+        },
+        VertBasic {
+            position: glm::vec3(0.5, -0.5, 0.0),
+            normal,
+            tex_coords: glm::vec2(1.0, 0.0),
+        },
+        VertBasic {
+            position: glm::vec3(0.5, 0.5, 0.0),
+            normal,
+            tex_coords: glm::vec2(1.0, 1.0),
+        },
+        VertBasic {
+            position: glm::vec3(-0.5, 0.5, 0.0),
+            normal,
+            tex_coords: glm::vec2(0.0, 1.0),
+        },
+    ]
+}
+
+pub fn quad_inds() -> Vec<u32> {
+    vec![0, 1, 2, 2, 3, 0]
+}
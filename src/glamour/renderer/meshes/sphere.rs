@@ -0,0 +1,47 @@
+use crate::{glm, VertBasic};
+
+/// A UV sphere, made of `sectors` slices around the y axis and `stacks` slices from pole to pole.
+///
+/// The vertices are pushed out so the flat faces circumscribe the unit sphere, which makes it
+/// suitable as a bounding volume.
+pub fn sphere_verts(sectors: u32, stacks: u32) -> Vec<VertBasic> {
+    let sector_step = 2.0 * std::f32::consts::PI / sectors as f32;
+    let stack_step = std::f32::consts::PI / stacks as f32;
+    let radius = 1.0 / ((sector_step * 0.5).cos() * (stack_step * 0.5).cos());
+    let mut verts = Vec::with_capacity(((sectors + 1) * (stacks + 1)) as usize);
+    for i in 0..=stacks {
+        // from the top pole (pi / 2) to the bottom pole (-pi / 2)
+        let stack_angle = std::f32::consts::FRAC_PI_2 - i as f32 * stack_step;
+        let xz = stack_angle.cos();
+        let y = stack_angle.sin();
+        for j in 0..=sectors {
+            let sector_angle = j as f32 * sector_step;
+            let normal = glm::vec3(xz * sector_angle.cos(), y, xz * sector_angle.sin());
+            verts.push(VertBasic {
+                position: normal * radius,
+                normal,
+                tex_coords: glm::vec2(j as f32 / sectors as f32, i as f32 / stacks as f32),
+            });
+        }
+    }
+    verts
+}
+
+/// Counter-clockwise (outward facing) triangle indices for `sphere_verts`.
+pub fn sphere_inds(sectors: u32, stacks: u32) -> Vec<u32> {
+    let mut inds = Vec::with_capacity((sectors * stacks * 6) as usize);
+    for i in 0..stacks {
+        let k1 = i * (sectors + 1);
+        let k2 = k1 + sectors + 1;
+        for j in 0..sectors {
+            // the poles only need one triangle per sector
+            if i != 0 {
+                inds.extend_from_slice(&[k1 + j, k1 + j + 1, k2 + j]);
+            }
+            if i != stacks - 1 {
+                inds.extend_from_slice(&[k1 + j + 1, k2 + j + 1, k2 + j]);
+            }
+        }
+    }
+    inds
+}
@@ -0,0 +1,27 @@
+use crate::{glm, VertBasic};
+
+/// An isosceles triangle in the xy plane, facing +z, that fits in a unit square.
+pub fn triangle_verts() -> Vec<VertBasic> {
+    let normal = glm::vec3(0.0, 0.0, 1.0);
+    vec![
+        VertBasic {
+            position: glm::vec3(-0.5, -0.5, 0.0),
+            normal,
+            tex_coords: glm::vec2(0.0, 0.0),
+        },
+        VertBasic {
+            position: glm::vec3(0.5, -0.5, 0.0),
+            normal,
+            tex_coords: glm::vec2(1.0, 0.0),
+        },
+        VertBasic {
+            position: glm::vec3(0.0, 0.5, 0.0),
+            normal,
+            tex_coords: glm::vec2(0.5, 1.0),
+        },
+    ]
+}
+
+pub fn triangle_inds() -> Vec<u32> {
+    vec![0, 1, 2]
+}
@@ -1,11 +1,26 @@
 pub mod camera;
+pub mod capture;
+pub mod framebuffer;
+pub mod frustum;
 pub mod g_buf;
+pub mod gpu_cull;
+pub mod light;
+pub mod material;
+pub mod mesh;
+pub mod model;
+pub mod post_process;
+#[allow(clippy::module_inception)]
 pub mod renderer;
 pub mod shader;
+pub mod shadow_map;
+pub mod ssao;
+pub mod tex_buf;
 pub mod texture;
+pub mod tone_map;
 pub mod transform;
 pub mod vert_array;
 pub mod vert_basic;
+pub mod vert_tangent;
 pub mod vert_trans;
 
-mod meshes;
+pub mod meshes;
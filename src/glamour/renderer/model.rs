@@ -0,0 +1,509 @@
+use crate::{glm, IndexBuf, VertBasic, VertBuf};
+use std::path::{Path, PathBuf};
+
+/// Where a material's texture comes from.
+#[derive(Debug, Clone)]
+pub enum TextureRef {
+    /// An image file, already resolved to a full path.
+    Path(PathBuf),
+    /// An image embedded in the model file, already decoded. Its origin is the top left.
+    Image(image::RgbaImage),
+}
+
+/// The parts of a model's material that the renderer can make use of.
+#[derive(Debug, Clone)]
+pub struct ModelMaterial {
+    pub name: String,
+    pub albedo_color: glm::Vec4,
+    pub albedo_texture: Option<TextureRef>,
+    pub specular_strength: f32,
+    pub specular_texture: Option<TextureRef>,
+    pub shininess: f32,
+    pub normal_texture: Option<TextureRef>,
+}
+
+impl Default for ModelMaterial {
+    fn default() -> Self {
+        ModelMaterial {
+            name: String::new(),
+            albedo_color: glm::vec4(1.0, 1.0, 1.0, 1.0),
+            albedo_texture: None,
+            specular_strength: 0.5,
+            specular_texture: None,
+            shininess: 256.0,
+            normal_texture: None,
+        }
+    }
+}
+
+/// Triangles with a single material, ready to be uploaded as a `VertBuf` and `IndexBuf`.
+#[derive(Debug, Clone)]
+pub struct ModelMesh {
+    pub name: String,
+    pub vertices: Vec<VertBasic>,
+    pub indices: Vec<u32>,
+    /// Index into the model's `materials`.
+    pub material: Option<usize>,
+}
+
+impl ModelMesh {
+    pub fn vert_buf(&self) -> VertBuf<VertBasic> {
+        VertBuf::new(self.vertices.clone())
+    }
+
+    pub fn index_buf(&self) -> IndexBuf {
+        IndexBuf::new(self.indices.clone())
+    }
+
+    /// Check that every index refers to one of the mesh's vertices.
+    pub fn validate(&self) -> Result<(), ModelError> {
+        let vertex_count = self.vertices.len();
+        match self.indices.iter().find(|&&i| i as usize >= vertex_count) {
+            Some(&index) => Err(ModelError::IndexOutOfRange {
+                index,
+                vertex_count,
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ModelError {
+    UnknownFormat(PathBuf),
+    Obj(tobj::LoadError),
+    Gltf(gltf::Error),
+    /// A face refers to a vertex the mesh doesn't have.
+    IndexOutOfRange {
+        index: u32,
+        vertex_count: usize,
+    },
+}
+
+impl std::fmt::Display for ModelError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ModelError::UnknownFormat(path) => {
+                write!(f, "unknown model format: {}", path.display())
+            }
+            ModelError::Obj(err) => write!(f, "failed to load OBJ: {}", err),
+            ModelError::Gltf(err) => write!(f, "failed to load glTF: {}", err),
+            ModelError::IndexOutOfRange {
+                index,
+                vertex_count,
+            } => write!(
+                f,
+                "index {} is out of range for a mesh with {} vertices",
+                index, vertex_count
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ModelError {}
+
+impl From<tobj::LoadError> for ModelError {
+    fn from(err: tobj::LoadError) -> Self {
+        ModelError::Obj(err)
+    }
+}
+
+impl From<gltf::Error> for ModelError {
+    fn from(err: gltf::Error) -> Self {
+        ModelError::Gltf(err)
+    }
+}
+
+/// Meshes and materials loaded from a model file.
+#[derive(Debug, Clone, Default)]
+pub struct Model {
+    pub meshes: Vec<ModelMesh>,
+    pub materials: Vec<ModelMaterial>,
+}
+
+impl Model {
+    /// Load an `.obj`, `.gltf` or `.glb` file, picked by its extension.
+    /// Relative paths are resolved against `assets_path()`.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Model, ModelError> {
+        let path = crate::resolve_asset_path(path);
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase());
+        match extension.as_deref() {
+            Some("obj") => Model::from_obj(&path),
+            Some("gltf") | Some("glb") => Model::from_gltf(&path),
+            _ => Err(ModelError::UnknownFormat(path)),
+        }
+    }
+
+    /// Load a Wavefront OBJ file and its MTL materials, faces are triangulated.
+    /// Relative paths are resolved against `assets_path()`.
+    pub fn from_obj<P: AsRef<Path>>(path: P) -> Result<Model, ModelError> {
+        let path = crate::resolve_asset_path(path);
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        let options = tobj::LoadOptions {
+            single_index: true,
+            triangulate: true,
+            ignore_points: true,
+            ignore_lines: true,
+        };
+        let (obj_models, obj_materials) = tobj::load_obj(&path, &options)?;
+        // a missing MTL file isn't worth failing over, the meshes just go without materials
+        let obj_materials = obj_materials.unwrap_or_default();
+
+        let texture = |name: &str| {
+            if name.is_empty() {
+                None
+            } else {
+                Some(TextureRef::Path(dir.join(name)))
+            }
+        };
+        let materials = obj_materials
+            .iter()
+            .map(|m| ModelMaterial {
+                name: m.name.clone(),
+                albedo_color: glm::vec4(m.diffuse[0], m.diffuse[1], m.diffuse[2], m.dissolve),
+                albedo_texture: texture(&m.diffuse_texture),
+                specular_strength: (m.specular[0] + m.specular[1] + m.specular[2]) / 3.0,
+                specular_texture: texture(&m.specular_texture),
+                shininess: m.shininess.max(1.0),
+                normal_texture: texture(&m.normal_texture),
+            })
+            .collect();
+
+        let meshes = obj_models
+            .into_iter()
+            .map(|obj_model| {
+                let mesh = obj_model.mesh;
+                let vertices = (0..mesh.positions.len() / 3)
+                    .map(|i| VertBasic {
+                        position: glm::make_vec3(&mesh.positions[i * 3..i * 3 + 3]),
+                        normal: mesh
+                            .normals
+                            .get(i * 3..i * 3 + 3)
+                            .map_or_else(|| glm::vec3(0.0, 0.0, 0.0), glm::make_vec3),
+                        tex_coords: mesh
+                            .texcoords
+                            .get(i * 2..i * 2 + 2)
+                            .map_or_else(|| glm::vec2(0.0, 0.0), glm::make_vec2),
+                    })
+                    .collect();
+                let mut model_mesh = ModelMesh {
+                    name: obj_model.name,
+                    vertices,
+                    indices: mesh.indices,
+                    material: mesh.material_id,
+                };
+                model_mesh.validate()?;
+                if mesh.normals.is_empty() {
+                    generate_normals(&mut model_mesh)?;
+                }
+                Ok(model_mesh)
+            })
+            .collect::<Result<_, ModelError>>()?;
+
+        Ok(Model { meshes, materials })
+    }
+
+    /// Load a glTF 2.0 file (`.gltf` with its buffers, or a binary `.glb`).
+    /// Relative paths are resolved against `assets_path()`.
+    ///
+    /// Every triangle primitive in the default scene becomes a mesh, with its node transforms
+    /// baked into the vertices.
+    pub fn from_gltf<P: AsRef<Path>>(path: P) -> Result<Model, ModelError> {
+        let path = crate::resolve_asset_path(path);
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        let (document, buffers, images) = gltf::import(&path)?;
+
+        let texture = |texture: gltf::Texture| {
+            let image = texture.source();
+            match image.source() {
+                gltf::image::Source::Uri { uri, .. } if !uri.starts_with("data:") => {
+                    Some(TextureRef::Path(dir.join(uri)))
+                }
+                _ => gltf_image_to_rgba(&images[image.index()]).map(TextureRef::Image),
+            }
+        };
+        let materials = document
+            .materials()
+            .map(|m| {
+                let pbr = m.pbr_metallic_roughness();
+                // a rough approximation of the roughness as a Blinn-Phong exponent
+                let alpha = pbr.roughness_factor().powi(2).max(0.01);
+                ModelMaterial {
+                    name: m.name().unwrap_or_default().to_string(),
+                    albedo_color: glm::make_vec4(&pbr.base_color_factor()),
+                    albedo_texture: pbr.base_color_texture().and_then(|t| texture(t.texture())),
+                    specular_strength: 1.0 - pbr.roughness_factor(),
+                    specular_texture: None,
+                    shininess: (2.0 / (alpha * alpha) - 2.0).clamp(1.0, 256.0),
+                    normal_texture: m.normal_texture().and_then(|t| texture(t.texture())),
+                }
+            })
+            .collect();
+
+        let mut meshes = Vec::new();
+        let scene = document
+            .default_scene()
+            .or_else(|| document.scenes().next());
+        let mut nodes: Vec<(gltf::Node, glm::Mat4)> = scene
+            .into_iter()
+            .flat_map(|scene| scene.nodes())
+            .map(|node| (node, glm::identity()))
+            .collect();
+        while let Some((node, parent)) = nodes.pop() {
+            let matrix = node.transform().matrix();
+            let transform = parent * glm::make_mat4(&matrix.concat());
+            if let Some(mesh) = node.mesh() {
+                for primitive in mesh.primitives() {
+                    if primitive.mode() != gltf::mesh::Mode::Triangles {
+                        continue;
+                    }
+                    if let Some(model_mesh) =
+                        gltf_primitive_to_mesh(&mesh, &primitive, &buffers, &transform)?
+                    {
+                        meshes.push(model_mesh);
+                    }
+                }
+            }
+            nodes.extend(node.children().map(|child| (child, transform)));
+        }
+
+        Ok(Model { meshes, materials })
+    }
+}
+
+fn gltf_primitive_to_mesh(
+    mesh: &gltf::Mesh,
+    primitive: &gltf::Primitive,
+    buffers: &[gltf::buffer::Data],
+    transform: &glm::Mat4,
+) -> Result<Option<ModelMesh>, ModelError> {
+    let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+    let normal_matrix = crate::Transform::normal_matrix(transform);
+    let positions = match reader.read_positions() {
+        Some(positions) => positions,
+        None => return Ok(None),
+    };
+    let mut vertices: Vec<VertBasic> = positions
+        .map(|p| VertBasic {
+            position: (transform * glm::vec4(p[0], p[1], p[2], 1.0)).xyz(),
+            normal: glm::vec3(0.0, 0.0, 0.0),
+            tex_coords: glm::vec2(0.0, 0.0),
+        })
+        .collect();
+    let has_normals = if let Some(normals) = reader.read_normals() {
+        for (v, n) in vertices.iter_mut().zip(normals) {
+            v.normal = glm::normalize(&(normal_matrix * glm::make_vec3(&n)));
+        }
+        true
+    } else {
+        false
+    };
+    if let Some(tex_coords) = reader.read_tex_coords(0) {
+        for (v, t) in vertices.iter_mut().zip(tex_coords.into_f32()) {
+            // glTF's origin is the top left, textures are flipped to the bottom left on load
+            v.tex_coords = glm::vec2(t[0], 1.0 - t[1]);
+        }
+    }
+    let indices = match reader.read_indices() {
+        Some(indices) => indices.into_u32().collect(),
+        None => (0..vertices.len() as u32).collect(),
+    };
+    let mut model_mesh = ModelMesh {
+        name: mesh.name().unwrap_or_default().to_string(),
+        vertices,
+        indices,
+        material: primitive.material().index(),
+    };
+    model_mesh.validate()?;
+    if !has_normals {
+        generate_normals(&mut model_mesh)?;
+    }
+    Ok(Some(model_mesh))
+}
+
+fn gltf_image_to_rgba(data: &gltf::image::Data) -> Option<image::RgbaImage> {
+    use gltf::image::Format;
+    let channels = match data.format {
+        Format::R8 => 1,
+        Format::R8G8 => 2,
+        Format::R8G8B8 => 3,
+        Format::R8G8B8A8 => 4,
+        // 16 bit and float images aren't supported
+        _ => return None,
+    };
+    let pixels = data
+        .pixels
+        .chunks_exact(channels)
+        .flat_map(|p| match channels {
+            1 => [p[0], p[0], p[0], 255],
+            2 => [p[0], p[1], 0, 255],
+            3 => [p[0], p[1], p[2], 255],
+            _ => [p[0], p[1], p[2], p[3]],
+        })
+        .collect();
+    image::RgbaImage::from_raw(data.width, data.height, pixels)
+}
+
+/// Smooth normals, averaged from the faces around each vertex and weighted by their area.
+fn generate_normals(mesh: &mut ModelMesh) -> Result<(), ModelError> {
+    mesh.validate()?;
+    for v in mesh.vertices.iter_mut() {
+        v.normal = glm::vec3(0.0, 0.0, 0.0);
+    }
+    for face in mesh.indices.chunks_exact(3) {
+        let (a, b, c) = (face[0] as usize, face[1] as usize, face[2] as usize);
+        let p = |i: usize| mesh.vertices[i].position;
+        // not normalized, so larger faces contribute more
+        let normal = glm::cross(&(p(b) - p(a)), &(p(c) - p(a)));
+        for &i in &[a, b, c] {
+            mesh.vertices[i].normal += normal;
+        }
+    }
+    for v in mesh.vertices.iter_mut() {
+        if glm::length(&v.normal) > 0.0 {
+            v.normal = glm::normalize(&v.normal);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("src/assets/models")
+            .join(name)
+    }
+
+    fn texture_path(texture: &Option<TextureRef>) -> Option<&Path> {
+        match texture {
+            Some(TextureRef::Path(path)) => Some(path),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn obj_quad_is_triangulated_with_its_material() {
+        let model = Model::load(fixture("quad.obj")).unwrap();
+        assert_eq!(model.meshes.len(), 1);
+        let mesh = &model.meshes[0];
+        assert_eq!(mesh.name, "quad");
+        assert_eq!(mesh.vertices.len(), 4);
+        assert_eq!(mesh.indices.len(), 6);
+        assert_eq!(mesh.material, Some(0));
+        for v in &mesh.vertices {
+            assert_eq!(v.normal, glm::vec3(0.0, 0.0, 1.0));
+        }
+
+        let material = &model.materials[0];
+        assert_eq!(material.name, "crate");
+        assert_eq!(material.albedo_color, glm::vec4(1.0, 0.5, 0.25, 1.0));
+        assert_eq!(material.shininess, 32.0);
+        assert!((material.specular_strength - 0.3).abs() < 1e-6);
+        // textures are resolved against the model's directory
+        assert_eq!(
+            texture_path(&material.albedo_texture),
+            Some(fixture("../container.jpg").as_path())
+        );
+        assert_eq!(
+            texture_path(&material.specular_texture),
+            Some(fixture("specular.png").as_path())
+        );
+        assert!(material.normal_texture.is_none());
+    }
+
+    #[test]
+    fn obj_without_normals_generates_them() {
+        let model = Model::from_obj(fixture("tetrahedron.obj")).unwrap();
+        assert!(model.materials.is_empty());
+        let mesh = &model.meshes[0];
+        assert_eq!(mesh.vertices.len(), 4);
+        assert_eq!(mesh.indices.len(), 12);
+        assert_eq!(mesh.material, None);
+        for v in &mesh.vertices {
+            assert!((glm::length(&v.normal) - 1.0).abs() < 1e-5);
+        }
+        // the corner at the origin is shared by three axis aligned faces
+        let corner = mesh
+            .vertices
+            .iter()
+            .find(|v| v.position == glm::vec3(0.0, 0.0, 0.0))
+            .unwrap();
+        let expected = glm::normalize(&glm::vec3(-1.0, -1.0, -1.0));
+        assert!(glm::length(&(corner.normal - expected)) < 1e-5);
+    }
+
+    #[test]
+    fn gltf_quad_bakes_node_transforms() {
+        let model = Model::load(fixture("quad.gltf")).unwrap();
+        assert_eq!(model.meshes.len(), 1);
+        let mesh = &model.meshes[0];
+        assert_eq!(mesh.vertices.len(), 4);
+        assert_eq!(mesh.indices, vec![0, 1, 2, 0, 2, 3]);
+        assert_eq!(mesh.material, Some(0));
+        // scaled by 2 and moved up by 2
+        assert_eq!(mesh.vertices[0].position, glm::vec3(-1.0, 1.0, 0.0));
+        assert_eq!(mesh.vertices[2].position, glm::vec3(1.0, 3.0, 0.0));
+        assert_eq!(mesh.vertices[0].normal, glm::vec3(0.0, 0.0, 1.0));
+        // flipped from glTF's top left origin
+        assert_eq!(mesh.vertices[0].tex_coords, glm::vec2(0.0, 0.0));
+
+        let material = &model.materials[0];
+        assert_eq!(material.name, "crate");
+        assert_eq!(material.albedo_color, glm::vec4(1.0, 0.5, 0.25, 1.0));
+        assert_eq!(
+            texture_path(&material.albedo_texture),
+            Some(fixture("../container.jpg").as_path())
+        );
+    }
+
+    #[test]
+    fn relative_paths_resolve_against_assets_path() {
+        match Model::load("models/quad.fbx") {
+            Err(ModelError::UnknownFormat(path)) => {
+                assert_eq!(path, crate::assets_path().join("models/quad.fbx"))
+            }
+            other => panic!("expected an unknown format, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn missing_files_are_errors() {
+        assert!(matches!(
+            Model::load(fixture("missing.obj")),
+            Err(ModelError::Obj(_))
+        ));
+        assert!(matches!(
+            Model::load(fixture("missing.gltf")),
+            Err(ModelError::Gltf(_))
+        ));
+    }
+
+    #[test]
+    fn out_of_range_indices_are_errors() {
+        let mut mesh = ModelMesh {
+            name: String::new(),
+            vertices: vec![
+                VertBasic::from_pos(0.0, 0.0, 0.0),
+                VertBasic::from_pos(1.0, 0.0, 0.0),
+                VertBasic::from_pos(0.0, 1.0, 0.0),
+            ],
+            indices: vec![0, 1, 3],
+            material: None,
+        };
+        assert!(matches!(
+            generate_normals(&mut mesh),
+            Err(ModelError::IndexOutOfRange {
+                index: 3,
+                vertex_count: 3
+            })
+        ));
+        mesh.indices = vec![0, 1, 2];
+        assert!(generate_normals(&mut mesh).is_ok());
+    }
+}
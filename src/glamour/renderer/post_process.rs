@@ -0,0 +1,446 @@
+use crate::{
+    glm, Framebuffer, FramebufferBuilder, ShaderBuilder, ShaderError, ShaderProgram, TextureFilter,
+    TextureFormat, VertArray,
+};
+
+/// Handle to an effect in a `PostProcess` chain, built in or added with `add_effect`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct PostEffectHandle(pub(crate) usize);
+
+/// Adds a blurred copy of the brightest parts of the image on top of it. It's taken from the
+/// HDR colours, before exposure and tone mapping, and added to them.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Bloom {
+    /// Channels brighter than this bloom, where 1 is a fully lit white surface.
+    pub threshold: f32,
+    pub intensity: f32,
+    /// Horizontal and vertical blur passes at half resolution, more is a wider glow.
+    pub blur_passes: u32,
+}
+
+impl Default for Bloom {
+    fn default() -> Self {
+        Bloom {
+            threshold: 1.0,
+            intensity: 1.0,
+            blur_passes: 4,
+        }
+    }
+}
+
+/// Darkens the edges of the screen.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Vignette {
+    /// How dark the corners get, from 0 to 1.
+    pub intensity: f32,
+    /// Distance from the centre where the darkening ends, 1 is the corners.
+    pub radius: f32,
+    /// Distance over which it fades in, inwards from `radius`.
+    pub softness: f32,
+}
+
+impl Default for Vignette {
+    fn default() -> Self {
+        Vignette {
+            intensity: 0.8,
+            radius: 1.1,
+            softness: 0.7,
+        }
+    }
+}
+
+/// Simple colour adjustments, applied in that order. The defaults change nothing.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ColorGrading {
+    /// Added to every channel.
+    pub brightness: f32,
+    /// Scales every channel around mid grey.
+    pub contrast: f32,
+    /// 0 is greyscale, above 1 is more saturated.
+    pub saturation: f32,
+    /// Multiplied with every colour.
+    pub tint: glm::Vec3,
+}
+
+impl Default for ColorGrading {
+    fn default() -> Self {
+        ColorGrading {
+            brightness: 0.0,
+            contrast: 1.0,
+            saturation: 1.0,
+            tint: glm::vec3(1.0, 1.0, 1.0),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum PostEffectKind {
+    Bloom,
+    Fxaa,
+    Vignette,
+    ColorGrading,
+    Custom,
+}
+
+struct PostEffect {
+    name: String,
+    kind: PostEffectKind,
+    shader: ShaderProgram,
+    enabled: bool,
+}
+
+// texture unit in the effect shaders
+const SCREEN_UNIT: u32 = 0;
+
+/// An ordered chain of full-screen effects, drawn by the renderer after tone mapping, with each
+/// effect reading the output of the one before it.
+///
+/// Bloom, colour grading, vignette and FXAA are built in, in that order and disabled. Bloom
+/// isn't part of the chain: it's drawn from the HDR target and added in before tone mapping,
+/// wherever it is in the order.
+/// Custom effects are fragment shaders like this, with `u_texel_size` the size of a pixel in
+/// texture coordinates:
+///
+/// ```glsl
+/// #version 410 core
+///
+/// in vec2 tex_coords;
+///
+/// uniform sampler2D u_screen;
+/// uniform vec2 u_texel_size;
+///
+/// out vec4 out_color;
+///
+/// void main() {
+///   out_color = vec4(1.0 - texture(u_screen, tex_coords).rgb, 1.0);
+/// }
+/// ```
+pub struct PostProcess {
+    effects: Vec<PostEffect>,
+    order: Vec<PostEffectHandle>,
+    // effects ping-pong between these, the last one draws to the default framebuffer
+    targets: [Framebuffer; 2],
+    // half resolution and floating-point, for blurring HDR colours
+    bloom_targets: [Framebuffer; 2],
+    bloom_blur: ShaderProgram,
+    bloom: Bloom,
+    vignette: Vignette,
+    color_grading: ColorGrading,
+}
+
+impl PostProcess {
+    pub fn new(width: u32, height: u32) -> Self {
+        let target = || {
+            FramebufferBuilder::new()
+                .with_color(TextureFormat::Rgba8)
+                .with_filter(TextureFilter::Linear)
+        };
+        let (bloom_width, bloom_height) = PostProcess::bloom_size(width, height);
+        let targets = [
+            target()
+                .build(width, height)
+                .expect("failed to create post-processing target"),
+            target()
+                .build(width, height)
+                .expect("failed to create post-processing target"),
+        ];
+        let bloom_target = || {
+            FramebufferBuilder::new()
+                .with_color(TextureFormat::Rgba16F)
+                .with_filter(TextureFilter::Linear)
+                .build(bloom_width, bloom_height)
+                .expect("failed to create bloom target")
+        };
+        let bloom_targets = [bloom_target(), bloom_target()];
+
+        let bloom_blur = PostProcess::build_shader(include_str!("shaders/blur.frag"))
+            .expect("failed to build blur shader");
+        let mut post_process = PostProcess {
+            effects: Vec::new(),
+            order: Vec::new(),
+            targets,
+            bloom_targets,
+            bloom_blur,
+            bloom: Bloom::default(),
+            vignette: Vignette::default(),
+            color_grading: ColorGrading::default(),
+        };
+        // built-in effects, always the first handles
+        let built_ins = [
+            // its shader extracts the bright parts of the HDR target
+            (
+                "bloom",
+                PostEffectKind::Bloom,
+                include_str!("shaders/bloom_bright.frag"),
+            ),
+            (
+                "colour grading",
+                PostEffectKind::ColorGrading,
+                include_str!("shaders/color_grading.frag"),
+            ),
+            (
+                "vignette",
+                PostEffectKind::Vignette,
+                include_str!("shaders/vignette.frag"),
+            ),
+            (
+                "FXAA",
+                PostEffectKind::Fxaa,
+                include_str!("shaders/fxaa.frag"),
+            ),
+        ];
+        for &(name, kind, frag_src) in &built_ins {
+            post_process
+                .push_effect(name, kind, frag_src, false)
+                .expect("failed to build post-processing shader");
+        }
+        post_process
+    }
+
+    /// Add an effect to the end of the chain, enabled. Nothing is added if its shader fails to
+    /// compile or link.
+    pub fn add_effect(
+        &mut self,
+        name: &str,
+        frag_src: &str,
+    ) -> Result<PostEffectHandle, ShaderError> {
+        self.push_effect(name, PostEffectKind::Custom, frag_src, true)
+    }
+
+    pub fn bloom_effect(&self) -> PostEffectHandle {
+        PostEffectHandle(0)
+    }
+
+    pub fn color_grading_effect(&self) -> PostEffectHandle {
+        PostEffectHandle(1)
+    }
+
+    pub fn vignette_effect(&self) -> PostEffectHandle {
+        PostEffectHandle(2)
+    }
+
+    pub fn fxaa_effect(&self) -> PostEffectHandle {
+        PostEffectHandle(3)
+    }
+
+    pub fn effect_name(&self, effect: PostEffectHandle) -> &str {
+        &self.effects[effect.0].name
+    }
+
+    /// The effect's shader, e.g. to set a custom effect's own uniforms.
+    pub fn effect_shader(&self, effect: PostEffectHandle) -> &ShaderProgram {
+        &self.effects[effect.0].shader
+    }
+
+    pub fn enabled(&self, effect: PostEffectHandle) -> bool {
+        self.effects[effect.0].enabled
+    }
+
+    pub fn set_enabled(&mut self, effect: PostEffectHandle, val: bool) {
+        self.effects[effect.0].enabled = val;
+    }
+
+    /// Every effect, in the order they're applied.
+    pub fn order(&self) -> &[PostEffectHandle] {
+        &self.order
+    }
+
+    /// Reorder the chain.
+    ///
+    /// # Panics
+    /// If `order` doesn't have every effect exactly once.
+    pub fn set_order(&mut self, order: &[PostEffectHandle]) {
+        let mut sorted = order.to_vec();
+        sorted.sort_by_key(|effect| effect.0);
+        assert!(
+            sorted
+                .iter()
+                .map(|effect| effect.0)
+                .eq(0..self.effects.len()),
+            "order must have every effect exactly once"
+        );
+        self.order = order.to_vec();
+    }
+
+    pub fn bloom(&self) -> &Bloom {
+        &self.bloom
+    }
+
+    pub fn bloom_mut(&mut self) -> &mut Bloom {
+        &mut self.bloom
+    }
+
+    pub fn vignette(&self) -> &Vignette {
+        &self.vignette
+    }
+
+    pub fn vignette_mut(&mut self) -> &mut Vignette {
+        &mut self.vignette
+    }
+
+    pub fn color_grading(&self) -> &ColorGrading {
+        &self.color_grading
+    }
+
+    pub fn color_grading_mut(&mut self) -> &mut ColorGrading {
+        &mut self.color_grading
+    }
+
+    /// Whether any effect is enabled, otherwise the chain is skipped.
+    pub fn is_active(&self) -> bool {
+        self.effects.iter().any(|effect| effect.enabled)
+    }
+
+    /// Whether any effect after tone mapping is enabled, that is anything but bloom.
+    pub(crate) fn chain_active(&self) -> bool {
+        self.effects
+            .iter()
+            .any(|effect| effect.enabled && effect.kind != PostEffectKind::Bloom)
+    }
+
+    /// Blurred bright parts of the HDR target, drawn by `draw_bloom`.
+    pub(crate) fn bloom_target(&self) -> &Framebuffer {
+        &self.bloom_targets[0]
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32) {
+        let (bloom_width, bloom_height) = PostProcess::bloom_size(width, height);
+        for target in &mut self.targets {
+            target.resize(width, height);
+        }
+        for target in &mut self.bloom_targets {
+            target.resize(bloom_width, bloom_height);
+        }
+    }
+
+    /// Where the image to post-process should be drawn.
+    pub(crate) fn input(&self) -> &Framebuffer {
+        &self.targets[0]
+    }
+
+    /// Run every enabled effect but bloom on `input`, drawing the result to the default
+    /// framebuffer. `quad_vao` is a full-screen quad, drawn as a triangle strip.
+    pub(crate) fn apply(&self, quad_vao: &VertArray) {
+        let enabled: Vec<&PostEffect> = self
+            .order
+            .iter()
+            .map(|effect| &self.effects[effect.0])
+            .filter(|effect| effect.enabled && effect.kind != PostEffectKind::Bloom)
+            .collect();
+        let (width, height) = (self.targets[0].width(), self.targets[0].height());
+        let texel_size = glm::vec2(1.0 / width as f32, 1.0 / height as f32);
+
+        gl_call!(gl::Disable(gl::DEPTH_TEST));
+        quad_vao.bind();
+        for (i, effect) in enabled.iter().enumerate() {
+            let source = &self.targets[i % 2];
+            if i + 1 == enabled.len() {
+                source.unbind();
+            } else {
+                self.targets[(i + 1) % 2].bind();
+            }
+            self.set_uniforms(effect, &texel_size);
+            effect.shader.bind();
+            source.bind_colors(SCREEN_UNIT);
+            gl_call!(gl::DrawArrays(gl::TRIANGLE_STRIP, 0, 4));
+        }
+        quad_vao.unbind();
+        self.targets[0].unbind_colors(SCREEN_UNIT);
+        gl_call!(gl::UseProgram(0));
+        gl_call!(gl::Enable(gl::DEPTH_TEST));
+    }
+
+    fn set_uniforms(&self, effect: &PostEffect, texel_size: &glm::Vec2) {
+        let shader = &effect.shader;
+        shader.set_float2("u_texel_size", texel_size);
+        match effect.kind {
+            PostEffectKind::Vignette => {
+                shader.set_float("u_intensity", self.vignette.intensity);
+                shader.set_float("u_radius", self.vignette.radius);
+                shader.set_float("u_softness", self.vignette.softness);
+            }
+            PostEffectKind::ColorGrading => {
+                shader.set_float("u_brightness", self.color_grading.brightness);
+                shader.set_float("u_contrast", self.color_grading.contrast);
+                shader.set_float("u_saturation", self.color_grading.saturation);
+                shader.set_float3("u_tint", &self.color_grading.tint);
+            }
+            PostEffectKind::Bloom | PostEffectKind::Fxaa | PostEffectKind::Custom => {}
+        }
+    }
+
+    /// If bloom is enabled, extract the bright parts of the HDR colours in `hdr` and blur them
+    /// into `bloom_target`, returning the intensity to add them with, otherwise `0`.
+    pub(crate) fn draw_bloom(&self, hdr: &Framebuffer, quad_vao: &VertArray) -> f32 {
+        let effect = &self.effects[self.bloom_effect().0];
+        if !effect.enabled {
+            return 0.0;
+        }
+        let [bloom_a, bloom_b] = &self.bloom_targets;
+        gl_call!(gl::Disable(gl::DEPTH_TEST));
+        gl_call!(gl::Viewport(
+            0,
+            0,
+            bloom_a.width() as i32,
+            bloom_a.height() as i32
+        ));
+        quad_vao.bind();
+        effect.shader.set_float("u_threshold", self.bloom.threshold);
+        bloom_a.bind();
+        effect.shader.bind();
+        hdr.bind_colors(SCREEN_UNIT);
+        gl_call!(gl::DrawArrays(gl::TRIANGLE_STRIP, 0, 4));
+        hdr.unbind_colors(SCREEN_UNIT);
+
+        let horizontal = glm::vec2(1.0 / bloom_a.width() as f32, 0.0);
+        let vertical = glm::vec2(0.0, 1.0 / bloom_a.height() as f32);
+        for _ in 0..self.bloom.blur_passes {
+            for &(from, to, direction) in &[
+                (bloom_a, bloom_b, &horizontal),
+                (bloom_b, bloom_a, &vertical),
+            ] {
+                self.bloom_blur.set_float2("u_direction", direction);
+                to.bind();
+                self.bloom_blur.bind();
+                from.bind_colors(SCREEN_UNIT);
+                gl_call!(gl::DrawArrays(gl::TRIANGLE_STRIP, 0, 4));
+            }
+        }
+        bloom_a.unbind_colors(SCREEN_UNIT);
+        bloom_a.unbind();
+        quad_vao.unbind();
+        gl_call!(gl::UseProgram(0));
+        gl_call!(gl::Viewport(0, 0, hdr.width() as i32, hdr.height() as i32));
+        gl_call!(gl::Enable(gl::DEPTH_TEST));
+        self.bloom.intensity
+    }
+
+    fn push_effect(
+        &mut self,
+        name: &str,
+        kind: PostEffectKind,
+        frag_src: &str,
+        enabled: bool,
+    ) -> Result<PostEffectHandle, ShaderError> {
+        self.effects.push(PostEffect {
+            name: name.to_string(),
+            kind,
+            shader: PostProcess::build_shader(frag_src)?,
+            enabled,
+        });
+        let handle = PostEffectHandle(self.effects.len() - 1);
+        self.order.push(handle);
+        Ok(handle)
+    }
+
+    fn build_shader(frag_src: &str) -> Result<ShaderProgram, ShaderError> {
+        let shader =
+            ShaderBuilder::new(include_str!("shaders/screen.vert"), frag_src).try_build()?;
+        shader.set_int("u_screen", SCREEN_UNIT as i32);
+        Ok(shader)
+    }
+
+    fn bloom_size(width: u32, height: u32) -> (u32, u32) {
+        ((width / 2).max(1), (height / 2).max(1))
+    }
+}
@@ -1,18 +1,36 @@
 use super::meshes;
 use crate::{
-    glm, Camera, GBuf, IndexBuf, ShaderBuilder, ShaderProgram, Texture, Transform, VertArray,
-    VertBasic, VertBuf, VertTrans,
+    glm, gpu_culling_supported, light_direction, Camera, CubeMap, CubeShadowMaps, CullStats,
+    DepthFormat, DirectionalLight, DirectionalLightTexels, Framebuffer, FramebufferBuilder,
+    FramebufferError, Frustum, GBuf, GBufAttachment, GBufLayout, GpuCull, IndexBuf, Material,
+    MaterialHandle, Mesh, MeshHandle, Model, PointLight, PointLightTexels, PostProcess,
+    ShaderBuilder, ShaderProgram, ShadowMap, SpotLight, SpotLightTexels, Ssao, StreamMode, TexBuf,
+    Texture, TextureArray, TextureBuilder, TextureError, TextureFormat, ToneMap, Transform,
+    VertArray, VertBasic, VertBuf, VertTangent, VertTrans,
 };
 use gl;
 use rayon::prelude::*;
+use std::rc::Rc;
 
+/// Draws instanced meshes and lights with either a forward or a deferred path, into an HDR
+/// target that's tone mapped, post-processed and presented at the end of `end_draw`.
+///
+/// Multisampling, see `set_msaa_samples`, only applies to the forward path. The deferred path's
+/// G-buffer has one sample per pixel, so its edges are as aliased as without MSAA, since
+/// multisampling it would mean lighting every sample of every G-buffer texel. To compare the two
+/// paths fairly, turn MSAA off, or give both the same screen-space anti-aliasing by enabling
+/// `PostProcess::fxaa_effect()`.
 #[allow(dead_code)]
 pub struct Renderer {
     cube_shader: ShaderProgram,
-    cube_vao: VertArray,
-    cube_vbo: VertBuf<VertBasic>,
-    cube_trans_vbo: VertBuf<VertTrans>,
-    cube_tex: Texture,
+    // bound in place of a material's missing textures
+    white_tex: Texture,
+    white_tex_array: TextureArray,
+    materials: Vec<Material>,
+    meshes: Vec<Mesh>,
+    cube_mesh: MeshHandle,
+    quad_mesh: MeshHandle,
+    triangle_mesh: MeshHandle,
     light_shader: ShaderProgram,
     light_vao: VertArray,
     light_vbo: VertBuf<VertBasic>,
@@ -22,26 +40,151 @@ pub struct Renderer {
     lit_def_light: ShaderProgram,
     ndc_quad_vbo: VertBuf<VertBasic>,
     ndc_quad_vao: VertArray,
+    point_lights_buf: TexBuf<PointLightTexels>,
+    spot_lights_buf: TexBuf<SpotLightTexels>,
+    directional_lights_buf: TexBuf<DirectionalLightTexels>,
+    light_volume_vbo: VertBuf<VertBasic>,
+    light_volume_vao: VertArray,
+    lit_def_volume: ShaderProgram,
+    lit_def_volume_stencil: ShaderProgram,
+    shadow_depth: ShaderProgram,
+    shadow_cube_depth: ShaderProgram,
+    shadow_map: ShadowMap,
+    cube_shadow_maps: CubeShadowMaps,
+    // direction of the shadow casting directional light, if there is one
+    directional_shadow: Option<glm::Vec3>,
+    directional_shadow_matrix: glm::Mat4,
+    // (position, radius) of each shadow casting point light, in cube map order
+    point_shadows: Vec<(glm::Vec3, f32)>,
+    shadow_bias: f32,
+    shadow_pcf: u32,
     deferred: bool,
+    light_volumes: bool,
+    light_volume_stencil: bool,
+    // lighting is rendered into the float target, then tone mapped onto the screen
+    hdr: bool,
+    hdr_target: Framebuffer,
+    tone_map_shader: ShaderProgram,
+    tone_map: ToneMap,
+    exposure: f32,
+    srgb_output: bool,
+    post_process: PostProcess,
+    ssao: Ssao,
+    use_ssao: bool,
+    msaa_samples: u32,
+    // the forward path's multisampled target and its colour format, built on first use
+    msaa_target: Option<Framebuffer>,
+    frustum_culling: bool,
+    // the camera's frustum as of `begin_draw`
+    frustum: Option<Frustum>,
+    cull_stats: CullStats,
+    // instances queued up since `begin_draw` to be culled on the GPU, which only counts them
+    // once they're drawn
+    gpu_cull_submitted: usize,
+    // `None` if the context is older than GL 4.3
+    gpu_cull: Option<GpuCull>,
+    gpu_culling: bool,
+    stream_mode: StreamMode,
+    // the camera's position as of `begin_draw`, to sort transparent instances by
+    view_pos: glm::Vec3,
+    transparent_instances: Vec<TransparentInstance>,
+    environment_map: Option<Rc<CubeMap>>,
+    skybox_shader: ShaderProgram,
+    skybox: bool,
 }
 
+// an instance with a transparent material, queued up to be sorted and drawn after everything
+// else
+struct TransparentInstance {
+    mesh: MeshHandle,
+    material: MaterialHandle,
+    instance: VertTrans,
+    // squared distance from the camera
+    distance: f32,
+}
+
+// texture units for the material textures, in the forward and deferred geometry shaders
+const ALBEDO_UNIT: u32 = 0;
+const SPECULAR_UNIT: u32 = 1;
+const ALBEDO_ARRAY_UNIT: u32 = 2;
+const NORMAL_UNIT: u32 = 3;
+
+// texture units for the light buffer textures, after any other textures in each shader.
+// the lit shaders take five consecutive units: point, spot, and directional lights,
+// then the directional and point shadow maps.
+const UNLIT_FOR_POINT_LIGHTS_UNIT: u32 = 0;
+const LIT_FOR_LIGHTS_UNIT: u32 = 4;
+const LIT_DEF_LIGHTS_UNIT: u32 = 5;
+// texture unit for the ambient occlusion in the deferred lighting shaders, after the G-buffer's
+const LIT_DEF_SSAO_UNIT: u32 = 4;
+// texture units for the environment map, after the lights in the lit shaders
+const LIT_FOR_ENVIRONMENT_UNIT: u32 = 9;
+const LIT_DEF_ENVIRONMENT_UNIT: u32 = 10;
+const SKYBOX_ENVIRONMENT_UNIT: u32 = 0;
+
+// texture units for the HDR colour and its bloom in the tone mapping shader
+const TONE_MAP_HDR_UNIT: u32 = 0;
+const TONE_MAP_BLOOM_UNIT: u32 = 1;
+
+// instance capacity of the built-in quad and triangle meshes
+const BUILT_IN_MESH_MAX_INSTANCES: usize = 10_000;
+
+// matches the `LIGHT_TYPE_*` defines in the light volume shaders
+const LIGHT_TYPE_POINT: i32 = 0;
+const LIGHT_TYPE_SPOT: i32 = 1;
+
 impl Renderer {
+    /// `max_cubes` and `max_lights` size the instance and light buffers up front, drawing more
+    /// than that in a frame grows them.
     pub fn new(resolution: (u32, u32), max_cubes: usize, max_lights: usize) -> Self {
         gl_call!(gl::Enable(gl::DEPTH_TEST));
+        // filter across cube map faces, or their edges show up in the skybox and reflections
+        gl_call!(gl::Enable(gl::TEXTURE_CUBE_MAP_SEAMLESS));
 
         let cube_shader = ShaderBuilder::new(
             include_str!("shaders/lit_for.vert"),
             include_str!("shaders/lit_for.frag"),
         )
-        .with_float4("u_color", glm::vec4(1.0, 1.0, 1.0, 1.0))
+        .with_frag_include(include_str!("shaders/lighting.glsl"))
         .build();
+        Renderer::set_material_units(&cube_shader);
+        Renderer::set_light_units(&cube_shader, LIT_FOR_LIGHTS_UNIT);
+        cube_shader.set_int("u_environment", LIT_FOR_ENVIRONMENT_UNIT as i32);
+
+        let white_tex = TextureBuilder::new()
+            .build_from_bytes(1, 1, &[255, 255, 255, 255])
+            .expect("failed to create white texture");
+        let white_tex_array = TextureBuilder::new()
+            .build_array_from_bytes(1, 1, 1, &[255, 255, 255, 255])
+            .expect("failed to create white texture array");
+        // the default material, always the first handle
         let img_path = crate::assets_path().join("tile_bookcaseFull.png");
-        let cube_tex = Texture::new(&img_path);
-        // TODO: check in draw functions if overflowing buffer, if so, draw (flush and reset).
-        let cube_vbo = VertBuf::<VertBasic>::new(meshes::cube::tex_cube_verts());
-        let cube_trans_vbo = VertBuf::<VertTrans>::new(Vec::with_capacity(max_cubes));
-        let ibo = IndexBuf::new(meshes::cube::tex_cube_inds());
-        let cube_vao = VertArray::new(&[&cube_vbo, &cube_trans_vbo], ibo);
+        let materials = vec![Material::from_texture(Rc::new(
+            Texture::new(&img_path).expect("failed to load default texture"),
+        ))];
+
+        // built-in meshes, always the first handles
+        let meshes = vec![
+            Mesh::with_tangents(
+                VertBuf::new(meshes::cube::tex_cube_verts()),
+                VertBuf::new(meshes::cube::tex_cube_tangents()),
+                IndexBuf::new(meshes::cube::tex_cube_inds()),
+                max_cubes,
+                MaterialHandle(0),
+            ),
+            Mesh::new(
+                VertBuf::new(meshes::quad::quad_verts()),
+                IndexBuf::new(meshes::quad::quad_inds()),
+                BUILT_IN_MESH_MAX_INSTANCES,
+                MaterialHandle(0),
+            ),
+            Mesh::new(
+                VertBuf::new(meshes::triangle::triangle_verts()),
+                IndexBuf::new(meshes::triangle::triangle_inds()),
+                BUILT_IN_MESH_MAX_INSTANCES,
+                MaterialHandle(0),
+            ),
+        ];
 
         let light_shader = ShaderBuilder::new(
             include_str!("shaders/unlit_for.vert"),
@@ -49,47 +192,158 @@ impl Renderer {
         )
         .with_float4("u_color", glm::vec4(1.0, 1.0, 1.0, 1.0))
         .build();
-        // TODO: check in draw functions if overflowing buffer, if so, draw (flush and reset).
+        light_shader.set_int("u_point_lights", UNLIT_FOR_POINT_LIGHTS_UNIT as i32);
         let light_vbo = VertBuf::<VertBasic>::new(meshes::cube::tex_cube_verts());
         let light_trans_vbo = VertBuf::<VertTrans>::new(Vec::with_capacity(max_lights));
         let ibo = IndexBuf::new(meshes::cube::tex_cube_inds());
         let light_vao = VertArray::new(&[&light_vbo, &light_trans_vbo], ibo);
 
+        let g_buf = GBuf::new(resolution.0, resolution.1, GBufLayout::default())
+            .expect("failed to create G-buffer");
         let lit_def_geo = ShaderBuilder::new(
             include_str!("shaders/lit_def_geo.vert"),
             include_str!("shaders/lit_def_geo.frag"),
         )
-        .with_float4("u_color", glm::vec4(1.0, 1.0, 1.0, 1.0))
+        .with_frag_include(include_str!("shaders/g_buf.glsl"))
         .build();
+        Renderer::set_material_units(&lit_def_geo);
+        g_buf.set_layout_uniform(&lit_def_geo);
 
         let lit_def_light = ShaderBuilder::new(
-            include_str!("shaders/lit_def_light.vert"),
+            include_str!("shaders/screen.vert"),
             include_str!("shaders/lit_def_light.frag"),
         )
+        .with_frag_include(include_str!("shaders/g_buf.glsl"))
+        .with_frag_include(include_str!("shaders/lighting.glsl"))
         .build();
-        lit_def_light.set_int("u_tex_pos", 0);
-        lit_def_light.set_int("u_tex_norm", 1);
-        lit_def_light.set_int("u_tex_alb_spec", 2);
+        g_buf.set_shader_uniforms(&lit_def_light);
+        lit_def_light.set_int("u_ssao", LIT_DEF_SSAO_UNIT as i32);
+        Renderer::set_light_units(&lit_def_light, LIT_DEF_LIGHTS_UNIT);
+        lit_def_light.set_int("u_environment", LIT_DEF_ENVIRONMENT_UNIT as i32);
 
         let ndc_quad_vbo = VertBuf::new(meshes::quad::ndc_quad_verts());
         let ndc_quad_vao = VertArray::new(&[&ndc_quad_vbo], IndexBuf::new(vec![]));
 
+        // one instance per light, positioned and scaled by the light buffer
+        let light_volume_vbo = VertBuf::new(meshes::sphere::sphere_verts(16, 12));
+        let light_volume_vao = VertArray::new(
+            &[&light_volume_vbo],
+            IndexBuf::new(meshes::sphere::sphere_inds(16, 12)),
+        );
+        let lit_def_volume = ShaderBuilder::new(
+            include_str!("shaders/lit_def_volume.vert"),
+            include_str!("shaders/lit_def_volume.frag"),
+        )
+        .with_frag_include(include_str!("shaders/g_buf.glsl"))
+        .with_frag_include(include_str!("shaders/lighting.glsl"))
+        .build();
+        g_buf.set_shader_uniforms(&lit_def_volume);
+        lit_def_volume.set_int("u_ssao", LIT_DEF_SSAO_UNIT as i32);
+        Renderer::set_light_units(&lit_def_volume, LIT_DEF_LIGHTS_UNIT);
+        lit_def_volume.set_int("u_environment", LIT_DEF_ENVIRONMENT_UNIT as i32);
+        let lit_def_volume_stencil = ShaderBuilder::new(
+            include_str!("shaders/lit_def_volume.vert"),
+            include_str!("shaders/lit_def_volume_stencil.frag"),
+        )
+        .build();
+        Renderer::set_light_units(&lit_def_volume_stencil, LIT_DEF_LIGHTS_UNIT);
+
+        let hdr_target = FramebufferBuilder::new()
+            .with_color(TextureFormat::Rgba16F)
+            // same format as the G-buffer's, so its depth can be blitted across
+            .with_depth(DepthFormat::Depth24Stencil8)
+            .build(resolution.0, resolution.1)
+            .expect("failed to create HDR target");
+        let tone_map_shader = ShaderBuilder::new(
+            include_str!("shaders/screen.vert"),
+            include_str!("shaders/tone_map.frag"),
+        )
+        .build();
+        tone_map_shader.set_int("u_hdr", TONE_MAP_HDR_UNIT as i32);
+        tone_map_shader.set_int("u_bloom", TONE_MAP_BLOOM_UNIT as i32);
+
+        let shadow_depth = ShaderBuilder::new(
+            include_str!("shaders/shadow_depth.vert"),
+            include_str!("shaders/shadow_depth.frag"),
+        )
+        .build();
+        let shadow_cube_depth = ShaderBuilder::new(
+            include_str!("shaders/shadow_depth.vert"),
+            include_str!("shaders/shadow_cube_depth.frag"),
+        )
+        .build();
+
+        let skybox_shader = ShaderBuilder::new(
+            include_str!("shaders/skybox.vert"),
+            include_str!("shaders/skybox.frag"),
+        )
+        .build();
+        skybox_shader.set_int("u_environment", SKYBOX_ENVIRONMENT_UNIT as i32);
+
         Renderer {
             cube_shader,
-            cube_vao,
-            cube_vbo,
-            cube_trans_vbo,
-            cube_tex,
+            white_tex,
+            white_tex_array,
+            materials,
+            meshes,
+            cube_mesh: MeshHandle(0),
+            quad_mesh: MeshHandle(1),
+            triangle_mesh: MeshHandle(2),
             light_shader,
             light_vao,
             light_vbo,
             light_trans_vbo,
-            g_buf: GBuf::new(resolution.0, resolution.1),
+            g_buf,
             lit_def_geo,
             lit_def_light,
             ndc_quad_vbo,
             ndc_quad_vao,
+            point_lights_buf: TexBuf::new(max_lights),
+            spot_lights_buf: TexBuf::new(max_lights),
+            directional_lights_buf: TexBuf::new(1),
+            light_volume_vbo,
+            light_volume_vao,
+            lit_def_volume,
+            lit_def_volume_stencil,
+            shadow_depth,
+            shadow_cube_depth,
+            shadow_map: ShadowMap::new(2048),
+            cube_shadow_maps: CubeShadowMaps::new(512, 4),
+            directional_shadow: None,
+            directional_shadow_matrix: glm::identity(),
+            point_shadows: Vec::new(),
+            shadow_bias: 0.05,
+            shadow_pcf: 1,
             deferred: false,
+            light_volumes: false,
+            light_volume_stencil: true,
+            hdr: false,
+            hdr_target,
+            tone_map_shader,
+            tone_map: ToneMap::default(),
+            exposure: 1.0,
+            srgb_output: true,
+            post_process: PostProcess::new(resolution.0, resolution.1),
+            ssao: Ssao::new(resolution.0, resolution.1),
+            use_ssao: false,
+            msaa_samples: 0,
+            msaa_target: None,
+            frustum_culling: false,
+            frustum: None,
+            cull_stats: CullStats::default(),
+            gpu_cull_submitted: 0,
+            gpu_cull: if gpu_culling_supported() {
+                Some(GpuCull::new())
+            } else {
+                None
+            },
+            gpu_culling: false,
+            stream_mode: StreamMode::default(),
+            view_pos: glm::vec3(0.0, 0.0, 0.0),
+            transparent_instances: Vec::new(),
+            environment_map: None,
+            skybox_shader,
+            skybox: true,
         }
     }
 
@@ -101,6 +355,268 @@ impl Renderer {
         self.deferred = val;
     }
 
+    pub fn light_volumes(&self) -> bool {
+        self.light_volumes
+    }
+
+    /// Shade each point and spot light in the deferred path by drawing a sphere bounding its
+    /// influence, rather than looping over every light for every pixel.
+    /// Directional lights light every pixel, so they're still shaded in a full-screen pass.
+    pub fn set_light_volumes(&mut self, val: bool) {
+        self.light_volumes = val;
+    }
+
+    pub fn light_volume_stencil(&self) -> bool {
+        self.light_volume_stencil
+    }
+
+    /// Run a stencil pass before the light volumes, so pixels outside of every volume are
+    /// rejected before shading.
+    pub fn set_light_volume_stencil(&mut self, val: bool) {
+        self.light_volume_stencil = val;
+    }
+
+    pub fn hdr(&self) -> bool {
+        self.hdr
+    }
+
+    /// Render lighting into a floating-point target rather than straight to the screen, so
+    /// bright and overlapping lights don't saturate, then tone map it in a final pass.
+    pub fn set_hdr(&mut self, val: bool) {
+        self.hdr = val;
+    }
+
+    pub fn tone_map(&self) -> ToneMap {
+        self.tone_map
+    }
+
+    /// The operator mapping HDR colours onto the screen, when `hdr` is set.
+    pub fn set_tone_map(&mut self, val: ToneMap) {
+        self.tone_map = val;
+    }
+
+    pub fn exposure(&self) -> f32 {
+        self.exposure
+    }
+
+    /// Multiplier for HDR colours before they're tone mapped, higher is brighter.
+    pub fn set_exposure(&mut self, val: f32) {
+        self.exposure = val;
+    }
+
+    pub fn srgb_output(&self) -> bool {
+        self.srgb_output
+    }
+
+    /// Encode the final colours as sRGB for the screen, after tone mapping if `hdr` is set.
+    /// Lighting is done in linear space, so textures holding sRGB colours, like albedo, should
+    /// use an sRGB `TextureFormat` to be decoded when sampled, or they'll be encoded twice and
+    /// look washed out.
+    pub fn set_srgb_output(&mut self, val: bool) {
+        self.srgb_output = val;
+    }
+
+    pub fn post_process(&self) -> &PostProcess {
+        &self.post_process
+    }
+
+    /// Full-screen effects drawn at the end of `end_draw`, after tone mapping.
+    pub fn post_process_mut(&mut self) -> &mut PostProcess {
+        &mut self.post_process
+    }
+
+    pub fn msaa_samples(&self) -> u32 {
+        self.msaa_samples
+    }
+
+    /// Multisample the forward path with `val` samples per pixel, clamped to `GL_MAX_SAMPLES`,
+    /// or `0` to turn it off. It renders into a multisampled framebuffer that's resolved into the
+    /// HDR target, and the window's own framebuffer stays single-sampled. The deferred path
+    /// ignores this, see `Renderer`.
+    pub fn set_msaa_samples(&mut self, val: u32) {
+        self.msaa_samples = val.min(crate::max_samples());
+        self.msaa_target = None;
+    }
+
+    pub fn g_buf_layout(&self) -> GBufLayout {
+        self.g_buf.layout()
+    }
+
+    /// Switch the deferred path's G-buffer between the full and compact layouts, recreating it.
+    /// If the new G-buffer can't be created, the current one is kept.
+    pub fn set_g_buf_layout(&mut self, layout: GBufLayout) -> Result<(), FramebufferError> {
+        if layout == self.g_buf.layout() {
+            return Ok(());
+        }
+        self.g_buf = GBuf::new(self.g_buf.width(), self.g_buf.height(), layout)?;
+        self.g_buf.set_layout_uniform(&self.lit_def_geo);
+        self.g_buf.set_shader_uniforms(&self.lit_def_light);
+        self.g_buf.set_shader_uniforms(&self.lit_def_volume);
+        Ok(())
+    }
+
+    pub fn frustum_culling(&self) -> bool {
+        self.frustum_culling
+    }
+
+    /// Skip instances whose bounding spheres are outside the camera's frustum when they're
+    /// queued up, before they're converted and uploaded. While shadows are drawn, every
+    /// instance is still uploaded separately for the shadow passes, culled or not.
+    pub fn set_frustum_culling(&mut self, val: bool) {
+        self.frustum_culling = val;
+    }
+
+    pub fn gpu_culling(&self) -> bool {
+        self.gpu_culling
+    }
+
+    /// Frustum cull in a compute shader instead, drawing the survivors indirectly, so the CPU
+    /// never touches them. Needs a GL 4.3 context, otherwise culling stays on the CPU.
+    /// Only applies while `frustum_culling` is on.
+    pub fn set_gpu_culling(&mut self, val: bool) {
+        self.gpu_culling = val;
+    }
+
+    /// Whether instances are culled on the GPU: it's asked for, and the context supports it.
+    pub fn gpu_culling_active(&self) -> bool {
+        self.frustum_culling && self.gpu_culling && self.gpu_cull.is_some()
+    }
+
+    pub fn stream_mode(&self) -> StreamMode {
+        self.stream_mode
+    }
+
+    /// How every mesh's instances are uploaded each frame, see `StreamMode`. Falls back to
+    /// `StreamMode::Orphan` if the context doesn't support `mode`.
+    pub fn set_stream_mode(&mut self, mode: StreamMode) {
+        let mode = if mode.is_supported() {
+            mode
+        } else {
+            StreamMode::Orphan
+        };
+        self.stream_mode = mode;
+        for mesh in &mut self.meshes {
+            mesh.set_stream_mode(mode);
+        }
+    }
+
+    /// Instances drawn and culled since `begin_draw`. When culling on the GPU, they're only
+    /// counted by `end_draw`, and read back without waiting, so a frame or more behind.
+    pub fn cull_stats(&self) -> CullStats {
+        if !self.gpu_culling_active() {
+            return self.cull_stats;
+        }
+        // transparent instances are still counted on the CPU
+        let submitted = self.gpu_cull_submitted;
+        let drawn = self
+            .meshes
+            .iter()
+            .map(|mesh| mesh.gpu_drawn_count().unwrap_or(0))
+            .sum::<usize>()
+            .min(submitted);
+        CullStats {
+            drawn: self.cull_stats.drawn + drawn,
+            culled: self.cull_stats.culled + submitted - drawn,
+        }
+    }
+
+    pub fn ssao_enabled(&self) -> bool {
+        self.use_ssao
+    }
+
+    /// Darken ambient light in corners and crevices with screen-space ambient occlusion.
+    /// Only the deferred path has the G-buffer it needs.
+    pub fn set_ssao_enabled(&mut self, val: bool) {
+        self.use_ssao = val;
+    }
+
+    pub fn ssao(&self) -> &Ssao {
+        &self.ssao
+    }
+
+    /// The SSAO pass's radius, bias and kernel size.
+    pub fn ssao_mut(&mut self) -> &mut Ssao {
+        &mut self.ssao
+    }
+
+    pub fn environment_map(&self) -> Option<&CubeMap> {
+        self.environment_map.as_deref()
+    }
+
+    /// The cube map surrounding the scene, drawn behind everything as a skybox and reflected
+    /// by materials with a `reflectivity`, in both paths. Build one from six faces or an
+    /// equirectangular HDR image with `TextureBuilder`.
+    pub fn set_environment_map(&mut self, val: Option<Rc<CubeMap>>) {
+        self.environment_map = val;
+        let use_environment = self.environment_map.is_some() as i32;
+        self.cube_shader
+            .set_int("u_use_environment", use_environment);
+        self.lit_def_light
+            .set_int("u_use_environment", use_environment);
+        self.lit_def_volume
+            .set_int("u_use_environment", use_environment);
+    }
+
+    pub fn skybox_enabled(&self) -> bool {
+        self.skybox
+    }
+
+    /// Draw the environment map behind the scene, if there is one. Turning it off still
+    /// leaves it reflected.
+    pub fn set_skybox_enabled(&mut self, val: bool) {
+        self.skybox = val;
+    }
+
+    pub fn shadow_bias(&self) -> f32 {
+        self.shadow_bias
+    }
+
+    /// World space distance fragments are pushed along their normal before looking up shadows,
+    /// larger values hide shadow acne but detach shadows from their casters.
+    pub fn set_shadow_bias(&mut self, val: f32) {
+        self.shadow_bias = val;
+    }
+
+    pub fn shadow_pcf(&self) -> u32 {
+        self.shadow_pcf
+    }
+
+    /// Radius of the percentage-closer filtering kernel, e.g. `1` for 3x3 samples of the
+    /// directional shadow map. `0` turns filtering off.
+    pub fn set_shadow_pcf(&mut self, val: u32) {
+        self.shadow_pcf = val;
+    }
+
+    pub fn shadow_map_size(&self) -> u32 {
+        self.shadow_map.size()
+    }
+
+    /// Width and height of the directional light's shadow map.
+    pub fn set_shadow_map_size(&mut self, val: u32) {
+        self.shadow_map.resize(val);
+    }
+
+    pub fn point_shadow_map_size(&self) -> u32 {
+        self.cube_shadow_maps.size()
+    }
+
+    /// Width and height of each face of the point lights' shadow cube maps.
+    pub fn set_point_shadow_map_size(&mut self, val: u32) {
+        let count = self.cube_shadow_maps.count();
+        self.cube_shadow_maps.resize(val, count);
+    }
+
+    pub fn max_point_shadows(&self) -> usize {
+        self.cube_shadow_maps.count()
+    }
+
+    /// How many point lights can cast shadows, each one renders the scene six more times.
+    /// Takes effect on the next `set_lights`.
+    pub fn set_max_point_shadows(&mut self, val: usize) {
+        let size = self.cube_shadow_maps.size();
+        self.cube_shadow_maps.resize(size, val);
+    }
+
     pub fn cube_shader(&self) -> &ShaderProgram {
         &self.cube_shader
     }
@@ -117,21 +633,33 @@ impl Renderer {
     }
 
     pub fn resize(&mut self, width: u32, height: u32) {
-        self.g_buf.resize(width, height)
+        self.g_buf.resize(width, height);
+        self.hdr_target.resize(width, height);
+        self.post_process.resize(width, height);
+        self.ssao.resize(width, height);
+        if let Some(msaa_target) = &mut self.msaa_target {
+            msaa_target.resize(width, height);
+        }
     }
 
     pub fn handle_event(&mut self, event: &glutin::event::Event<()>) {
-        match event {
-            glutin::event::Event::WindowEvent {
-                event: glutin::event::WindowEvent::Resized(physical_size),
-                ..
-            } => self.resize(physical_size.width, physical_size.height),
-            _ => (),
+        if let glutin::event::Event::WindowEvent {
+            event: glutin::event::WindowEvent::Resized(physical_size),
+            ..
+        } = event
+        {
+            self.resize(physical_size.width, physical_size.height);
         }
     }
 
-    pub fn begin_draw(&self, camera: &Camera) {
+    /// Start a frame seen from `camera`. This keeps the camera's frustum, which is why it takes
+    /// `&mut self`, so the `draw_*` calls until `end_draw` can be culled against it.
+    pub fn begin_draw(&mut self, camera: &Camera) {
         let vp_mat = camera.view_projection_matrix();
+        self.frustum = Some(camera.frustum());
+        self.cull_stats = CullStats::default();
+        self.gpu_cull_submitted = 0;
+        self.view_pos = camera.position;
 
         self.light_shader.set_mat4("u_view_projection", &vp_mat);
 
@@ -140,18 +668,70 @@ impl Renderer {
 
         self.lit_def_geo.set_mat4("u_view_projection", &vp_mat);
 
+        // for reconstructing position from the compact G-buffer's depth
+        let inv_vp_mat = glm::inverse(&vp_mat);
+        self.lit_def_light
+            .set_float3("u_view_pos", &camera.position);
         self.lit_def_light
+            .set_mat4("u_inv_view_projection", &inv_vp_mat);
+
+        self.lit_def_volume.set_mat4("u_view_projection", &vp_mat);
+        self.lit_def_volume
             .set_float3("u_view_pos", &camera.position);
+        self.lit_def_volume
+            .set_mat4("u_inv_view_projection", &inv_vp_mat);
+        self.lit_def_volume_stencil
+            .set_mat4("u_view_projection", &vp_mat);
+
+        self.skybox_shader
+            .set_mat4("u_inv_view_projection", &inv_vp_mat);
+
+        self.ssao.set_camera(camera);
     }
 
     pub fn end_draw(&mut self) {
+        let gpu_cull = match (&self.gpu_cull, &self.frustum) {
+            (Some(gpu_cull), Some(frustum)) if self.gpu_culling_active() => {
+                gpu_cull.set_frustum(frustum);
+                Some(gpu_cull)
+            }
+            _ => None,
+        };
+        let shadows = self.directional_shadow.is_some() || !self.point_shadows.is_empty();
+        for mesh in self.meshes.iter_mut() {
+            mesh.set_data(gpu_cull);
+            mesh.set_caster_data(shadows);
+        }
+        self.draw_shadow_maps();
+        let msaa = self.msaa_active();
+        if msaa {
+            self.bind_msaa_target();
+        } else {
+            self.bind_output();
+        }
         self.clear();
         if self.deferred {
-            self.draw_cubes_def();
+            self.draw_meshes_def();
         } else {
-            self.draw_cubes();
+            self.draw_meshes();
         }
         self.draw_lights();
+        self.draw_skybox();
+        self.draw_transparent();
+        if let (true, Some(msaa_target)) = (msaa, &self.msaa_target) {
+            msaa_target.resolve_to(&self.hdr_target);
+        }
+        if self.output().is_some() {
+            self.draw_tone_map();
+        }
+        if self.post_process.chain_active() {
+            self.post_process.apply(&self.ndc_quad_vao);
+        }
+        // instances are submitted fresh every frame
+        for mesh in self.meshes.iter_mut() {
+            mesh.clear_instances();
+        }
+        self.transparent_instances.clear();
     }
 
     pub fn set_vert_trans(vertices: &mut Vec<VertTrans>, transforms: &[Transform]) {
@@ -174,45 +754,457 @@ impl Renderer {
         );
     }
 
+    /// Register a mesh to draw instances of with `draw_mesh`. `max_instances` is how many
+    /// instances of it to make room for up front, drawing more in a frame grows the buffer.
+    pub fn register_mesh(
+        &mut self,
+        vbo: VertBuf<VertBasic>,
+        ibo: IndexBuf,
+        max_instances: usize,
+    ) -> MeshHandle {
+        let material = self.default_material();
+        let mut mesh = Mesh::new(vbo, ibo, max_instances, material);
+        mesh.set_stream_mode(self.stream_mode);
+        self.meshes.push(mesh);
+        MeshHandle(self.meshes.len() - 1)
+    }
+
+    /// Register a mesh with its own tangents for normal mapping, one for each vertex, rather
+    /// than generating them like `register_mesh` does.
+    pub fn register_mesh_with_tangents(
+        &mut self,
+        vbo: VertBuf<VertBasic>,
+        tangent_vbo: VertBuf<VertTangent>,
+        ibo: IndexBuf,
+        max_instances: usize,
+    ) -> MeshHandle {
+        let material = self.default_material();
+        let mut mesh = Mesh::with_tangents(vbo, tangent_vbo, ibo, max_instances, material);
+        mesh.set_stream_mode(self.stream_mode);
+        self.meshes.push(mesh);
+        MeshHandle(self.meshes.len() - 1)
+    }
+
+    /// Register every mesh of a loaded model along with its materials, the handles are in the
+    /// same order as `model.meshes`. Meshes without a material get the default material.
+    pub fn register_model(
+        &mut self,
+        model: &Model,
+        max_instances: usize,
+    ) -> Result<Vec<MeshHandle>, TextureError> {
+        let mut materials = Vec::with_capacity(model.materials.len());
+        for material in &model.materials {
+            let material = Material::from_model_material(material)?;
+            materials.push(self.register_material(material));
+        }
+        Ok(model
+            .meshes
+            .iter()
+            .map(|mesh| {
+                let handle = self.register_mesh(mesh.vert_buf(), mesh.index_buf(), max_instances);
+                if let Some(&material) = mesh.material.and_then(|index| materials.get(index)) {
+                    self.set_mesh_material(handle, material);
+                }
+                handle
+            })
+            .collect())
+    }
+
+    /// Register a material to assign to meshes with `set_mesh_material`, or to draw instances
+    /// with `draw_mesh_with_material`.
+    pub fn register_material(&mut self, material: Material) -> MaterialHandle {
+        self.materials.push(material);
+        MaterialHandle(self.materials.len() - 1)
+    }
+
+    /// The material meshes get when they're registered, and the built-in meshes use.
+    pub fn default_material(&self) -> MaterialHandle {
+        MaterialHandle(0)
+    }
+
+    pub fn material(&self, material: MaterialHandle) -> &Material {
+        &self.materials[material.0]
+    }
+
+    pub fn material_mut(&mut self, material: MaterialHandle) -> &mut Material {
+        &mut self.materials[material.0]
+    }
+
+    pub fn mesh_material(&self, mesh: MeshHandle) -> MaterialHandle {
+        self.meshes[mesh.0].material()
+    }
+
+    /// The material a mesh's instances are drawn with, unless drawn with
+    /// `draw_mesh_with_material`.
+    pub fn set_mesh_material(&mut self, mesh: MeshHandle, material: MaterialHandle) {
+        self.meshes[mesh.0].set_material(material);
+    }
+
+    /// Queue up instances of a mesh for this frame, they're all drawn with one instanced draw
+    /// call in `end_draw`.
+    pub fn draw_mesh(&mut self, mesh: MeshHandle, transforms: &[Transform]) {
+        self.push_instances(mesh, transforms, None, None);
+    }
+
+    /// Queue up a batch of instances of a mesh with a different material to the mesh's own.
+    /// Each run of instances with the same material is a separate draw call.
+    pub fn draw_mesh_with_material(
+        &mut self,
+        mesh: MeshHandle,
+        material: MaterialHandle,
+        transforms: &[Transform],
+    ) {
+        self.push_instances(mesh, transforms, None, Some(material));
+    }
+
+    /// Queue up instances that each sample their own layer of the mesh material's albedo
+    /// texture array, still in one instanced draw call.
+    pub fn draw_mesh_layers(&mut self, mesh: MeshHandle, instances: &[(Transform, u32)]) {
+        let (transforms, layers): (Vec<Transform>, Vec<u32>) = instances.iter().copied().unzip();
+        self.push_instances(mesh, &transforms, Some(&layers), None);
+    }
+
+    /// Replace this frame's instances of the built-in cube mesh.
     pub fn set_cubes(&mut self, transforms: &[Transform]) {
-        let vertices = self.cube_trans_vbo.vertices_mut();
-        Renderer::set_vert_trans(vertices, transforms);
+        self.meshes[self.cube_mesh.0].clear_instances();
+        self.push_instances(self.cube_mesh, transforms, None, None);
     }
 
-    pub fn set_lights(&mut self, transforms: &[Transform]) {
+    /// Queue up the instances that survive frustum culling, if it's on.
+    fn push_instances(
+        &mut self,
+        mesh: MeshHandle,
+        transforms: &[Transform],
+        layers: Option<&[u32]>,
+        material: Option<MaterialHandle>,
+    ) {
+        let handle = material.unwrap_or_else(|| self.meshes[mesh.0].material());
+        if self.materials[handle.0].transparent {
+            self.push_transparent(mesh, handle, transforms, layers);
+            return;
+        }
+        let gpu_culling = self.gpu_culling_active();
+        let mesh = &mut self.meshes[mesh.0];
+        let frustum = match (self.frustum_culling, &self.frustum) {
+            (true, Some(frustum)) if !gpu_culling => frustum,
+            _ => {
+                if gpu_culling {
+                    // culled in `end_draw`
+                    self.gpu_cull_submitted += transforms.len();
+                } else {
+                    self.cull_stats.drawn += transforms.len();
+                }
+                mesh.push_instances(transforms, layers, material);
+                return;
+            }
+        };
+        mesh.push_casters(transforms);
+        let visible = frustum.cull(transforms, mesh.bounding_radius());
+        self.cull_stats.drawn += visible.len();
+        self.cull_stats.culled += transforms.len() - visible.len();
+        let visible_transforms: Vec<Transform> =
+            visible.par_iter().map(|&i| transforms[i]).collect();
+        let visible_layers: Option<Vec<u32>> =
+            layers.map(|layers| visible.par_iter().map(|&i| layers[i]).collect());
+        mesh.push_instances(&visible_transforms, visible_layers.as_deref(), material);
+    }
+
+    /// Queue up instances with a transparent material to be sorted in `end_draw`. They're
+    /// always frustum culled on the CPU, since they're sorted there anyway.
+    fn push_transparent(
+        &mut self,
+        mesh: MeshHandle,
+        material: MaterialHandle,
+        transforms: &[Transform],
+        layers: Option<&[u32]>,
+    ) {
+        let visible = match (self.frustum_culling, &self.frustum) {
+            (true, Some(frustum)) => {
+                frustum.cull(transforms, self.meshes[mesh.0].bounding_radius())
+            }
+            _ => (0..transforms.len()).collect(),
+        };
+        self.cull_stats.drawn += visible.len();
+        self.cull_stats.culled += transforms.len() - visible.len();
+        let view_pos = self.view_pos;
+        self.transparent_instances
+            .par_extend(visible.par_iter().map(|&i| {
+                let mut instance = VertTrans::from_transform(&transforms[i]);
+                if let Some(layers) = layers {
+                    instance.layer = layers[i] as f32;
+                }
+                TransparentInstance {
+                    mesh,
+                    material,
+                    instance,
+                    distance: glm::distance2(&transforms[i].position, &view_pos),
+                }
+            }));
+    }
+
+    pub fn cube_mesh(&self) -> MeshHandle {
+        self.cube_mesh
+    }
+
+    pub fn quad_mesh(&self) -> MeshHandle {
+        self.quad_mesh
+    }
+
+    pub fn triangle_mesh(&self) -> MeshHandle {
+        self.triangle_mesh
+    }
+
+    pub fn set_lights(&mut self, lights: &[PointLight]) {
+        let transforms: Vec<Transform> = lights.par_iter().map(PointLight::transform).collect();
         let vertices = self.light_trans_vbo.vertices_mut();
-        Renderer::set_vert_trans(vertices, transforms);
-        transforms.iter().enumerate().for_each(|(i, t)| {
-            let name = format!("u_point_lights[{}].position", i);
-            self.cube_shader.set_float3(&name, &t.position);
-            self.lit_def_light.set_float3(&name, &t.position);
-        });
-        self.cube_shader
-            .set_int("u_point_lights_count", transforms.len() as i32);
-        self.lit_def_light
-            .set_int("u_point_lights_count", transforms.len() as i32);
+        Renderer::set_vert_trans(vertices, &transforms);
+        self.light_trans_vbo.set_data();
+        let texels = self.point_lights_buf.data_mut();
+        texels.clear();
+        texels.par_extend(lights.par_iter().map(PointLightTexels::from_light));
+        // the first shadow casting lights get a cube each, until they run out
+        let max_point_shadows = self.cube_shadow_maps.count();
+        self.point_shadows.clear();
+        for (light, texel) in lights.iter().zip(texels.iter_mut()) {
+            if self.point_shadows.len() == max_point_shadows {
+                break;
+            }
+            if light.cast_shadows {
+                texel.set_shadow_index(self.point_shadows.len() as i32);
+                self.point_shadows.push((light.position, light.radius()));
+            }
+        }
+        self.point_lights_buf.set_data();
     }
 
-    fn draw_cubes(&self) {
-        self.cube_trans_vbo.set_data();
-        self.cube_shader.bind();
-        self.cube_tex.bind();
-        self.cube_vao.bind();
-        gl_call!(gl::DrawElementsInstanced(
-            gl::TRIANGLES,
-            self.cube_vao.index_buf().len() as i32,
-            gl::UNSIGNED_INT,
-            std::ptr::null(),
-            self.cube_trans_vbo.vertices().len() as i32,
+    pub fn set_spot_lights(&mut self, lights: &[SpotLight]) {
+        let texels = self.spot_lights_buf.data_mut();
+        texels.clear();
+        texels.par_extend(lights.par_iter().map(SpotLightTexels::from_light));
+        self.spot_lights_buf.set_data();
+    }
+
+    pub fn set_directional_lights(&mut self, lights: &[DirectionalLight]) {
+        let texels = self.directional_lights_buf.data_mut();
+        texels.clear();
+        texels.extend(lights.iter().map(DirectionalLightTexels::from_light));
+        // only the first shadow casting light gets the shadow map
+        self.directional_shadow = None;
+        if let Some(index) = lights.iter().position(|light| light.cast_shadows) {
+            texels[index].set_shadow_index(0);
+            self.directional_shadow = Some(lights[index].direction);
+        }
+        self.directional_lights_buf.set_data();
+    }
+
+    fn set_light_units(shader: &ShaderProgram, first_unit: u32) {
+        shader.set_int("u_point_lights", first_unit as i32);
+        shader.set_int("u_spot_lights", first_unit as i32 + 1);
+        shader.set_int("u_directional_lights", first_unit as i32 + 2);
+        shader.set_int("u_directional_shadow", first_unit as i32 + 3);
+        shader.set_int("u_point_shadows", first_unit as i32 + 4);
+    }
+
+    fn set_shadow_uniforms(&self, shader: &ShaderProgram) {
+        shader.set_mat4(
+            "u_directional_shadow_matrix",
+            &self.directional_shadow_matrix,
+        );
+        shader.set_float("u_shadow_bias", self.shadow_bias);
+        shader.set_int("u_shadow_pcf", self.shadow_pcf as i32);
+    }
+
+    /// `local_lights` is whether to count point and spot lights, or only directional lights.
+    fn set_light_counts(&self, shader: &ShaderProgram, local_lights: bool) {
+        let (point_count, spot_count) = if local_lights {
+            (
+                self.point_lights_buf.data().len() as i32,
+                self.spot_lights_buf.data().len() as i32,
+            )
+        } else {
+            (0, 0)
+        };
+        shader.set_int("u_point_lights_count", point_count);
+        shader.set_int("u_spot_lights_count", spot_count);
+        shader.set_int(
+            "u_directional_lights_count",
+            self.directional_lights_buf.data().len() as i32,
+        );
+    }
+
+    fn bind_lights(&self, first_unit: u32) {
+        self.point_lights_buf.bind(first_unit);
+        self.spot_lights_buf.bind(first_unit + 1);
+        self.directional_lights_buf.bind(first_unit + 2);
+        self.shadow_map.bind_tex(first_unit + 3);
+        self.cube_shadow_maps.bind_tex(first_unit + 4);
+    }
+
+    fn unbind_lights(&self, first_unit: u32) {
+        self.point_lights_buf.unbind(first_unit);
+        self.spot_lights_buf.unbind(first_unit + 1);
+        self.directional_lights_buf.unbind(first_unit + 2);
+        self.shadow_map.unbind_tex(first_unit + 3);
+        self.cube_shadow_maps.unbind_tex(first_unit + 4);
+    }
+
+    /// Bounding sphere (centre, radius) of every shadow caster, culled or not, which is what
+    /// the directional shadow map has to cover.
+    fn shadow_caster_bounds(&self) -> (glm::Vec3, f32) {
+        let empty = (
+            glm::vec3(f32::MAX, f32::MAX, f32::MAX),
+            glm::vec3(f32::MIN, f32::MIN, f32::MIN),
+        );
+        let (min, max) = self
+            .meshes
+            .iter()
+            .map(|mesh| {
+                let bounding_radius = mesh.bounding_radius();
+                mesh.shadow_casters()
+                    .par_iter()
+                    .map(|v| {
+                        let m = &v.transform;
+                        let position = glm::vec3(m[(0, 3)], m[(1, 3)], m[(2, 3)]);
+                        // the mesh's bounding sphere, at its largest scale
+                        let scale = (0..3)
+                            .map(|i| glm::length(&glm::vec3(m[(0, i)], m[(1, i)], m[(2, i)])))
+                            .fold(0.0, f32::max);
+                        let extent = glm::vec3(1.0, 1.0, 1.0) * scale * bounding_radius;
+                        (position - extent, position + extent)
+                    })
+                    .reduce(
+                        || empty,
+                        |a, b| (glm::min2(&a.0, &b.0), glm::max2(&a.1, &b.1)),
+                    )
+            })
+            .fold(empty, |a, b| (glm::min2(&a.0, &b.0), glm::max2(&a.1, &b.1)));
+        if min.x > max.x {
+            return (glm::vec3(0.0, 0.0, 0.0), 1.0);
+        }
+        (
+            (min + max) * 0.5,
+            (glm::length(&(max - min)) * 0.5).max(1.0),
+        )
+    }
+
+    fn draw_shadow_maps(&mut self) {
+        if let Some(direction) = self.directional_shadow {
+            let (center, radius) = self.shadow_caster_bounds();
+            let direction = light_direction(&direction);
+            let up = if direction.y.abs() > 0.99 {
+                glm::vec3(0.0, 0.0, 1.0)
+            } else {
+                glm::vec3(0.0, 1.0, 0.0)
+            };
+            let view = glm::look_at(&(center - direction * radius), &center, &up);
+            let projection = glm::ortho(-radius, radius, -radius, radius, 0.0, radius * 2.0);
+            self.directional_shadow_matrix = projection * view;
+        }
+        self.set_shadow_uniforms(&self.cube_shader);
+        self.set_shadow_uniforms(&self.lit_def_light);
+        self.set_shadow_uniforms(&self.lit_def_volume);
+
+        if self.directional_shadow.is_none() && self.point_shadows.is_empty() {
+            return;
+        }
+        if self.directional_shadow.is_some() {
+            self.shadow_map.bind();
+            gl_call!(gl::Clear(gl::DEPTH_BUFFER_BIT));
+            self.shadow_depth
+                .set_mat4("u_light_space", &self.directional_shadow_matrix);
+            self.shadow_depth.bind();
+            for mesh in self.meshes.iter() {
+                mesh.draw_all();
+            }
+            self.shadow_depth.unbind();
+            self.shadow_map.unbind();
+        }
+
+        for (index, (position, radius)) in self.point_shadows.iter().enumerate() {
+            let projection = glm::perspective(1.0, std::f32::consts::FRAC_PI_2, 0.05, *radius);
+            self.shadow_cube_depth.set_float3("u_light_pos", position);
+            self.shadow_cube_depth.set_float("u_light_radius", *radius);
+            for (face, view) in CubeShadowMaps::face_views(position).iter().enumerate() {
+                self.cube_shadow_maps.bind_face(index, face);
+                gl_call!(gl::Clear(gl::DEPTH_BUFFER_BIT));
+                self.shadow_cube_depth
+                    .set_mat4("u_light_space", &(projection * view));
+                self.shadow_cube_depth.bind();
+                for mesh in self.meshes.iter() {
+                    mesh.draw_all();
+                }
+                self.shadow_cube_depth.unbind();
+            }
+        }
+        self.cube_shadow_maps.unbind();
+
+        gl_call!(gl::Viewport(
+            0,
+            0,
+            self.g_buf.width() as i32,
+            self.g_buf.height() as i32
         ));
-        self.cube_vao.unbind();
-        self.cube_tex.unbind();
+    }
+
+    fn draw_meshes(&self) {
+        self.set_light_counts(&self.cube_shader, true);
+        self.bind_lights(LIT_FOR_LIGHTS_UNIT);
+        self.bind_environment(LIT_FOR_ENVIRONMENT_UNIT);
+        for mesh in self.meshes.iter() {
+            mesh.draw_batches(|material| self.bind_material(&self.cube_shader, material));
+        }
+        self.unbind_environment(LIT_FOR_ENVIRONMENT_UNIT);
+        self.unbind_lights(LIT_FOR_LIGHTS_UNIT);
+        self.unbind_material();
         self.cube_shader.unbind();
     }
 
+    fn set_material_units(shader: &ShaderProgram) {
+        shader.set_int("u_albedo_tex", ALBEDO_UNIT as i32);
+        shader.set_int("u_specular_tex", SPECULAR_UNIT as i32);
+        shader.set_int("u_albedo_array", ALBEDO_ARRAY_UNIT as i32);
+        shader.set_int("u_normal_tex", NORMAL_UNIT as i32);
+    }
+
+    /// Set a material's uniforms and bind its textures, leaving `shader` bound to draw with.
+    fn bind_material(&self, shader: &ShaderProgram, material: MaterialHandle) {
+        let material = &self.materials[material.0];
+        shader.set_float4("u_albedo_color", &material.albedo_color);
+        shader.set_float("u_specular_strength", material.specular_strength);
+        shader.set_float("u_shininess", material.shininess);
+        shader.set_float("u_reflectivity", material.reflectivity);
+        let albedo_tex = material
+            .albedo_texture
+            .as_deref()
+            .unwrap_or(&self.white_tex);
+        albedo_tex.bind(ALBEDO_UNIT);
+        let specular_tex = material
+            .specular_texture
+            .as_deref()
+            .unwrap_or(&self.white_tex);
+        specular_tex.bind(SPECULAR_UNIT);
+        // the sampler needs something bound, even if it isn't used
+        let albedo_array = material.albedo_texture_array.as_deref();
+        albedo_array
+            .unwrap_or(&self.white_tex_array)
+            .bind(ALBEDO_ARRAY_UNIT);
+        shader.set_int("u_use_albedo_array", albedo_array.is_some() as i32);
+        let normal_tex = material.normal_texture.as_deref();
+        normal_tex.unwrap_or(&self.white_tex).bind(NORMAL_UNIT);
+        shader.set_int("u_use_normal_tex", normal_tex.is_some() as i32);
+        shader.bind();
+    }
+
+    fn unbind_material(&self) {
+        self.white_tex.unbind(ALBEDO_UNIT);
+        self.white_tex.unbind(SPECULAR_UNIT);
+        self.white_tex_array.unbind(ALBEDO_ARRAY_UNIT);
+        self.white_tex.unbind(NORMAL_UNIT);
+    }
+
     fn draw_lights(&self) {
-        self.light_trans_vbo.set_data();
         self.light_shader.bind();
+        self.point_lights_buf.bind(UNLIT_FOR_POINT_LIGHTS_UNIT);
         self.light_vao.bind();
         gl_call!(gl::DrawElementsInstanced(
             gl::TRIANGLES,
@@ -222,53 +1214,344 @@ impl Renderer {
             self.light_trans_vbo.vertices().len() as i32,
         ));
         self.light_vao.unbind();
+        self.point_lights_buf.unbind(UNLIT_FOR_POINT_LIGHTS_UNIT);
         self.light_shader.unbind();
     }
 
-    fn draw_cubes_def(&mut self) {
-        self.cube_trans_vbo.set_data();
+    /// Bind the environment map to `unit` for the lit shaders, if there is one.
+    fn bind_environment(&self, unit: u32) {
+        if let Some(environment_map) = &self.environment_map {
+            environment_map.bind(unit);
+        }
+    }
+
+    fn unbind_environment(&self, unit: u32) {
+        if let Some(environment_map) = &self.environment_map {
+            environment_map.unbind(unit);
+        }
+    }
+
+    /// Fill whatever's left at the far plane with the environment map, after the opaque
+    /// geometry so covered pixels fail the depth test. The deferred path has blitted its depth
+    /// into the output by now.
+    fn draw_skybox(&self) {
+        let environment_map = match &self.environment_map {
+            Some(environment_map) if self.skybox => environment_map,
+            _ => return,
+        };
+        // the depth buffer is cleared to the far plane, which the skybox is drawn on
+        gl_call!(gl::DepthFunc(gl::LEQUAL));
+        gl_call!(gl::DepthMask(gl::FALSE));
+        self.skybox_shader.bind();
+        environment_map.bind(SKYBOX_ENVIRONMENT_UNIT);
+        self.ndc_quad_vao.bind();
+        gl_call!(gl::DrawArrays(gl::TRIANGLE_STRIP, 0, 4));
+        self.ndc_quad_vao.unbind();
+        environment_map.unbind(SKYBOX_ENVIRONMENT_UNIT);
+        self.skybox_shader.unbind();
+        gl_call!(gl::DepthMask(gl::TRUE));
+        gl_call!(gl::DepthFunc(gl::LESS));
+    }
 
+    /// Blend the transparent instances over the scene, furthest first, depth tested against
+    /// it without writing depth. The deferred path has blitted its depth into the output by now.
+    fn draw_transparent(&mut self) {
+        if self.transparent_instances.is_empty() {
+            return;
+        }
+        // stable, so instances the same distance away keep the order they were queued in
+        self.transparent_instances
+            .par_sort_by(|a, b| b.distance.total_cmp(&a.distance));
+        // runs of instances with the same mesh and material, as (mesh, material, first, count)
+        // with `first` into the mesh's transparent instances
+        let mut runs: Vec<(MeshHandle, MaterialHandle, usize, usize)> = Vec::new();
+        for transparent in &self.transparent_instances {
+            let index = self.meshes[transparent.mesh.0].push_transparent(transparent.instance);
+            match runs.last_mut() {
+                Some((mesh, material, _, count))
+                    if *mesh == transparent.mesh && *material == transparent.material =>
+                {
+                    *count += 1
+                }
+                _ => runs.push((transparent.mesh, transparent.material, index, 1)),
+            }
+        }
+        for mesh in self.meshes.iter_mut() {
+            mesh.set_transparent_data();
+        }
+
+        gl_call!(gl::Enable(gl::BLEND));
+        gl_call!(gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA));
+        gl_call!(gl::DepthMask(gl::FALSE));
+        self.set_light_counts(&self.cube_shader, true);
+        self.bind_lights(LIT_FOR_LIGHTS_UNIT);
+        self.bind_environment(LIT_FOR_ENVIRONMENT_UNIT);
+        for (mesh, material, first, count) in runs {
+            self.bind_material(&self.cube_shader, material);
+            self.meshes[mesh.0].draw_transparent(first, count);
+        }
+        self.unbind_environment(LIT_FOR_ENVIRONMENT_UNIT);
+        self.unbind_lights(LIT_FOR_LIGHTS_UNIT);
+        self.unbind_material();
+        self.cube_shader.unbind();
+        gl_call!(gl::DepthMask(gl::TRUE));
+        gl_call!(gl::Disable(gl::BLEND));
+    }
+
+    fn draw_meshes_def(&mut self) {
         // goemetry pass (must be cleared black beforehand)
         self.g_buf.bind();
         {
             gl_call!(gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT));
-            self.lit_def_geo.bind();
-            self.cube_tex.bind();
-            self.cube_vao.bind();
+            for mesh in self.meshes.iter() {
+                mesh.draw_batches(|material| self.bind_material(&self.lit_def_geo, material));
+            }
+            self.unbind_material();
+            self.lit_def_geo.unbind();
+        }
+
+        if self.use_ssao {
+            self.ssao.draw(&self.g_buf, &self.ndc_quad_vao);
+            self.ssao.bind_tex(LIT_DEF_SSAO_UNIT);
+        }
+        self.lit_def_light
+            .set_int("u_use_ssao", self.use_ssao as i32);
+        self.lit_def_volume
+            .set_int("u_use_ssao", self.use_ssao as i32);
+        self.bind_output();
+
+        if self.light_volumes {
+            self.draw_light_volumes();
+        } else {
+            // lighting pass
+            gl_call!(gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT));
+            self.draw_def_light_quad(true);
+
+            // blit depth buffer
+            self.g_buf.blit_depth_to(self.output());
+        }
+
+        if self.use_ssao {
+            self.ssao.unbind_tex(LIT_DEF_SSAO_UNIT);
+        }
+    }
+
+    /// Shade the whole screen from the G-buffer, with every light or only the directional lights.
+    fn draw_def_light_quad(&self, local_lights: bool) {
+        self.set_light_counts(&self.lit_def_light, local_lights);
+        self.lit_def_light.bind();
+        self.g_buf.bind_bufs();
+        self.bind_lights(LIT_DEF_LIGHTS_UNIT);
+        self.bind_environment(LIT_DEF_ENVIRONMENT_UNIT);
+        self.ndc_quad_vao.bind();
+        gl_call!(gl::DrawArrays(gl::TRIANGLE_STRIP, 0, 4));
+        self.ndc_quad_vao.unbind();
+        self.unbind_environment(LIT_DEF_ENVIRONMENT_UNIT);
+        self.unbind_lights(LIT_DEF_LIGHTS_UNIT);
+        self.g_buf.unbind_bufs();
+        self.lit_def_light.unbind();
+    }
+
+    fn draw_light_volumes(&mut self) {
+        // light volumes are depth tested against the scene, so blit depth first
+        gl_call!(gl::Clear(gl::COLOR_BUFFER_BIT));
+        self.g_buf.blit_depth_to(self.output());
+
+        gl_call!(gl::DepthMask(gl::FALSE));
+        self.bind_lights(LIT_DEF_LIGHTS_UNIT);
+        self.bind_environment(LIT_DEF_ENVIRONMENT_UNIT);
+        self.light_volume_vao.bind();
+        self.draw_light_volume_instances(LIGHT_TYPE_POINT, self.point_lights_buf.data().len());
+        self.draw_light_volume_instances(LIGHT_TYPE_SPOT, self.spot_lights_buf.data().len());
+        self.light_volume_vao.unbind();
+        self.unbind_environment(LIT_DEF_ENVIRONMENT_UNIT);
+        self.unbind_lights(LIT_DEF_LIGHTS_UNIT);
+
+        // directional lights cover every pixel, added on top with a full-screen pass, which
+        // also adds the environment's reflection
+        if !self.directional_lights_buf.data().is_empty() || self.environment_map.is_some() {
+            gl_call!(gl::Disable(gl::DEPTH_TEST));
+            gl_call!(gl::Enable(gl::BLEND));
+            gl_call!(gl::BlendFunc(gl::ONE, gl::ONE));
+            self.draw_def_light_quad(false);
+            gl_call!(gl::Disable(gl::BLEND));
+            gl_call!(gl::Enable(gl::DEPTH_TEST));
+        }
+        gl_call!(gl::DepthMask(gl::TRUE));
+    }
+
+    /// Draw one light volume per light of `light_type`, with the light buffers and volume
+    /// mesh already bound.
+    fn draw_light_volume_instances(&self, light_type: i32, light_count: usize) {
+        if light_count == 0 {
+            return;
+        }
+        let light_count = light_count as i32;
+        let index_count = self.light_volume_vao.index_buf().len() as i32;
+        self.lit_def_volume.set_int("u_light_type", light_type);
+        self.lit_def_volume_stencil
+            .set_int("u_light_type", light_type);
+        // a light's radius can reach well past the far plane, where its back faces would be
+        // clipped away and the light lost, so clamp their depth to the far plane instead
+        gl_call!(gl::Enable(gl::DEPTH_CLAMP));
+
+        if self.light_volume_stencil {
+            gl_call!(gl::Clear(gl::STENCIL_BUFFER_BIT));
+            // z-fail: back faces behind the scene increment, front faces behind it decrement,
+            // so only pixels inside of at least one volume are left non-zero
+            gl_call!(gl::Enable(gl::STENCIL_TEST));
+            gl_call!(gl::ColorMask(gl::FALSE, gl::FALSE, gl::FALSE, gl::FALSE));
+            gl_call!(gl::StencilFunc(gl::ALWAYS, 0, 0xFF));
+            gl_call!(gl::StencilOpSeparate(
+                gl::BACK,
+                gl::KEEP,
+                gl::INCR_WRAP,
+                gl::KEEP
+            ));
+            gl_call!(gl::StencilOpSeparate(
+                gl::FRONT,
+                gl::KEEP,
+                gl::DECR_WRAP,
+                gl::KEEP
+            ));
+            self.lit_def_volume_stencil.bind();
             gl_call!(gl::DrawElementsInstanced(
                 gl::TRIANGLES,
-                self.cube_vao.index_buf().len() as i32,
+                index_count,
                 gl::UNSIGNED_INT,
                 std::ptr::null(),
-                self.cube_trans_vbo.vertices().len() as i32,
+                light_count,
             ));
-            self.cube_vao.unbind();
-            self.cube_tex.unbind();
-            self.lit_def_geo.unbind();
+            self.lit_def_volume_stencil.unbind();
+            gl_call!(gl::ColorMask(gl::TRUE, gl::TRUE, gl::TRUE, gl::TRUE));
+            gl_call!(gl::StencilFunc(gl::NOTEQUAL, 0, 0xFF));
+            gl_call!(gl::StencilOp(gl::KEEP, gl::KEEP, gl::KEEP));
         }
-        self.g_buf.unbind();
 
-        // lighting pass
-        gl_call!(gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT));
-        {
-            self.lit_def_light.bind();
-            self.g_buf.bind_bufs();
-            self.ndc_quad_vao.bind();
-            gl_call!(gl::DrawArrays(gl::TRIANGLE_STRIP, 0, 4));
-            self.ndc_quad_vao.unbind();
-            self.g_buf.unbind_bufs();
-            self.lit_def_light.unbind();
+        // back faces only, so volumes still shade when the camera is inside them,
+        // and only where the scene is in front of the back of the volume
+        gl_call!(gl::Enable(gl::CULL_FACE));
+        gl_call!(gl::CullFace(gl::FRONT));
+        gl_call!(gl::DepthFunc(gl::GEQUAL));
+        gl_call!(gl::Enable(gl::BLEND));
+        gl_call!(gl::BlendFunc(gl::ONE, gl::ONE));
+        self.lit_def_volume.bind();
+        self.g_buf.bind_bufs();
+        gl_call!(gl::DrawElementsInstanced(
+            gl::TRIANGLES,
+            index_count,
+            gl::UNSIGNED_INT,
+            std::ptr::null(),
+            light_count,
+        ));
+        self.g_buf.unbind_bufs();
+        self.lit_def_volume.unbind();
+
+        gl_call!(gl::Disable(gl::BLEND));
+        gl_call!(gl::DepthFunc(gl::LESS));
+        gl_call!(gl::CullFace(gl::BACK));
+        gl_call!(gl::Disable(gl::CULL_FACE));
+        gl_call!(gl::Disable(gl::STENCIL_TEST));
+        gl_call!(gl::Disable(gl::DEPTH_CLAMP));
+    }
+
+    /// Where lighting is rendered to: the HDR target, or the default framebuffer if `None`.
+    /// Post-processing needs a texture to read, sRGB output a final pass to encode it and MSAA
+    /// a single-sampled target to resolve into, so they always go through the HDR target.
+    fn output(&self) -> Option<&Framebuffer> {
+        if self.hdr || self.srgb_output || self.post_process.is_active() || self.msaa_active() {
+            Some(&self.hdr_target)
+        } else {
+            None
         }
+    }
+
+    fn bind_output(&self) {
+        match self.output() {
+            Some(framebuffer) => framebuffer.bind(),
+            None => gl_call!(gl::BindFramebuffer(gl::FRAMEBUFFER, 0)),
+        }
+    }
+
+    fn msaa_active(&self) -> bool {
+        !self.deferred && self.msaa_samples > 0
+    }
+
+    /// Bind the forward path's multisampled target, building it if the sample count changed.
+    /// It has the HDR target's formats, which it's resolved into.
+    fn bind_msaa_target(&mut self) {
+        if self.msaa_target.is_none() {
+            let msaa_target = FramebufferBuilder::new()
+                .with_color(self.hdr_target.color(0).format())
+                .with_depth(DepthFormat::Depth24Stencil8)
+                .with_samples(self.msaa_samples)
+                .build(self.hdr_target.width(), self.hdr_target.height())
+                .expect("failed to create MSAA target");
+            self.msaa_target = Some(msaa_target);
+        }
+        if let Some(msaa_target) = &self.msaa_target {
+            msaa_target.bind();
+        }
+    }
+
+    /// Map the HDR target onto the default framebuffer, or the start of the post-processing
+    /// chain, adding bloom first if it's enabled. Without `hdr` it's only copied across, and sRGB
+    /// encoded if `srgb_output` is set.
+    fn draw_tone_map(&self) {
+        let bloom_intensity = self
+            .post_process
+            .draw_bloom(&self.hdr_target, &self.ndc_quad_vao);
+        if self.post_process.chain_active() {
+            self.post_process.input().bind();
+        } else {
+            self.hdr_target.unbind();
+        }
+        let (tone_map, exposure) = if self.hdr {
+            (self.tone_map, self.exposure)
+        } else {
+            (ToneMap::None, 1.0)
+        };
+        self.tone_map_shader
+            .set_int("u_tone_map", tone_map.shader_index());
+        self.tone_map_shader.set_float("u_exposure", exposure);
+        self.tone_map_shader
+            .set_float("u_bloom_intensity", bloom_intensity);
+        self.tone_map_shader
+            .set_int("u_srgb", self.srgb_output as i32);
+        gl_call!(gl::Disable(gl::DEPTH_TEST));
+        self.tone_map_shader.bind();
+        self.hdr_target.bind_colors(TONE_MAP_HDR_UNIT);
+        self.post_process
+            .bloom_target()
+            .bind_colors(TONE_MAP_BLOOM_UNIT);
+        self.ndc_quad_vao.bind();
+        gl_call!(gl::DrawArrays(gl::TRIANGLE_STRIP, 0, 4));
+        self.ndc_quad_vao.unbind();
+        self.post_process
+            .bloom_target()
+            .unbind_colors(TONE_MAP_BLOOM_UNIT);
+        self.hdr_target.unbind_colors(TONE_MAP_HDR_UNIT);
+        self.tone_map_shader.unbind();
+        gl_call!(gl::Enable(gl::DEPTH_TEST));
+    }
+
+    /// Read the last frame back from the default framebuffer.
+    /// Call this after `end_draw`, before the buffers are swapped.
+    pub fn capture(&self) -> image::RgbaImage {
+        crate::read_default_framebuffer(self.g_buf.width(), self.g_buf.height())
+    }
 
-        // blit depth buffer
-        self.g_buf.blit_depth();
+    /// Read a G-buffer attachment back, only meaningful after a deferred `end_draw`. `None` if
+    /// the layout doesn't store it, see `GBuf::capture`.
+    pub fn capture_g_buf(&self, attachment: GBufAttachment) -> Option<image::RgbaImage> {
+        self.g_buf.capture(attachment)
     }
 
-    pub fn draw_quad(&mut self, _transform: &Transform) {
-        todo!()
+    pub fn draw_quad(&mut self, transform: &Transform) {
+        self.draw_mesh(self.quad_mesh, std::slice::from_ref(transform));
     }
 
-    pub fn draw_triangle(&mut self, _transform: &Transform) {
-        todo!()
+    pub fn draw_triangle(&mut self, transform: &Transform) {
+        self.draw_mesh(self.triangle_mesh, std::slice::from_ref(transform));
     }
 }
@@ -2,9 +2,29 @@ use crate::glm;
 use gl;
 use std::ffi::CString;
 
+#[derive(Debug)]
+pub enum ShaderError {
+    /// A shader failed to compile, with the driver's info log.
+    Compile(String),
+    /// The program failed to link, with the driver's info log.
+    Link(String),
+}
+
+impl std::fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ShaderError::Compile(log) => write!(f, "shader compilation failed:\n{}", log),
+            ShaderError::Link(log) => write!(f, "program linking failed:\n{}", log),
+        }
+    }
+}
+
+impl std::error::Error for ShaderError {}
+
 pub struct ShaderBuilder {
     vert_src: String,
     frag_src: String,
+    frag_includes: Vec<String>,
     uniforms: Vec<Uniform>,
 }
 
@@ -13,9 +33,16 @@ impl ShaderBuilder {
         ShaderBuilder {
             vert_src: vert_src.to_string(),
             frag_src: frag_src.to_string(),
+            frag_includes: Vec::new(),
             uniforms: Vec::new(),
         }
     }
+    /// Shared GLSL (without a `#version`) that's spliced in after the fragment shader's
+    /// `#version` line, in the order they're added.
+    pub fn with_frag_include(mut self, src: &str) -> Self {
+        self.frag_includes.push(src.to_string());
+        self
+    }
     pub fn with_float4(mut self, name: &str, value: glm::Vec4) -> Self {
         let uniform = Uniform {
             name: name.to_string(),
@@ -32,10 +59,18 @@ impl ShaderBuilder {
         self.uniforms.push(uniform);
         self
     }
+    /// # Panics
+    /// If compilation or linking fails, see `try_build` for an error instead.
     pub fn build(&self) -> ShaderProgram {
-        let vert = Shader::new(ShaderType::Vertex, &self.vert_src);
-        let frag = Shader::new(ShaderType::Fragment, &self.frag_src);
-        let prog = ShaderProgram::new(&[vert, frag]);
+        self.try_build().unwrap_or_else(|err| panic!("{}", err))
+    }
+    pub fn try_build(&self) -> Result<ShaderProgram, ShaderError> {
+        let vert = Shader::new(ShaderType::Vertex, &self.vert_src)?;
+        let frag = Shader::new(
+            ShaderType::Fragment,
+            &ShaderBuilder::splice_includes(&self.frag_src, &self.frag_includes),
+        )?;
+        let prog = ShaderProgram::new(&[vert, frag])?;
         prog.bind();
         for uniform in &self.uniforms {
             let name = CString::new(uniform.name.as_str()).unwrap();
@@ -52,7 +87,23 @@ impl ShaderBuilder {
                 _ => unimplemented!("not currently supported."),
             }
         }
-        prog
+        Ok(prog)
+    }
+    fn splice_includes(src: &str, includes: &[String]) -> String {
+        if includes.is_empty() {
+            return src.to_string();
+        }
+        let (version, rest) = match src.find('\n') {
+            Some(index) if src.starts_with("#version") => src.split_at(index + 1),
+            _ => ("", src),
+        };
+        let mut spliced = version.to_string();
+        for include in includes {
+            spliced.push_str(include);
+            spliced.push('\n');
+        }
+        spliced.push_str(rest);
+        spliced
     }
 }
 
@@ -79,11 +130,12 @@ pub struct ShaderProgram {
 
 impl ShaderProgram {
     /// Creates a program and links shaders to it.
-    ///
-    /// # Panics
-    /// If program linking fails, this function will `panic!`.
-    fn new(shaders: &[Shader]) -> ShaderProgram {
-        let id = gl_call!(gl::CreateProgram());
+    fn new(shaders: &[Shader]) -> Result<ShaderProgram, ShaderError> {
+        // deleted on drop if linking fails
+        let program = ShaderProgram {
+            id: gl_call!(gl::CreateProgram()),
+        };
+        let id = program.id;
         for shader in shaders {
             gl_call!(gl::AttachShader(id, shader.id()));
         }
@@ -100,12 +152,19 @@ impl ShaderProgram {
                 std::ptr::null_mut(),
                 error.as_ptr() as *mut gl::types::GLchar,
             ));
-            panic!(
-                "program linking failed:\n{}",
-                error.to_string_lossy().into_owned()
-            );
+            return Err(ShaderError::Link(error.to_string_lossy().into_owned()));
         }
-        ShaderProgram { id }
+        Ok(program)
+    }
+
+    /// Creates a program from a single compute shader, which needs a GL 4.3 context.
+    ///
+    /// # Panics
+    /// If compilation or linking fails, this function will `panic!`.
+    pub fn new_compute(src: &str) -> ShaderProgram {
+        Shader::new(ShaderType::Compute, src)
+            .and_then(|shader| ShaderProgram::new(&[shader]))
+            .unwrap_or_else(|err| panic!("{}", err))
     }
 
     fn id(&self) -> u32 {
@@ -128,6 +187,30 @@ impl ShaderProgram {
         self.unbind();
     }
 
+    pub fn set_uint(&self, name: &str, value: u32) {
+        self.bind();
+        let name = CString::new(name).unwrap();
+        let location = gl_call!(gl::GetUniformLocation(self.id(), name.as_ptr()));
+        gl_call!(gl::Uniform1ui(location, value));
+        self.unbind();
+    }
+
+    pub fn set_float(&self, name: &str, value: f32) {
+        self.bind();
+        let name = CString::new(name).unwrap();
+        let location = gl_call!(gl::GetUniformLocation(self.id(), name.as_ptr()));
+        gl_call!(gl::Uniform1f(location, value));
+        self.unbind();
+    }
+
+    pub fn set_float2(&self, name: &str, value: &glm::Vec2) {
+        self.bind();
+        let name = CString::new(name).unwrap();
+        let location = gl_call!(gl::GetUniformLocation(self.id(), name.as_ptr()));
+        gl_call!(gl::Uniform2f(location, value.x, value.y));
+        self.unbind();
+    }
+
     pub fn set_float3(&self, name: &str, value: &glm::Vec3) {
         self.bind();
         let name = CString::new(name).unwrap();
@@ -164,6 +247,7 @@ impl Drop for ShaderProgram {
 enum ShaderType {
     Vertex = gl::VERTEX_SHADER,
     Fragment = gl::FRAGMENT_SHADER,
+    Compute = gl::COMPUTE_SHADER,
 }
 
 struct Shader {
@@ -172,11 +256,12 @@ struct Shader {
 
 impl Shader {
     /// Creates a shader from source.
-    ///
-    /// # Panics
-    /// If shader compilation fails, this function will `panic!`.
-    pub fn new(shader_type: ShaderType, source: &str) -> Shader {
-        let id = gl_call!(gl::CreateShader(shader_type as gl::types::GLenum));
+    pub fn new(shader_type: ShaderType, source: &str) -> Result<Shader, ShaderError> {
+        // deleted on drop if compilation fails
+        let shader = Shader {
+            id: gl_call!(gl::CreateShader(shader_type as gl::types::GLenum)),
+        };
+        let id = shader.id;
         let source = CString::new(source).unwrap();
         gl_call!(gl::ShaderSource(id, 1, &source.as_ptr(), std::ptr::null()));
         gl_call!(gl::CompileShader(id));
@@ -192,12 +277,9 @@ impl Shader {
                 std::ptr::null_mut(),
                 error.as_ptr() as *mut gl::types::GLchar,
             ));
-            panic!(
-                "shader compilation failed:\n{}",
-                error.to_string_lossy().into_owned()
-            );
+            return Err(ShaderError::Compile(error.to_string_lossy().into_owned()));
         }
-        Shader { id }
+        Ok(shader)
     }
 
     pub fn id(&self) -> u32 {
@@ -0,0 +1,16 @@
+#version 410 core
+
+in vec2 tex_coords;
+
+uniform sampler2D u_screen;
+uniform float u_threshold;
+
+out vec4 out_color;
+
+void main() {
+  vec3 color = texture(u_screen, tex_coords).rgb;
+  // keep the part of the colour above the threshold, without shifting its hue
+  float brightness = max(color.r, max(color.g, color.b));
+  float weight = max(brightness - u_threshold, 0.0) / max(brightness, 0.0001);
+  out_color = vec4(color * weight, 1.0);
+}
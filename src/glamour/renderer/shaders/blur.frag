@@ -0,0 +1,22 @@
+#version 410 core
+
+in vec2 tex_coords;
+
+uniform sampler2D u_screen;
+// one texel along the direction being blurred
+uniform vec2 u_direction;
+
+out vec4 out_color;
+
+// 9-tap gaussian, sampled between texels so linear filtering does half of the work
+const float offsets[3] = float[](0.0, 1.3846153846, 3.2307692308);
+const float weights[3] = float[](0.2270270270, 0.3162162162, 0.0702702703);
+
+void main() {
+  vec3 color = texture(u_screen, tex_coords).rgb * weights[0];
+  for (int i = 1; i < 3; i++) {
+    color += texture(u_screen, tex_coords + u_direction * offsets[i]).rgb * weights[i];
+    color += texture(u_screen, tex_coords - u_direction * offsets[i]).rgb * weights[i];
+  }
+  out_color = vec4(color, 1.0);
+}
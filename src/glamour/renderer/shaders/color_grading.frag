@@ -0,0 +1,19 @@
+#version 410 core
+
+in vec2 tex_coords;
+
+uniform sampler2D u_screen;
+uniform float u_brightness;
+uniform float u_contrast;
+uniform float u_saturation;
+uniform vec3 u_tint;
+
+out vec4 out_color;
+
+void main() {
+  vec3 color = texture(u_screen, tex_coords).rgb;
+  color = (color - 0.5) * u_contrast + 0.5 + u_brightness;
+  float luma = dot(color, vec3(0.2126, 0.7152, 0.0722));
+  color = mix(vec3(luma), color, u_saturation) * u_tint;
+  out_color = vec4(clamp(color, 0.0, 1.0), 1.0);
+}
@@ -0,0 +1,45 @@
+#version 410 core
+
+in vec2 tex_coords;
+
+uniform sampler2D u_screen;
+uniform vec2 u_texel_size;
+
+out vec4 out_color;
+
+#define FXAA_REDUCE_MIN (1.0 / 128.0)
+#define FXAA_REDUCE_MUL (1.0 / 8.0)
+#define FXAA_SPAN_MAX 8.0
+
+float luma(vec3 color) { return dot(color, vec3(0.299, 0.587, 0.114)); }
+
+void main() {
+  vec3 rgb_m = texture(u_screen, tex_coords).rgb;
+  float luma_nw = luma(texture(u_screen, tex_coords + vec2(-1.0, -1.0) * u_texel_size).rgb);
+  float luma_ne = luma(texture(u_screen, tex_coords + vec2(1.0, -1.0) * u_texel_size).rgb);
+  float luma_sw = luma(texture(u_screen, tex_coords + vec2(-1.0, 1.0) * u_texel_size).rgb);
+  float luma_se = luma(texture(u_screen, tex_coords + vec2(1.0, 1.0) * u_texel_size).rgb);
+  float luma_m = luma(rgb_m);
+  float luma_min = min(luma_m, min(min(luma_nw, luma_ne), min(luma_sw, luma_se)));
+  float luma_max = max(luma_m, max(max(luma_nw, luma_ne), max(luma_sw, luma_se)));
+
+  // blur along the edge, perpendicular to the luma gradient
+  vec2 dir = vec2(-((luma_nw + luma_ne) - (luma_sw + luma_se)),
+                  (luma_nw + luma_sw) - (luma_ne + luma_se));
+  float dir_reduce = max((luma_nw + luma_ne + luma_sw + luma_se) * 0.25 * FXAA_REDUCE_MUL,
+                         FXAA_REDUCE_MIN);
+  float dir_scale = 1.0 / (min(abs(dir.x), abs(dir.y)) + dir_reduce);
+  dir = clamp(dir * dir_scale, vec2(-FXAA_SPAN_MAX), vec2(FXAA_SPAN_MAX)) * u_texel_size;
+
+  vec3 rgb_a = 0.5 * (texture(u_screen, tex_coords + dir * (1.0 / 3.0 - 0.5)).rgb +
+                      texture(u_screen, tex_coords + dir * (2.0 / 3.0 - 0.5)).rgb);
+  vec3 rgb_b = rgb_a * 0.5 + 0.25 * (texture(u_screen, tex_coords + dir * -0.5).rgb +
+                                     texture(u_screen, tex_coords + dir * 0.5).rgb);
+  // the wider blur crossed another edge, so fall back to the narrower one
+  float luma_b = luma(rgb_b);
+  if (luma_b < luma_min || luma_b > luma_max) {
+    out_color = vec4(rgb_a, 1.0);
+  } else {
+    out_color = vec4(rgb_b, 1.0);
+  }
+}
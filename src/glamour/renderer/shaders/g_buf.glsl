@@ -0,0 +1,66 @@
+// Reading and writing the G-buffer in either layout, see `GBufLayout`.
+//
+// full:    0 = world position + reflectivity, 1 = normal + shininess,
+//          2 = albedo + specular strength
+// compact: 0 = octahedral normal + shininess + reflectivity, 1 = albedo + specular strength,
+//          and world position is reconstructed from the depth texture
+//
+// `u_tex_norm` and `u_tex_alb_spec` are always bound, `u_tex_pos` only in the full layout
+uniform sampler2D u_tex_pos;
+uniform sampler2D u_tex_norm;
+uniform sampler2D u_tex_alb_spec;
+uniform sampler2D u_tex_depth;
+uniform bool u_compact_g_buf;
+uniform mat4 u_inv_view_projection;
+
+// unit vector to a point on an octahedron, unfolded onto [-1, 1]^2
+vec2 oct_encode(vec3 n) {
+  n /= abs(n.x) + abs(n.y) + abs(n.z);
+  if (n.z < 0.0) {
+    vec2 s = vec2(n.x >= 0.0 ? 1.0 : -1.0, n.y >= 0.0 ? 1.0 : -1.0);
+    n.xy = (1.0 - abs(n.yx)) * s;
+  }
+  return n.xy;
+}
+
+vec3 oct_decode(vec2 e) {
+  vec3 n = vec3(e, 1.0 - abs(e.x) - abs(e.y));
+  if (n.z < 0.0) {
+    vec2 s = vec2(n.x >= 0.0 ? 1.0 : -1.0, n.y >= 0.0 ? 1.0 : -1.0);
+    n.xy = (1.0 - abs(n.yx)) * s;
+  }
+  return normalize(n);
+}
+
+vec3 g_buf_position(vec2 tex_coords) {
+  if (!u_compact_g_buf) {
+    return texture(u_tex_pos, tex_coords).xyz;
+  }
+  float depth = texture(u_tex_depth, tex_coords).r;
+  vec4 ndc = vec4(tex_coords, depth, 1.0) * 2.0 - 1.0;
+  vec4 world = u_inv_view_projection * ndc;
+  return world.xyz / world.w;
+}
+
+// empty texels have a zero normal and shininess in both layouts
+vec4 g_buf_normal_shininess(vec2 tex_coords) {
+  if (!u_compact_g_buf) {
+    return texture(u_tex_norm, tex_coords);
+  }
+  if (texture(u_tex_depth, tex_coords).r >= 1.0) {
+    return vec4(0.0);
+  }
+  vec4 encoded = texture(u_tex_norm, tex_coords);
+  return vec4(oct_decode(encoded.xy), encoded.z);
+}
+
+// empty texels reflect nothing in both layouts
+float g_buf_reflectivity(vec2 tex_coords) {
+  if (g_buf_normal_shininess(tex_coords).xyz == vec3(0.0)) {
+    return 0.0;
+  }
+  if (u_compact_g_buf) {
+    return texture(u_tex_norm, tex_coords).w;
+  }
+  return texture(u_tex_pos, tex_coords).w;
+}
@@ -0,0 +1,261 @@
+// shared lighting functions, included by the lit shaders
+
+#define LIGHT_TYPE_POINT 0
+#define LIGHT_TYPE_SPOT 1
+
+const float AMBIENT_STRENGTH = 0.05;
+// scales the ambient light of every light, shaders with ambient occlusion set it before
+// calculating lighting
+float ambient_occlusion = 1.0;
+
+struct PointLight {
+  vec3 position;
+  float radius;
+  vec3 color;
+  float intensity;
+  vec3 attenuation; // constant, linear, quadratic
+  int shadow_index; // cube in u_point_shadows, or negative for no shadows
+};
+// three RGBA32F texels per light:
+// [position.xyz, radius], [color.rgb, intensity], [attenuation.xyz, shadow_index]
+uniform samplerBuffer u_point_lights;
+uniform int u_point_lights_count;
+
+struct SpotLight {
+  vec3 position;
+  float radius;
+  vec3 color;
+  float intensity;
+  vec3 attenuation; // constant, linear, quadratic
+  float cos_inner;
+  vec3 direction;
+  float cos_outer;
+};
+// four RGBA32F texels per light:
+// [position.xyz, radius], [color.rgb, intensity], [attenuation.xyz, cos_inner],
+// [direction.xyz, cos_outer]
+uniform samplerBuffer u_spot_lights;
+uniform int u_spot_lights_count;
+
+struct DirectionalLight {
+  vec3 direction;
+  vec3 color;
+  float intensity;
+  int shadow_index; // negative for no shadows, there's only one shadow map
+};
+// two RGBA32F texels per light: [direction.xyz, shadow_index], [color.rgb, intensity]
+uniform samplerBuffer u_directional_lights;
+uniform int u_directional_lights_count;
+
+// orthographic depth map of the shadow casting directional light
+uniform sampler2D u_directional_shadow;
+uniform mat4 u_directional_shadow_matrix;
+// distance to each shadow casting point light, divided by the light's radius
+uniform samplerCubeArray u_point_shadows;
+// world space distance to push fragments along their normal before looking up shadows
+uniform float u_shadow_bias;
+// radius of the PCF kernel, 0 for a single sample
+uniform int u_shadow_pcf;
+
+// the environment surfaces reflect, if there is one
+uniform samplerCube u_environment;
+uniform bool u_use_environment;
+
+PointLight get_point_light(int index) {
+  vec4 position_radius = texelFetch(u_point_lights, index * 3);
+  vec4 color_intensity = texelFetch(u_point_lights, index * 3 + 1);
+  PointLight light;
+  light.position = position_radius.xyz;
+  light.radius = position_radius.w;
+  light.color = color_intensity.rgb;
+  light.intensity = color_intensity.a;
+  vec4 attenuation_shadow = texelFetch(u_point_lights, index * 3 + 2);
+  light.attenuation = attenuation_shadow.xyz;
+  light.shadow_index = int(attenuation_shadow.w);
+  return light;
+}
+
+SpotLight get_spot_light(int index) {
+  vec4 position_radius = texelFetch(u_spot_lights, index * 4);
+  vec4 color_intensity = texelFetch(u_spot_lights, index * 4 + 1);
+  vec4 attenuation_inner = texelFetch(u_spot_lights, index * 4 + 2);
+  vec4 direction_outer = texelFetch(u_spot_lights, index * 4 + 3);
+  SpotLight light;
+  light.position = position_radius.xyz;
+  light.radius = position_radius.w;
+  light.color = color_intensity.rgb;
+  light.intensity = color_intensity.a;
+  light.attenuation = attenuation_inner.xyz;
+  light.cos_inner = attenuation_inner.w;
+  light.direction = direction_outer.xyz;
+  light.cos_outer = direction_outer.w;
+  return light;
+}
+
+DirectionalLight get_directional_light(int index) {
+  vec4 direction_shadow = texelFetch(u_directional_lights, index * 2);
+  vec4 color_intensity = texelFetch(u_directional_lights, index * 2 + 1);
+  DirectionalLight light;
+  light.direction = direction_shadow.xyz;
+  light.shadow_index = int(direction_shadow.w);
+  light.color = color_intensity.rgb;
+  light.intensity = color_intensity.a;
+  return light;
+}
+
+// fraction of the fragment in shadow, from 0 (lit) to 1 (shadowed)
+float calc_directional_shadow(vec3 norm, vec3 frag_pos) {
+  vec4 light_space =
+      u_directional_shadow_matrix * vec4(frag_pos + norm * u_shadow_bias, 1.0);
+  vec3 coords = light_space.xyz / light_space.w * 0.5 + 0.5;
+  // beyond the far plane of the shadow map
+  if (coords.z > 1.0) {
+    return 0.0;
+  }
+  vec2 texel_size = 1.0 / vec2(textureSize(u_directional_shadow, 0));
+  float shadow = 0.0;
+  for (int x = -u_shadow_pcf; x <= u_shadow_pcf; x++) {
+    for (int y = -u_shadow_pcf; y <= u_shadow_pcf; y++) {
+      float closest =
+          texture(u_directional_shadow, coords.xy + vec2(x, y) * texel_size).r;
+      shadow += coords.z > closest ? 1.0 : 0.0;
+    }
+  }
+  float kernel_width = float(u_shadow_pcf * 2 + 1);
+  return shadow / (kernel_width * kernel_width);
+}
+
+const vec3 POINT_SHADOW_OFFSETS[20] = vec3[](
+    vec3(1, 1, 1), vec3(1, -1, 1), vec3(-1, -1, 1), vec3(-1, 1, 1),
+    vec3(1, 1, -1), vec3(1, -1, -1), vec3(-1, -1, -1), vec3(-1, 1, -1),
+    vec3(1, 1, 0), vec3(1, -1, 0), vec3(-1, -1, 0), vec3(-1, 1, 0),
+    vec3(1, 0, 1), vec3(-1, 0, 1), vec3(1, 0, -1), vec3(-1, 0, -1),
+    vec3(0, 1, 1), vec3(0, -1, 1), vec3(0, -1, -1), vec3(0, 1, -1));
+
+// fraction of the fragment in shadow, from 0 (lit) to 1 (shadowed)
+float calc_point_shadow(PointLight light, vec3 norm, vec3 frag_pos) {
+  vec3 light_to_frag = frag_pos + norm * u_shadow_bias - light.position;
+  float current = length(light_to_frag) / light.radius;
+  if (u_shadow_pcf == 0) {
+    float closest =
+        texture(u_point_shadows, vec4(light_to_frag, light.shadow_index)).r;
+    return current > closest ? 1.0 : 0.0;
+  }
+  // sample around the direction instead of a grid, a grid wastes samples on a cube map
+  float disk_radius = 0.005 * float(u_shadow_pcf) * length(light_to_frag);
+  float shadow = 0.0;
+  for (int i = 0; i < 20; i++) {
+    vec3 dir = light_to_frag + POINT_SHADOW_OFFSETS[i] * disk_radius;
+    float closest = texture(u_point_shadows, vec4(dir, light.shadow_index)).r;
+    shadow += current > closest ? 1.0 : 0.0;
+  }
+  return shadow / 20.0;
+}
+
+// light_dir points from the fragment towards the light
+vec3 calc_diffuse_specular(vec3 light_dir, vec3 light_color, vec3 norm,
+                           vec3 view_dir, float specular_strength,
+                           float shininess) {
+  float diffuse = max(dot(norm, light_dir), 0.0);
+  vec3 diffuse_light = diffuse * light_color;
+
+  vec3 halfway_dir = normalize(light_dir + view_dir);
+  float specular = pow(max(dot(norm, halfway_dir), 0.0), shininess);
+  vec3 specular_light = specular_strength * specular * light_color;
+
+  return diffuse_light + specular_light;
+}
+
+float calc_attenuation(vec3 attenuation, float dist) {
+  return 1.0 / (attenuation.x + attenuation.y * dist +
+                attenuation.z * (dist * dist));
+}
+
+vec3 calc_point_light(PointLight light, vec3 norm, vec3 frag_pos, vec3 view_dir,
+                      float specular_strength, float shininess) {
+  vec3 light_color = light.color * light.intensity;
+  vec3 ambient_light = AMBIENT_STRENGTH * ambient_occlusion * light_color;
+
+  vec3 light_dir = normalize(light.position - frag_pos);
+  vec3 diffuse_specular = calc_diffuse_specular(
+      light_dir, light_color, norm, view_dir, specular_strength, shininess);
+
+  float dist = length(light.position - frag_pos);
+  float attenuation = calc_attenuation(light.attenuation, dist);
+
+  if (light.shadow_index >= 0) {
+    diffuse_specular *= 1.0 - calc_point_shadow(light, norm, frag_pos);
+  }
+
+  return (ambient_light + diffuse_specular) * attenuation;
+}
+
+vec3 calc_spot_light(SpotLight light, vec3 norm, vec3 frag_pos, vec3 view_dir,
+                     float specular_strength, float shininess) {
+  vec3 light_color = light.color * light.intensity;
+  vec3 ambient_light = AMBIENT_STRENGTH * ambient_occlusion * light_color;
+
+  vec3 light_dir = normalize(light.position - frag_pos);
+  vec3 diffuse_specular = calc_diffuse_specular(
+      light_dir, light_color, norm, view_dir, specular_strength, shininess);
+
+  // smooth falloff from the inner to the outer cone
+  float theta = dot(light_dir, normalize(-light.direction));
+  float cone = clamp((theta - light.cos_outer) /
+                         max(light.cos_inner - light.cos_outer, 1e-4),
+                     0.0, 1.0);
+
+  float dist = length(light.position - frag_pos);
+  float attenuation = calc_attenuation(light.attenuation, dist);
+
+  return (ambient_light + diffuse_specular * cone) * attenuation;
+}
+
+vec3 calc_directional_light(DirectionalLight light, vec3 norm, vec3 frag_pos,
+                            vec3 view_dir, float specular_strength,
+                            float shininess) {
+  vec3 light_color = light.color * light.intensity;
+  vec3 ambient_light = AMBIENT_STRENGTH * ambient_occlusion * light_color;
+
+  vec3 light_dir = normalize(-light.direction);
+  vec3 diffuse_specular = calc_diffuse_specular(
+      light_dir, light_color, norm, view_dir, specular_strength, shininess);
+
+  if (light.shadow_index >= 0) {
+    diffuse_specular *= 1.0 - calc_directional_shadow(norm, frag_pos);
+  }
+
+  return ambient_light + diffuse_specular;
+}
+
+// every light in the light buffers, for shading a single fragment
+vec3 calc_lights(vec3 norm, vec3 frag_pos, vec3 view_dir,
+                 float specular_strength, float shininess) {
+  vec3 lighting = vec3(0.0, 0.0, 0.0);
+  for (int i = 0; i < u_directional_lights_count; i++) {
+    lighting += calc_directional_light(get_directional_light(i), norm, frag_pos,
+                                       view_dir, specular_strength, shininess);
+  }
+  for (int i = 0; i < u_point_lights_count; i++) {
+    lighting += calc_point_light(get_point_light(i), norm, frag_pos, view_dir,
+                                 specular_strength, shininess);
+  }
+  for (int i = 0; i < u_spot_lights_count; i++) {
+    lighting += calc_spot_light(get_spot_light(i), norm, frag_pos, view_dir,
+                                specular_strength, shininess);
+  }
+  return lighting;
+}
+
+// blend a lit colour with the environment reflected about the normal, by how reflective the
+// surface is. it's linear in `color`, so additively blended passes can each scale their own
+// lighting by `1 - reflectivity` and add the reflection once.
+vec3 reflect_environment(vec3 color, vec3 norm, vec3 view_dir, float reflectivity) {
+  if (!u_use_environment) {
+    return color;
+  }
+  // the base level, since the deferred path's derivatives jump across every edge in the
+  // G-buffer and would pick tiny mips there
+  vec3 reflection = textureLod(u_environment, reflect(-view_dir, norm), 0.0).rgb;
+  return mix(color, reflection, reflectivity);
+}
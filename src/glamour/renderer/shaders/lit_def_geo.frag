@@ -1,22 +1,61 @@
 #version 410 core
 
-layout(location = 0) out vec3 out_pos;
-layout(location = 1) out vec3 out_norm;
-layout(location = 2) out vec4 out_alb_spec;
+// see g_buf.glsl for what each attachment holds in either layout, the compact layout only
+// has the first two
+layout(location = 0) out vec4 out_g_buf0;
+layout(location = 1) out vec4 out_g_buf1;
+layout(location = 2) out vec4 out_g_buf2;
 
 in vec3 frag_pos;
 in vec3 normal;
 in vec2 tex_coords;
+flat in float layer;
+in vec3 tangent;
+in vec3 bitangent;
 
-uniform vec4 u_color;
-uniform sampler2D u_tex;
+// material
+uniform vec4 u_albedo_color;
+uniform sampler2D u_albedo_tex;
+// used instead of u_albedo_tex if set, with the instance's layer
+uniform sampler2DArray u_albedo_array;
+uniform bool u_use_albedo_array;
+uniform float u_specular_strength;
+uniform sampler2D u_specular_tex;
+uniform float u_shininess;
+uniform float u_reflectivity;
+// tangent space normals
+uniform sampler2D u_normal_tex;
+uniform bool u_use_normal_tex;
 
-void main() {
-  out_pos = frag_pos;
-  out_norm = normalize(normal);
-  out_alb_spec.rgb = (texture(u_tex, tex_coords) * u_color).rgb;
-  // specular is hard coded atm
-  out_alb_spec.a = 0.5;
+vec4 albedo_tex_color() {
+  if (u_use_albedo_array) {
+    return texture(u_albedo_array, vec3(tex_coords, layer));
+  }
+  return texture(u_albedo_tex, tex_coords);
+}
 
-  //   out_pos = out_alb_spec.rgb;
+vec3 surface_normal() {
+  vec3 n = normalize(normal);
+  if (!u_use_normal_tex) {
+    return n;
+  }
+  // re-orthogonalize, since interpolation skews the tangent space
+  vec3 t = normalize(tangent - n * dot(n, tangent));
+  vec3 b = cross(n, t) * sign(dot(cross(n, t), bitangent));
+  vec3 tex_normal = texture(u_normal_tex, tex_coords).rgb * 2.0 - 1.0;
+  return normalize(mat3(t, b, n) * tex_normal);
+}
+
+void main() {
+  vec4 alb_spec;
+  alb_spec.rgb = (albedo_tex_color() * u_albedo_color).rgb;
+  alb_spec.a = u_specular_strength * texture(u_specular_tex, tex_coords).r;
+  if (u_compact_g_buf) {
+    out_g_buf0 = vec4(oct_encode(surface_normal()), u_shininess, u_reflectivity);
+    out_g_buf1 = alb_spec;
+  } else {
+    out_g_buf0 = vec4(frag_pos, u_reflectivity);
+    out_g_buf1 = vec4(surface_normal(), u_shininess);
+    out_g_buf2 = alb_spec;
+  }
 }
@@ -5,10 +5,16 @@ layout(location = 1) in vec3 in_normal;
 layout(location = 2) in vec2 in_tex_coords;
 layout(location = 3) in mat4 in_model_mat;
 layout(location = 7) in mat3 in_norm_mat;
+layout(location = 10) in float in_layer;
+layout(location = 11) in vec3 in_tangent;
+layout(location = 12) in vec3 in_bitangent;
 
 out vec3 frag_pos;
 out vec3 normal;
 out vec2 tex_coords;
+flat out float layer;
+out vec3 tangent;
+out vec3 bitangent;
 
 uniform mat4 u_view_projection;
 
@@ -18,4 +24,8 @@ void main() {
   gl_Position = u_view_projection * model;
   normal = in_norm_mat * in_normal;
   tex_coords = in_tex_coords;
+  layer = in_layer;
+  // tangents follow the surface, so they aren't transformed like normals
+  tangent = mat3(in_model_mat) * in_tangent;
+  bitangent = mat3(in_model_mat) * in_bitangent;
 }
@@ -0,0 +1,61 @@
+#version 410 core
+
+flat in int light_index;
+
+// blurred ambient occlusion in the red channel
+uniform sampler2D u_ssao;
+uniform bool u_use_ssao;
+
+uniform vec3 u_view_pos;
+uniform int u_light_type;
+
+out vec4 out_color;
+
+void main() {
+  vec2 tex_coords = gl_FragCoord.xy / vec2(textureSize(u_tex_alb_spec, 0));
+  vec3 frag_pos = g_buf_position(tex_coords);
+
+  // the volume is only an approximation of the light's sphere of influence
+  // (spot lights use the same sphere, their cone is always inside of it)
+  PointLight point_light;
+  SpotLight spot_light;
+  vec3 light_pos;
+  float light_radius;
+  if (u_light_type == LIGHT_TYPE_SPOT) {
+    spot_light = get_spot_light(light_index);
+    light_pos = spot_light.position;
+    light_radius = spot_light.radius;
+  } else {
+    point_light = get_point_light(light_index);
+    light_pos = point_light.position;
+    light_radius = point_light.radius;
+  }
+  if (length(light_pos - frag_pos) > light_radius) {
+    discard;
+  }
+
+  vec4 norm_shininess = g_buf_normal_shininess(tex_coords);
+  vec3 norm = norm_shininess.xyz;
+  // empty texels are cleared to 0, which pow() isn't defined for
+  float shininess = max(norm_shininess.w, 1.0);
+  vec4 alb_spec = texture(u_tex_alb_spec, tex_coords);
+  vec4 albedo = vec4(alb_spec.rgb, 1.0);
+  float specular_strength = alb_spec.a;
+
+  vec3 view_dir = normalize(u_view_pos - frag_pos);
+  if (u_use_ssao) {
+    ambient_occlusion = texture(u_ssao, tex_coords).r;
+  }
+  vec3 lighting = u_light_type == LIGHT_TYPE_SPOT
+                      ? calc_spot_light(spot_light, norm, frag_pos, view_dir,
+                                        specular_strength, shininess)
+                      : calc_point_light(point_light, norm, frag_pos, view_dir,
+                                         specular_strength, shininess);
+
+  if (u_use_environment) {
+    // the full-screen pass adds the reflection in its place
+    lighting *= 1.0 - g_buf_reflectivity(tex_coords);
+  }
+  // additively blended with every other light volume covering this fragment
+  out_color = vec4(lighting, 1.0) * albedo;
+}
@@ -0,0 +1,23 @@
+#version 410 core
+
+#define LIGHT_TYPE_POINT 0
+#define LIGHT_TYPE_SPOT 1
+
+layout(location = 0) in vec3 in_pos;
+
+// one instance per light, its position and radius come from the light buffer
+uniform samplerBuffer u_point_lights;
+uniform samplerBuffer u_spot_lights;
+uniform int u_light_type;
+uniform mat4 u_view_projection;
+
+flat out int light_index;
+
+void main() {
+  // [position.xyz, radius] is the first of each light's texels
+  vec4 light = u_light_type == LIGHT_TYPE_SPOT
+                   ? texelFetch(u_spot_lights, gl_InstanceID * 4)
+                   : texelFetch(u_point_lights, gl_InstanceID * 3);
+  gl_Position = u_view_projection * vec4(light.xyz + in_pos * light.w, 1.0);
+  light_index = gl_InstanceID;
+}
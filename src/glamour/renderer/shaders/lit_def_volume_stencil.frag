@@ -0,0 +1,4 @@
+#version 410 core
+
+// the stencil pass only cares about depth test results, nothing is written
+void main() {}
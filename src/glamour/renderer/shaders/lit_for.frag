@@ -3,61 +3,60 @@
 in vec3 frag_pos;
 in vec3 normal;
 in vec2 tex_coords;
+flat in float layer;
+in vec3 tangent;
+in vec3 bitangent;
 
-uniform vec4 u_color;
 uniform vec3 u_view_pos;
-uniform sampler2D u_tex;
 
-struct PointLight {
-  vec3 position;
-};
-#define NR_POINT_LIGHTS 1019
-uniform PointLight u_point_lights[NR_POINT_LIGHTS];
-uniform int u_point_lights_count;
+// material
+uniform vec4 u_albedo_color;
+uniform sampler2D u_albedo_tex;
+// used instead of u_albedo_tex if set, with the instance's layer
+uniform sampler2DArray u_albedo_array;
+uniform bool u_use_albedo_array;
+uniform float u_specular_strength;
+uniform sampler2D u_specular_tex;
+uniform float u_shininess;
+// how much of the environment is reflected, 0 to 1
+uniform float u_reflectivity;
+// tangent space normals
+uniform sampler2D u_normal_tex;
+uniform bool u_use_normal_tex;
 
 out vec4 out_color;
 
-vec3 calc_point_light(PointLight light, vec3 normal, vec3 frag_pos,
-                      vec3 view_dir);
-
-void main() {
-  vec3 norm = normalize(normal);
-  vec3 view_dir = normalize(u_view_pos - frag_pos);
-
-  vec3 lighting = vec3(0.0, 0.0, 0.0);
-  for (int i = 0; i < u_point_lights_count; i++) {
-    lighting += calc_point_light(u_point_lights[i], norm, frag_pos, view_dir);
+vec4 albedo_tex_color() {
+  if (u_use_albedo_array) {
+    return texture(u_albedo_array, vec3(tex_coords, layer));
   }
-
-  vec4 albedo = texture(u_tex, tex_coords) * u_color;
-
-  out_color = vec4(lighting, 1.0) * albedo;
+  return texture(u_albedo_tex, tex_coords);
 }
 
-vec3 calc_point_light(PointLight light, vec3 norm, vec3 frag_pos,
-                      vec3 view_dir) {
-  vec3 light_color = vec3(1.0, 1.0, 1.0);
-
-  float ambient_strength = 0.05;
-  vec3 ambient_light = ambient_strength * light_color;
-
-  vec3 light_dir = normalize(light.position - frag_pos);
+vec3 surface_normal() {
+  vec3 n = normalize(normal);
+  if (!u_use_normal_tex) {
+    return n;
+  }
+  // re-orthogonalize, since interpolation skews the tangent space
+  vec3 t = normalize(tangent - n * dot(n, tangent));
+  vec3 b = cross(n, t) * sign(dot(cross(n, t), bitangent));
+  vec3 tex_normal = texture(u_normal_tex, tex_coords).rgb * 2.0 - 1.0;
+  return normalize(mat3(t, b, n) * tex_normal);
+}
 
-  float diffuse = max(dot(norm, light_dir), 0.0);
-  vec3 diffuse_light = diffuse * light_color;
+void main() {
+  vec3 norm = surface_normal();
+  vec3 view_dir = normalize(u_view_pos - frag_pos);
 
-  float specular_strength = 0.5;
-  float shininess = 256.0;
-  vec3 halfway_dir = normalize(light_dir + view_dir);
-  float specular = pow(max(dot(norm, halfway_dir), 0.0), shininess);
-  vec3 specular_light = specular_strength * specular * light_color;
+  float specular_strength =
+      u_specular_strength * texture(u_specular_tex, tex_coords).r;
+  vec3 lighting =
+      calc_lights(norm, frag_pos, view_dir, specular_strength, u_shininess);
 
-  float dist = length(light.position - frag_pos);
-  float atten_constant = 1.0;
-  float atten_linear = 0.14;
-  float atten_quadratic = 0.07;
-  float attenuation = 1.0 / (atten_constant + atten_linear * dist +
-                             atten_quadratic * (dist * dist));
+  vec4 albedo = albedo_tex_color() * u_albedo_color;
 
-  return (ambient_light + diffuse_light + specular_light) * attenuation;
+  vec3 color = reflect_environment(lighting * albedo.rgb, norm, view_dir,
+                                  u_reflectivity);
+  out_color = vec4(color, albedo.a);
 }
@@ -0,0 +1,11 @@
+#version 410 core
+
+in vec3 frag_pos;
+
+uniform vec3 u_light_pos;
+uniform float u_light_radius;
+
+void main() {
+  // linear distance, so it can be compared in world space without the face's projection
+  gl_FragDepth = length(frag_pos - u_light_pos) / u_light_radius;
+}
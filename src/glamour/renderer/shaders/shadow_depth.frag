@@ -0,0 +1,4 @@
+#version 410 core
+
+// only depth is written
+void main() {}
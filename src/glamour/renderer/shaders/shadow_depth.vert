@@ -0,0 +1,14 @@
+#version 410 core
+
+layout(location = 0) in vec3 in_pos;
+layout(location = 3) in mat4 in_model_mat;
+
+uniform mat4 u_light_space;
+
+out vec3 frag_pos;
+
+void main() {
+  vec4 model = in_model_mat * vec4(in_pos, 1.0);
+  frag_pos = model.xyz;
+  gl_Position = u_light_space * model;
+}
@@ -0,0 +1,16 @@
+#version 410 core
+
+in vec2 ndc;
+
+uniform mat4 u_inv_view_projection;
+uniform samplerCube u_environment;
+
+out vec4 out_color;
+
+void main() {
+  // the view ray through this pixel, from the near plane to the far plane
+  vec4 near = u_inv_view_projection * vec4(ndc, -1.0, 1.0);
+  vec4 far = u_inv_view_projection * vec4(ndc, 1.0, 1.0);
+  vec3 direction = far.xyz / far.w - near.xyz / near.w;
+  out_color = vec4(texture(u_environment, direction).rgb, 1.0);
+}
@@ -0,0 +1,11 @@
+#version 410 core
+
+layout(location = 0) in vec3 in_pos;
+
+out vec2 ndc;
+
+void main() {
+  // on the far plane, so it's only drawn where nothing else was
+  gl_Position = vec4(in_pos.xy, 1.0, 1.0);
+  ndc = in_pos.xy;
+}
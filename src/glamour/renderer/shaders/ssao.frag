@@ -0,0 +1,65 @@
+#version 410 core
+
+#define MAX_KERNEL_SIZE 64
+
+in vec2 tex_coords;
+
+// random unit vectors, tiled across the screen to rotate the kernel per pixel
+uniform sampler2D u_noise;
+
+// offsets in a unit hemisphere around +z, denser towards the centre
+uniform vec3 u_kernel[MAX_KERNEL_SIZE];
+uniform int u_kernel_size;
+uniform float u_radius;
+uniform float u_bias;
+
+uniform mat4 u_view;
+uniform mat4 u_view_projection;
+
+out float out_occlusion;
+
+float view_depth(vec3 world_pos) { return -(u_view * vec4(world_pos, 1.0)).z; }
+
+// empty texels are cleared to 0, so have no normal
+bool is_empty(vec3 norm) { return dot(norm, norm) < 0.5; }
+
+void main() {
+  vec3 norm = g_buf_normal_shininess(tex_coords).xyz;
+  if (is_empty(norm)) {
+    out_occlusion = 1.0;
+    return;
+  }
+  norm = normalize(norm);
+  vec3 frag_pos = g_buf_position(tex_coords);
+
+  // orient the kernel's hemisphere around the normal, with a random rotation
+  vec2 noise_scale =
+      vec2(textureSize(u_tex_alb_spec, 0)) / vec2(textureSize(u_noise, 0));
+  vec3 random = texture(u_noise, tex_coords * noise_scale).xyz;
+  vec3 tangent = random - norm * dot(random, norm);
+  if (dot(tangent, tangent) < 1e-4) {
+    tangent = abs(norm.x) < 0.9 ? vec3(1.0, 0.0, 0.0) : vec3(0.0, 1.0, 0.0);
+    tangent -= norm * dot(tangent, norm);
+  }
+  tangent = normalize(tangent);
+  mat3 tbn = mat3(tangent, cross(norm, tangent), norm);
+
+  float frag_depth = view_depth(frag_pos);
+  float occlusion = 0.0;
+  for (int i = 0; i < u_kernel_size; i++) {
+    vec3 sample_pos = frag_pos + tbn * u_kernel[i] * u_radius;
+    vec4 clip = u_view_projection * vec4(sample_pos, 1.0);
+    vec2 sample_coords = clip.xy / clip.w * 0.5 + 0.5;
+
+    vec3 scene_norm = g_buf_normal_shininess(sample_coords).xyz;
+    if (is_empty(scene_norm)) {
+      continue;
+    }
+    float scene_depth = view_depth(g_buf_position(sample_coords));
+    // surfaces much closer to the camera than the fragment don't occlude it
+    float range = smoothstep(0.0, 1.0, u_radius / abs(frag_depth - scene_depth));
+    occlusion +=
+        (scene_depth <= view_depth(sample_pos) - u_bias ? 1.0 : 0.0) * range;
+  }
+  out_occlusion = 1.0 - occlusion / float(u_kernel_size);
+}
@@ -0,0 +1,22 @@
+#version 410 core
+
+in vec2 tex_coords;
+
+uniform sampler2D u_ssao;
+// the size of the noise texture, so its tiling pattern is averaged out
+uniform int u_blur_size;
+
+out float out_occlusion;
+
+void main() {
+  vec2 texel_size = 1.0 / vec2(textureSize(u_ssao, 0));
+  float offset = float(u_blur_size / 2);
+  float occlusion = 0.0;
+  for (int x = 0; x < u_blur_size; x++) {
+    for (int y = 0; y < u_blur_size; y++) {
+      vec2 coords = tex_coords + (vec2(x, y) - offset) * texel_size;
+      occlusion += texture(u_ssao, coords).r;
+    }
+  }
+  out_occlusion = occlusion / float(u_blur_size * u_blur_size);
+}
@@ -0,0 +1,52 @@
+#version 410 core
+
+#define TONE_MAP_NONE 0
+#define TONE_MAP_REINHARD 1
+#define TONE_MAP_ACES 2
+#define TONE_MAP_EXPOSURE 3
+
+in vec2 tex_coords;
+
+uniform sampler2D u_hdr;
+// blurred bright parts of `u_hdr`, zero intensity without bloom
+uniform sampler2D u_bloom;
+uniform float u_bloom_intensity;
+uniform int u_tone_map;
+uniform float u_exposure;
+uniform bool u_srgb;
+
+out vec4 out_color;
+
+// Krzysztof Narkowicz's fit of the ACES reference rendering transform
+vec3 aces(vec3 c) {
+  return (c * (2.51 * c + 0.03)) / (c * (2.43 * c + 0.59) + 0.14);
+}
+
+vec3 linear_to_srgb(vec3 c) {
+  vec3 low = c * 12.92;
+  vec3 high = 1.055 * pow(c, vec3(1.0 / 2.4)) - 0.055;
+  return mix(low, high, step(vec3(0.0031308), c));
+}
+
+void main() {
+  vec3 hdr = texture(u_hdr, tex_coords).rgb;
+  if (u_bloom_intensity > 0.0) {
+    hdr += texture(u_bloom, tex_coords).rgb * u_bloom_intensity;
+  }
+  hdr *= u_exposure;
+  vec3 ldr;
+  if (u_tone_map == TONE_MAP_REINHARD) {
+    ldr = hdr / (1.0 + hdr);
+  } else if (u_tone_map == TONE_MAP_ACES) {
+    ldr = aces(hdr);
+  } else if (u_tone_map == TONE_MAP_EXPOSURE) {
+    ldr = 1.0 - exp(-hdr);
+  } else {
+    ldr = hdr;
+  }
+  ldr = clamp(ldr, 0.0, 1.0);
+  if (u_srgb) {
+    ldr = linear_to_srgb(ldr);
+  }
+  out_color = vec4(ldr, 1.0);
+}
@@ -1,7 +1,9 @@
 #version 410 core
 
+flat in vec3 light_color;
+
 uniform vec4 u_color;
 
 out vec4 out_color;
 
-void main() { out_color = u_color; }
+void main() { out_color = vec4(light_color, 1.0) * u_color; }
@@ -4,7 +4,13 @@ layout(location = 0) in vec3 in_pos;
 layout(location = 3) in mat4 in_model_mat;
 
 uniform mat4 u_view_projection;
+// instances are drawn in the same order as the lights in the light buffer
+uniform samplerBuffer u_point_lights;
+
+flat out vec3 light_color;
 
 void main() {
   gl_Position = u_view_projection * in_model_mat * vec4(in_pos, 1.0);
+  // [color.rgb, intensity] is the second of each light's three texels
+  light_color = texelFetch(u_point_lights, gl_InstanceID * 3 + 1).rgb;
 }
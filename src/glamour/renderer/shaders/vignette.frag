@@ -0,0 +1,18 @@
+#version 410 core
+
+in vec2 tex_coords;
+
+uniform sampler2D u_screen;
+uniform float u_intensity;
+uniform float u_radius;
+uniform float u_softness;
+
+out vec4 out_color;
+
+void main() {
+  vec3 color = texture(u_screen, tex_coords).rgb;
+  // 0 at the centre, 1 in the corners
+  float dist = length(tex_coords - 0.5) * sqrt(2.0);
+  float vignette = smoothstep(u_radius, u_radius - u_softness, dist);
+  out_color = vec4(color * mix(1.0, vignette, u_intensity), 1.0);
+}
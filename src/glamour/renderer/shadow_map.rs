@@ -0,0 +1,267 @@
+use crate::glm;
+
+/// A depth-only framebuffer with a single 2D depth texture, for a directional light's
+/// orthographic shadow map.
+pub struct ShadowMap {
+    id: u32,
+    depth_id: u32,
+    size: u32,
+}
+
+impl ShadowMap {
+    pub fn new(size: u32) -> Self {
+        let mut id: u32 = 0;
+        gl_call!(gl::GenFramebuffers(1, &mut id));
+        let mut depth_id: u32 = 0;
+        gl_call!(gl::GenTextures(1, &mut depth_id));
+        let shadow_map = ShadowMap { id, depth_id, size };
+        shadow_map.specify_texture();
+
+        gl_call!(gl::BindFramebuffer(gl::FRAMEBUFFER, id));
+        gl_call!(gl::FramebufferTexture2D(
+            gl::FRAMEBUFFER,
+            gl::DEPTH_ATTACHMENT,
+            gl::TEXTURE_2D,
+            depth_id,
+            0
+        ));
+        // no colour attachments
+        gl_call!(gl::DrawBuffer(gl::NONE));
+        gl_call!(gl::ReadBuffer(gl::NONE));
+        if gl_call!(gl::CheckFramebufferStatus(gl::FRAMEBUFFER) != gl::FRAMEBUFFER_COMPLETE) {
+            panic!("shadow map framebuffer not complete!");
+        }
+        gl_call!(gl::BindFramebuffer(gl::FRAMEBUFFER, 0));
+        shadow_map
+    }
+
+    fn specify_texture(&self) {
+        gl_call!(gl::BindTexture(gl::TEXTURE_2D, self.depth_id));
+        gl_call!(gl::TexImage2D(
+            gl::TEXTURE_2D,
+            0,
+            gl::DEPTH_COMPONENT32F as i32,
+            self.size as i32,
+            self.size as i32,
+            0,
+            gl::DEPTH_COMPONENT,
+            gl::FLOAT,
+            std::ptr::null()
+        ));
+        // compared manually in the shaders, so no filtering
+        gl_call!(gl::TexParameteri(
+            gl::TEXTURE_2D,
+            gl::TEXTURE_MIN_FILTER,
+            gl::NEAREST as i32
+        ));
+        gl_call!(gl::TexParameteri(
+            gl::TEXTURE_2D,
+            gl::TEXTURE_MAG_FILTER,
+            gl::NEAREST as i32
+        ));
+        // anything outside of the map is at the far plane, i.e. not in shadow
+        gl_call!(gl::TexParameteri(
+            gl::TEXTURE_2D,
+            gl::TEXTURE_WRAP_S,
+            gl::CLAMP_TO_BORDER as i32
+        ));
+        gl_call!(gl::TexParameteri(
+            gl::TEXTURE_2D,
+            gl::TEXTURE_WRAP_T,
+            gl::CLAMP_TO_BORDER as i32
+        ));
+        let border = [1.0f32, 1.0, 1.0, 1.0];
+        gl_call!(gl::TexParameterfv(
+            gl::TEXTURE_2D,
+            gl::TEXTURE_BORDER_COLOR,
+            border.as_ptr()
+        ));
+        gl_call!(gl::BindTexture(gl::TEXTURE_2D, 0));
+    }
+
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    pub fn resize(&mut self, size: u32) {
+        self.size = size;
+        self.specify_texture();
+    }
+
+    /// Bind the framebuffer and set the viewport to cover the whole map.
+    pub fn bind(&self) {
+        gl_call!(gl::BindFramebuffer(gl::FRAMEBUFFER, self.id));
+        gl_call!(gl::Viewport(0, 0, self.size as i32, self.size as i32));
+    }
+
+    /// Bind the default framebuffer, the viewport is left for the caller to restore.
+    pub fn unbind(&self) {
+        gl_call!(gl::BindFramebuffer(gl::FRAMEBUFFER, 0));
+    }
+
+    /// Bind the depth texture to texture unit `unit`, e.g. `1` for `GL_TEXTURE1`.
+    pub fn bind_tex(&self, unit: u32) {
+        gl_call!(gl::ActiveTexture(gl::TEXTURE0 + unit));
+        gl_call!(gl::BindTexture(gl::TEXTURE_2D, self.depth_id));
+        gl_call!(gl::ActiveTexture(gl::TEXTURE0));
+    }
+
+    pub fn unbind_tex(&self, unit: u32) {
+        gl_call!(gl::ActiveTexture(gl::TEXTURE0 + unit));
+        gl_call!(gl::BindTexture(gl::TEXTURE_2D, 0));
+        gl_call!(gl::ActiveTexture(gl::TEXTURE0));
+    }
+}
+
+impl Drop for ShadowMap {
+    fn drop(&mut self) {
+        gl_call!(gl::DeleteTextures(1, &self.depth_id));
+        gl_call!(gl::DeleteFramebuffers(1, &self.id));
+    }
+}
+
+/// A depth-only framebuffer with a cube map array, one cube per shadow casting point light.
+///
+/// Rather than window-space depth, each texel stores the distance to the light divided by the
+/// light's radius, which is read in GLSL as a `samplerCubeArray`.
+pub struct CubeShadowMaps {
+    id: u32,
+    depth_id: u32,
+    size: u32,
+    count: usize,
+}
+
+impl CubeShadowMaps {
+    pub fn new(size: u32, count: usize) -> Self {
+        let mut id: u32 = 0;
+        gl_call!(gl::GenFramebuffers(1, &mut id));
+        let mut depth_id: u32 = 0;
+        gl_call!(gl::GenTextures(1, &mut depth_id));
+        let shadow_maps = CubeShadowMaps {
+            id,
+            depth_id,
+            size,
+            count,
+        };
+        shadow_maps.specify_texture();
+
+        gl_call!(gl::BindFramebuffer(gl::FRAMEBUFFER, id));
+        gl_call!(gl::FramebufferTextureLayer(
+            gl::FRAMEBUFFER,
+            gl::DEPTH_ATTACHMENT,
+            depth_id,
+            0,
+            0
+        ));
+        gl_call!(gl::DrawBuffer(gl::NONE));
+        gl_call!(gl::ReadBuffer(gl::NONE));
+        if gl_call!(gl::CheckFramebufferStatus(gl::FRAMEBUFFER) != gl::FRAMEBUFFER_COMPLETE) {
+            panic!("cube shadow map framebuffer not complete!");
+        }
+        gl_call!(gl::BindFramebuffer(gl::FRAMEBUFFER, 0));
+        shadow_maps
+    }
+
+    fn specify_texture(&self) {
+        gl_call!(gl::BindTexture(gl::TEXTURE_CUBE_MAP_ARRAY, self.depth_id));
+        // always allocate at least one cube, so there's something to bind
+        gl_call!(gl::TexImage3D(
+            gl::TEXTURE_CUBE_MAP_ARRAY,
+            0,
+            gl::DEPTH_COMPONENT32F as i32,
+            self.size as i32,
+            self.size as i32,
+            (self.count.max(1) * 6) as i32,
+            0,
+            gl::DEPTH_COMPONENT,
+            gl::FLOAT,
+            std::ptr::null()
+        ));
+        gl_call!(gl::TexParameteri(
+            gl::TEXTURE_CUBE_MAP_ARRAY,
+            gl::TEXTURE_MIN_FILTER,
+            gl::NEAREST as i32
+        ));
+        gl_call!(gl::TexParameteri(
+            gl::TEXTURE_CUBE_MAP_ARRAY,
+            gl::TEXTURE_MAG_FILTER,
+            gl::NEAREST as i32
+        ));
+        for wrap in &[gl::TEXTURE_WRAP_S, gl::TEXTURE_WRAP_T, gl::TEXTURE_WRAP_R] {
+            gl_call!(gl::TexParameteri(
+                gl::TEXTURE_CUBE_MAP_ARRAY,
+                *wrap,
+                gl::CLAMP_TO_EDGE as i32
+            ));
+        }
+        gl_call!(gl::BindTexture(gl::TEXTURE_CUBE_MAP_ARRAY, 0));
+    }
+
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    /// The number of cube maps, i.e. how many point lights can cast shadows.
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    pub fn resize(&mut self, size: u32, count: usize) {
+        self.size = size;
+        self.count = count;
+        self.specify_texture();
+    }
+
+    /// Bind the framebuffer to render into one face of one cube, and set the viewport to cover
+    /// the whole face. Faces are in the usual `+X, -X, +Y, -Y, +Z, -Z` order.
+    pub fn bind_face(&self, index: usize, face: usize) {
+        assert!(index < self.count && face < 6);
+        gl_call!(gl::BindFramebuffer(gl::FRAMEBUFFER, self.id));
+        gl_call!(gl::FramebufferTextureLayer(
+            gl::FRAMEBUFFER,
+            gl::DEPTH_ATTACHMENT,
+            self.depth_id,
+            0,
+            (index * 6 + face) as i32
+        ));
+        gl_call!(gl::Viewport(0, 0, self.size as i32, self.size as i32));
+    }
+
+    /// Bind the default framebuffer, the viewport is left for the caller to restore.
+    pub fn unbind(&self) {
+        gl_call!(gl::BindFramebuffer(gl::FRAMEBUFFER, 0));
+    }
+
+    /// Bind the cube map array to texture unit `unit`, e.g. `1` for `GL_TEXTURE1`.
+    pub fn bind_tex(&self, unit: u32) {
+        gl_call!(gl::ActiveTexture(gl::TEXTURE0 + unit));
+        gl_call!(gl::BindTexture(gl::TEXTURE_CUBE_MAP_ARRAY, self.depth_id));
+        gl_call!(gl::ActiveTexture(gl::TEXTURE0));
+    }
+
+    pub fn unbind_tex(&self, unit: u32) {
+        gl_call!(gl::ActiveTexture(gl::TEXTURE0 + unit));
+        gl_call!(gl::BindTexture(gl::TEXTURE_CUBE_MAP_ARRAY, 0));
+        gl_call!(gl::ActiveTexture(gl::TEXTURE0));
+    }
+
+    /// View matrices looking down each cube face from `position`, in the same order as the faces.
+    pub fn face_views(position: &glm::Vec3) -> [glm::Mat4; 6] {
+        let face = |dir: glm::Vec3, up: glm::Vec3| glm::look_at(position, &(position + dir), &up);
+        [
+            face(glm::vec3(1.0, 0.0, 0.0), glm::vec3(0.0, -1.0, 0.0)),
+            face(glm::vec3(-1.0, 0.0, 0.0), glm::vec3(0.0, -1.0, 0.0)),
+            face(glm::vec3(0.0, 1.0, 0.0), glm::vec3(0.0, 0.0, 1.0)),
+            face(glm::vec3(0.0, -1.0, 0.0), glm::vec3(0.0, 0.0, -1.0)),
+            face(glm::vec3(0.0, 0.0, 1.0), glm::vec3(0.0, -1.0, 0.0)),
+            face(glm::vec3(0.0, 0.0, -1.0), glm::vec3(0.0, -1.0, 0.0)),
+        ]
+    }
+}
+
+impl Drop for CubeShadowMaps {
+    fn drop(&mut self) {
+        gl_call!(gl::DeleteTextures(1, &self.depth_id));
+        gl_call!(gl::DeleteFramebuffers(1, &self.id));
+    }
+}
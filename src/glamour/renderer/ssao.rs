@@ -0,0 +1,197 @@
+use crate::{
+    glm, Camera, Framebuffer, FramebufferBuilder, GBuf, ShaderBuilder, ShaderProgram, Texture,
+    TextureBuilder, TextureFilter, TextureFormat, TextureWrap, VertArray,
+};
+use rand::{Rng, SeedableRng};
+
+/// Matches `MAX_KERNEL_SIZE` in the SSAO shader.
+pub const SSAO_MAX_KERNEL_SIZE: usize = 64;
+
+// width and height of the tiled noise texture, which the blur averages over
+const NOISE_SIZE: u32 = 4;
+
+// texture unit of the noise in the SSAO shader, the G-buffer takes the first four
+const NOISE_UNIT: u32 = 4;
+
+/// Screen-space ambient occlusion for the deferred path: how much of the hemisphere around each
+/// G-buffer texel is blocked by nearby geometry, blurred and read back by the lighting pass to
+/// darken ambient light.
+pub struct Ssao {
+    target: Framebuffer,
+    blur_target: Framebuffer,
+    shader: ShaderProgram,
+    blur_shader: ShaderProgram,
+    noise: Texture,
+    radius: f32,
+    bias: f32,
+    kernel_size: usize,
+}
+
+impl Ssao {
+    pub fn new(width: u32, height: u32) -> Self {
+        let target = || {
+            FramebufferBuilder::new()
+                .with_color(TextureFormat::R8)
+                .build(width, height)
+                .expect("failed to create SSAO target")
+        };
+
+        let shader = ShaderBuilder::new(
+            include_str!("shaders/screen.vert"),
+            include_str!("shaders/ssao.frag"),
+        )
+        .with_frag_include(include_str!("shaders/g_buf.glsl"))
+        .build();
+        shader.set_int("u_noise", NOISE_UNIT as i32);
+        let blur_shader = ShaderBuilder::new(
+            include_str!("shaders/screen.vert"),
+            include_str!("shaders/ssao_blur.frag"),
+        )
+        .build();
+        blur_shader.set_int("u_ssao", 0);
+        blur_shader.set_int("u_blur_size", NOISE_SIZE as i32);
+
+        // random rotations, deterministic so frames are reproducible
+        let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(0);
+        let noise_data: Vec<f32> = (0..NOISE_SIZE * NOISE_SIZE)
+            .flat_map(|_| {
+                let v = glm::normalize(&glm::vec3(
+                    rng.gen_range(-1.0, 1.0),
+                    rng.gen_range(-1.0, 1.0),
+                    rng.gen_range(-1.0, 1.0),
+                ));
+                vec![v.x, v.y, v.z]
+            })
+            .collect();
+        let noise = TextureBuilder::new()
+            .with_format(TextureFormat::Rgb16F)
+            .with_filter(TextureFilter::Nearest, TextureFilter::Nearest)
+            .with_wrap(TextureWrap::Repeat)
+            .build_from_bytes(
+                NOISE_SIZE,
+                NOISE_SIZE,
+                &noise_data
+                    .iter()
+                    .flat_map(|f| f.to_ne_bytes().to_vec())
+                    .collect::<Vec<u8>>(),
+            )
+            .expect("failed to create SSAO noise");
+
+        let mut ssao = Ssao {
+            target: target(),
+            blur_target: target(),
+            shader,
+            blur_shader,
+            noise,
+            radius: 0.5,
+            bias: 0.025,
+            kernel_size: 32,
+        };
+        ssao.set_radius(ssao.radius);
+        ssao.set_bias(ssao.bias);
+        ssao.set_kernel_size(ssao.kernel_size);
+        ssao
+    }
+
+    pub fn radius(&self) -> f32 {
+        self.radius
+    }
+
+    /// World space radius of the hemisphere sampled around each texel.
+    pub fn set_radius(&mut self, val: f32) {
+        self.radius = val;
+        self.shader.set_float("u_radius", val);
+    }
+
+    pub fn bias(&self) -> f32 {
+        self.bias
+    }
+
+    /// Depth a sample has to be behind the scene to count as occluded, against self-occlusion
+    /// of flat surfaces.
+    pub fn set_bias(&mut self, val: f32) {
+        self.bias = val;
+        self.shader.set_float("u_bias", val);
+    }
+
+    pub fn kernel_size(&self) -> usize {
+        self.kernel_size
+    }
+
+    /// Samples per texel, up to `SSAO_MAX_KERNEL_SIZE`. More is smoother and slower.
+    pub fn set_kernel_size(&mut self, val: usize) {
+        self.kernel_size = val.clamp(1, SSAO_MAX_KERNEL_SIZE);
+        for (i, sample) in Ssao::kernel(self.kernel_size).iter().enumerate() {
+            self.shader.set_float3(&format!("u_kernel[{}]", i), sample);
+        }
+        self.shader
+            .set_int("u_kernel_size", self.kernel_size as i32);
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.target.resize(width, height);
+        self.blur_target.resize(width, height);
+    }
+
+    pub fn set_camera(&self, camera: &Camera) {
+        self.shader.set_mat4("u_view", &camera.view_matrix());
+        let vp_mat = camera.view_projection_matrix();
+        self.shader.set_mat4("u_view_projection", &vp_mat);
+        self.shader
+            .set_mat4("u_inv_view_projection", &glm::inverse(&vp_mat));
+    }
+
+    /// Calculate and blur the occlusion of the G-buffer's contents. Leaves the blur target
+    /// bound, so the caller has to bind its own framebuffer afterwards.
+    /// `quad_vao` is a full-screen quad, drawn as a triangle strip.
+    pub fn draw(&self, g_buf: &GBuf, quad_vao: &VertArray) {
+        // the G-buffer's layout can change between frames
+        g_buf.set_shader_uniforms(&self.shader);
+
+        gl_call!(gl::Disable(gl::DEPTH_TEST));
+        quad_vao.bind();
+
+        self.target.bind();
+        self.shader.bind();
+        g_buf.bind_bufs();
+        self.noise.bind(NOISE_UNIT);
+        gl_call!(gl::DrawArrays(gl::TRIANGLE_STRIP, 0, 4));
+        self.noise.unbind(NOISE_UNIT);
+        g_buf.unbind_bufs();
+
+        self.blur_target.bind();
+        self.blur_shader.bind();
+        self.target.bind_colors(0);
+        gl_call!(gl::DrawArrays(gl::TRIANGLE_STRIP, 0, 4));
+        self.target.unbind_colors(0);
+        self.blur_shader.unbind();
+
+        quad_vao.unbind();
+        gl_call!(gl::Enable(gl::DEPTH_TEST));
+    }
+
+    /// Bind the blurred occlusion to texture unit `unit`, read from the red channel.
+    pub fn bind_tex(&self, unit: u32) {
+        self.blur_target.color(0).bind(unit);
+    }
+
+    pub fn unbind_tex(&self, unit: u32) {
+        self.blur_target.color(0).unbind(unit);
+    }
+
+    /// Random offsets in a unit hemisphere around +z, scaled so more are close to the centre.
+    fn kernel(size: usize) -> Vec<glm::Vec3> {
+        let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(1);
+        (0..size)
+            .map(|i| {
+                let sample = glm::normalize(&glm::vec3(
+                    rng.gen_range(-1.0, 1.0),
+                    rng.gen_range(-1.0, 1.0),
+                    rng.gen_range(0.0, 1.0),
+                )) * rng.gen_range(0.0, 1.0);
+                let t = i as f32 / size as f32;
+                sample * glm::lerp_scalar(0.1, 1.0, t * t)
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,110 @@
+use std::convert::TryFrom;
+
+/// A buffer texture, read in GLSL as a `samplerBuffer` with `texelFetch`.
+///
+/// Each element of `T` is laid out as consecutive `RGBA32F` texels, so `T` must be `#[repr(C)]`
+/// and made of floats, with a size that's a multiple of 16 bytes (e.g. `glm::Vec4`).
+/// Unlike uniform arrays, the size is only limited by `GL_MAX_TEXTURE_BUFFER_SIZE`.
+pub struct TexBuf<T: Copy> {
+    id: u32,
+    tex_id: u32,
+    data: Vec<T>,
+    capacity: usize,
+}
+
+impl<T: Copy> TexBuf<T> {
+    pub fn new(capacity: usize) -> Self {
+        assert_eq!(
+            std::mem::size_of::<T>() % TexBuf::<T>::texel_size(),
+            0,
+            "TexBuf elements must be a multiple of 16 bytes"
+        );
+        let capacity = capacity.max(1);
+        let mut id = 0;
+        gl_call!(gl::GenBuffers(1, &mut id));
+        gl_call!(gl::BindBuffer(gl::TEXTURE_BUFFER, id));
+        gl_call!(gl::BufferData(
+            gl::TEXTURE_BUFFER,
+            TexBuf::<T>::byte_size(capacity),
+            std::ptr::null(),
+            gl::DYNAMIC_DRAW,
+        ));
+        gl_call!(gl::BindBuffer(gl::TEXTURE_BUFFER, 0));
+
+        let mut tex_id = 0;
+        gl_call!(gl::GenTextures(1, &mut tex_id));
+        gl_call!(gl::BindTexture(gl::TEXTURE_BUFFER, tex_id));
+        gl_call!(gl::TexBuffer(gl::TEXTURE_BUFFER, gl::RGBA32F, id));
+        gl_call!(gl::BindTexture(gl::TEXTURE_BUFFER, 0));
+
+        TexBuf {
+            id,
+            tex_id,
+            data: Vec::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    fn texel_size() -> usize {
+        4 * std::mem::size_of::<f32>()
+    }
+
+    fn byte_size(count: usize) -> gl::types::GLsizeiptr {
+        gl::types::GLsizeiptr::try_from(count * std::mem::size_of::<T>()).unwrap()
+    }
+
+    /// Number of `RGBA32F` texels each element takes up.
+    pub fn texels_per_element() -> usize {
+        std::mem::size_of::<T>() / TexBuf::<T>::texel_size()
+    }
+
+    pub fn data(&self) -> &[T] {
+        &self.data
+    }
+
+    pub fn data_mut(&mut self) -> &mut Vec<T> {
+        &mut self.data
+    }
+
+    /// Upload all the data in one transfer, growing the buffer if it no longer fits.
+    pub fn set_data(&mut self) {
+        gl_call!(gl::BindBuffer(gl::TEXTURE_BUFFER, self.id));
+        if self.data.len() > self.capacity {
+            self.capacity = self.data.len().next_power_of_two();
+            gl_call!(gl::BufferData(
+                gl::TEXTURE_BUFFER,
+                TexBuf::<T>::byte_size(self.capacity),
+                std::ptr::null(),
+                gl::DYNAMIC_DRAW,
+            ));
+        }
+        let ptr = self.data.as_ptr() as *const gl::types::GLvoid;
+        gl_call!(gl::BufferSubData(
+            gl::TEXTURE_BUFFER,
+            0,
+            TexBuf::<T>::byte_size(self.data.len()),
+            ptr
+        ));
+        gl_call!(gl::BindBuffer(gl::TEXTURE_BUFFER, 0));
+    }
+
+    /// Bind the buffer texture to texture unit `unit`, e.g. `1` for `GL_TEXTURE1`.
+    pub fn bind(&self, unit: u32) {
+        gl_call!(gl::ActiveTexture(gl::TEXTURE0 + unit));
+        gl_call!(gl::BindTexture(gl::TEXTURE_BUFFER, self.tex_id));
+        gl_call!(gl::ActiveTexture(gl::TEXTURE0));
+    }
+
+    pub fn unbind(&self, unit: u32) {
+        gl_call!(gl::ActiveTexture(gl::TEXTURE0 + unit));
+        gl_call!(gl::BindTexture(gl::TEXTURE_BUFFER, 0));
+        gl_call!(gl::ActiveTexture(gl::TEXTURE0));
+    }
+}
+
+impl<T: Copy> Drop for TexBuf<T> {
+    fn drop(&mut self) {
+        gl_call!(gl::DeleteTextures(1, &self.tex_id));
+        gl_call!(gl::DeleteBuffers(1, &self.id));
+    }
+}
@@ -1,59 +1,823 @@
-pub struct Texture {
-    id: u32,
+use crate::glm;
+use rayon::prelude::*;
+use std::convert::TryFrom;
+
+// from EXT_texture_filter_anisotropic, which was only made core in 4.6
+const TEXTURE_MAX_ANISOTROPY: gl::types::GLenum = 0x84FE;
+const MAX_TEXTURE_MAX_ANISOTROPY: gl::types::GLenum = 0x84FF;
+
+/// The internal format of a texture, and the layout of the data uploaded to it.
+///
+/// Byte formats take one `u8` per channel, float formats take one `f32` per channel (so four
+/// bytes, in native byte order) whatever their precision on the GPU.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TextureFormat {
+    R8,
+    Rg8,
+    Rgb8,
+    Rgba8,
+    /// RGB in the sRGB colour space, converted to linear when sampled.
+    Srgb8,
+    /// RGB in the sRGB colour space with linear alpha, converted to linear when sampled.
+    Srgb8Alpha8,
+    R16F,
+    Rg16F,
+    Rgb16F,
+    Rgba16F,
+    Rgba32F,
 }
 
-impl Texture {
-    pub fn new(file_path: &std::path::Path) -> Self {
-        let image = image::open(file_path).unwrap();
-        let image_buf = image.flipv().into_rgb();
-        let (width, height) = image_buf.dimensions();
+impl TextureFormat {
+    pub(crate) fn gl_internal_format(&self) -> gl::types::GLenum {
+        match self {
+            TextureFormat::R8 => gl::R8,
+            TextureFormat::Rg8 => gl::RG8,
+            TextureFormat::Rgb8 => gl::RGB8,
+            TextureFormat::Rgba8 => gl::RGBA8,
+            TextureFormat::Srgb8 => gl::SRGB8,
+            TextureFormat::Srgb8Alpha8 => gl::SRGB8_ALPHA8,
+            TextureFormat::R16F => gl::R16F,
+            TextureFormat::Rg16F => gl::RG16F,
+            TextureFormat::Rgb16F => gl::RGB16F,
+            TextureFormat::Rgba16F => gl::RGBA16F,
+            TextureFormat::Rgba32F => gl::RGBA32F,
+        }
+    }
+
+    fn gl_format(&self) -> gl::types::GLenum {
+        match self.channels() {
+            1 => gl::RED,
+            2 => gl::RG,
+            3 => gl::RGB,
+            _ => gl::RGBA,
+        }
+    }
+
+    fn gl_type(&self) -> gl::types::GLenum {
+        if self.is_float() {
+            gl::FLOAT
+        } else {
+            gl::UNSIGNED_BYTE
+        }
+    }
+
+    pub fn channels(&self) -> usize {
+        match self {
+            TextureFormat::R8 | TextureFormat::R16F => 1,
+            TextureFormat::Rg8 | TextureFormat::Rg16F => 2,
+            TextureFormat::Rgb8 | TextureFormat::Srgb8 | TextureFormat::Rgb16F => 3,
+            TextureFormat::Rgba8
+            | TextureFormat::Srgb8Alpha8
+            | TextureFormat::Rgba16F
+            | TextureFormat::Rgba32F => 4,
+        }
+    }
+
+    pub fn is_float(&self) -> bool {
+        matches!(
+            self,
+            TextureFormat::R16F
+                | TextureFormat::Rg16F
+                | TextureFormat::Rgb16F
+                | TextureFormat::Rgba16F
+                | TextureFormat::Rgba32F
+        )
+    }
+
+    /// Size of a single pixel of uploaded data.
+    ///
+    /// ```
+    /// # use glamour::TextureFormat;
+    /// assert_eq!(TextureFormat::Srgb8.bytes_per_pixel(), 3);
+    /// assert_eq!(TextureFormat::Rgba16F.bytes_per_pixel(), 16);
+    /// ```
+    pub fn bytes_per_pixel(&self) -> usize {
+        let channel_size = if self.is_float() {
+            std::mem::size_of::<f32>()
+        } else {
+            1
+        };
+        self.channels() * channel_size
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TextureWrap {
+    Repeat,
+    MirroredRepeat,
+    ClampToEdge,
+    /// Transparent black outside of the texture.
+    ClampToBorder,
+}
+
+impl TextureWrap {
+    fn gl_wrap(&self) -> gl::types::GLenum {
+        match self {
+            TextureWrap::Repeat => gl::REPEAT,
+            TextureWrap::MirroredRepeat => gl::MIRRORED_REPEAT,
+            TextureWrap::ClampToEdge => gl::CLAMP_TO_EDGE,
+            TextureWrap::ClampToBorder => gl::CLAMP_TO_BORDER,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TextureFilter {
+    Nearest,
+    Linear,
+}
+
+impl TextureFilter {
+    fn gl_filter(&self) -> gl::types::GLenum {
+        match self {
+            TextureFilter::Nearest => gl::NEAREST,
+            TextureFilter::Linear => gl::LINEAR,
+        }
+    }
+
+    fn gl_min_filter(&self, mipmap_filter: Option<TextureFilter>) -> gl::types::GLenum {
+        match (self, mipmap_filter) {
+            (_, None) => self.gl_filter(),
+            (TextureFilter::Nearest, Some(TextureFilter::Nearest)) => gl::NEAREST_MIPMAP_NEAREST,
+            (TextureFilter::Nearest, Some(TextureFilter::Linear)) => gl::NEAREST_MIPMAP_LINEAR,
+            (TextureFilter::Linear, Some(TextureFilter::Nearest)) => gl::LINEAR_MIPMAP_NEAREST,
+            (TextureFilter::Linear, Some(TextureFilter::Linear)) => gl::LINEAR_MIPMAP_LINEAR,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum TextureError {
+    Image(image::ImageError),
+    /// The data doesn't match the size and format of the texture.
+    DataSize {
+        expected: usize,
+        actual: usize,
+    },
+    /// Empty, or larger than `GL_MAX_TEXTURE_SIZE`.
+    Dimensions {
+        width: u32,
+        height: u32,
+    },
+    /// No layers, or more than `GL_MAX_ARRAY_TEXTURE_LAYERS`.
+    Layers {
+        layers: u32,
+    },
+    /// A layer of a texture array isn't the same size as the first.
+    LayerSize {
+        layer: usize,
+    },
+    /// A face of a cube map isn't the same size as the first.
+    FaceSize {
+        face: usize,
+    },
+    /// Cube map faces are square.
+    FaceNotSquare {
+        width: u32,
+        height: u32,
+    },
+    /// Cube maps are built from exactly six faces.
+    FaceCount {
+        faces: usize,
+    },
+}
+
+impl std::fmt::Display for TextureError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TextureError::Image(err) => write!(f, "failed to load image: {}", err),
+            TextureError::DataSize { expected, actual } => write!(
+                f,
+                "texture data is {} bytes, expected {} bytes",
+                actual, expected
+            ),
+            TextureError::Dimensions { width, height } => {
+                write!(f, "unsupported texture size: {} x {}", width, height)
+            }
+            TextureError::Layers { layers } => {
+                write!(f, "unsupported texture array layer count: {}", layers)
+            }
+            TextureError::LayerSize { layer } => write!(
+                f,
+                "texture layer {} isn't the same size as the first layer",
+                layer
+            ),
+            TextureError::FaceSize { face } => write!(
+                f,
+                "cube map face {} isn't the same size as the first face",
+                face
+            ),
+            TextureError::FaceNotSquare { width, height } => {
+                write!(f, "cube map face isn't square: {} x {}", width, height)
+            }
+            TextureError::FaceCount { faces } => {
+                write!(f, "cube map needs 6 faces, got {}", faces)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TextureError {}
+
+impl From<image::ImageError> for TextureError {
+    fn from(err: image::ImageError) -> Self {
+        TextureError::Image(err)
+    }
+}
+
+/// Creates `Texture`s with a given format and sampling settings.
+///
+/// The defaults are `Srgb8Alpha8` for colour images, repeating, linear filtering without
+/// mipmaps and no anisotropic filtering. Data such as normal or specular maps should use a
+/// linear format like `Rgba8`.
+#[derive(Debug, Clone)]
+pub struct TextureBuilder {
+    format: TextureFormat,
+    wrap_s: TextureWrap,
+    wrap_t: TextureWrap,
+    min_filter: TextureFilter,
+    mag_filter: TextureFilter,
+    mipmap_filter: Option<TextureFilter>,
+    anisotropy: f32,
+}
+
+impl TextureBuilder {
+    pub fn new() -> Self {
+        TextureBuilder {
+            format: TextureFormat::Srgb8Alpha8,
+            wrap_s: TextureWrap::Repeat,
+            wrap_t: TextureWrap::Repeat,
+            min_filter: TextureFilter::Linear,
+            mag_filter: TextureFilter::Linear,
+            mipmap_filter: None,
+            anisotropy: 1.0,
+        }
+    }
+    pub fn with_format(mut self, format: TextureFormat) -> Self {
+        self.format = format;
+        self
+    }
+    /// Wrap both the `s` and `t` coordinates the same way.
+    pub fn with_wrap(mut self, wrap: TextureWrap) -> Self {
+        self.wrap_s = wrap;
+        self.wrap_t = wrap;
+        self
+    }
+    pub fn with_wrap_st(mut self, wrap_s: TextureWrap, wrap_t: TextureWrap) -> Self {
+        self.wrap_s = wrap_s;
+        self.wrap_t = wrap_t;
+        self
+    }
+    pub fn with_filter(mut self, min_filter: TextureFilter, mag_filter: TextureFilter) -> Self {
+        self.min_filter = min_filter;
+        self.mag_filter = mag_filter;
+        self
+    }
+    /// Generate mipmaps, and filter between them with `filter` when minifying.
+    pub fn with_mipmaps(mut self, filter: TextureFilter) -> Self {
+        self.mipmap_filter = Some(filter);
+        self
+    }
+    /// Maximum anisotropic filtering samples, e.g. `16.0`. Clamped to what the driver supports,
+    /// and ignored without `EXT_texture_filter_anisotropic`.
+    pub fn with_anisotropy(mut self, anisotropy: f32) -> Self {
+        self.anisotropy = anisotropy;
+        self
+    }
+
+    /// Load an image file, flipped so its bottom left is at texture coordinates `(0, 0)`.
+    pub fn build_from_file(&self, file_path: &std::path::Path) -> Result<Texture, TextureError> {
+        let image = image::open(file_path)?;
+        self.build_from_image(&image)
+    }
+
+    /// Upload a decoded image, flipped so its bottom left is at texture coordinates `(0, 0)`.
+    /// It's converted to however many channels the format has.
+    pub fn build_from_image(&self, image: &image::DynamicImage) -> Result<Texture, TextureError> {
+        let (width, height, data) = self.image_data(image);
+        self.build_from_bytes(width, height, &data)
+    }
+
+    /// Upload raw pixel data, row by row from the bottom left, laid out as described by
+    /// `TextureFormat`.
+    pub fn build_from_bytes(
+        &self,
+        width: u32,
+        height: u32,
+        data: &[u8],
+    ) -> Result<Texture, TextureError> {
+        self.check_data_size(width, height, 1, data)?;
+        self.build(width, height, data.as_ptr())
+    }
+
+    /// Allocate a texture without any data, e.g. to render into.
+    pub fn build_empty(&self, width: u32, height: u32) -> Result<Texture, TextureError> {
+        self.build(width, height, std::ptr::null())
+    }
+
+    /// Load image files as the layers of a texture array, in order. They must all be the same
+    /// size.
+    pub fn build_array_from_files<P: AsRef<std::path::Path>>(
+        &self,
+        file_paths: &[P],
+    ) -> Result<TextureArray, TextureError> {
+        let images = file_paths
+            .iter()
+            .map(image::open)
+            .collect::<Result<Vec<_>, _>>()?;
+        self.build_array_from_images(&images)
+    }
+
+    /// Upload decoded images as the layers of a texture array, in order. They must all be the
+    /// same size, and are flipped and converted the same way as `build_from_image`.
+    pub fn build_array_from_images(
+        &self,
+        images: &[image::DynamicImage],
+    ) -> Result<TextureArray, TextureError> {
+        let mut layers = images.iter().map(|image| self.image_data(image));
+        let (width, height, mut data) = match layers.next() {
+            Some(layer) => layer,
+            None => return Err(TextureError::Layers { layers: 0 }),
+        };
+        for (index, (layer_width, layer_height, layer_data)) in layers.enumerate() {
+            if (layer_width, layer_height) != (width, height) {
+                return Err(TextureError::LayerSize { layer: index + 1 });
+            }
+            data.extend(layer_data);
+        }
+        self.build_array_from_bytes(width, height, images.len() as u32, &data)
+    }
+
+    /// Upload raw pixel data for every layer of a texture array, one layer after another.
+    pub fn build_array_from_bytes(
+        &self,
+        width: u32,
+        height: u32,
+        layers: u32,
+        data: &[u8],
+    ) -> Result<TextureArray, TextureError> {
+        self.check_data_size(width, height, layers, data)?;
+        self.build_array(width, height, layers, data.as_ptr())
+    }
+
+    /// Allocate a texture array without any data.
+    pub fn build_array_empty(
+        &self,
+        width: u32,
+        height: u32,
+        layers: u32,
+    ) -> Result<TextureArray, TextureError> {
+        self.build_array(width, height, layers, std::ptr::null())
+    }
+
+    /// Load six image files as the faces of a cube map, in `CubeMap` order. They must all be
+    /// the same square size.
+    pub fn build_cube_map_from_files<P: AsRef<std::path::Path>>(
+        &self,
+        file_paths: &[P],
+    ) -> Result<CubeMap, TextureError> {
+        if file_paths.len() != 6 {
+            return Err(TextureError::FaceCount {
+                faces: file_paths.len(),
+            });
+        }
+        let images = file_paths
+            .iter()
+            .map(image::open)
+            .collect::<Result<Vec<_>, _>>()?;
+        self.build_cube_map_from_images(&images)
+    }
+
+    /// Upload six decoded images as the faces of a cube map, in `CubeMap` order. Unlike
+    /// `build_from_image` they aren't flipped, since cube map faces start at the top left.
+    pub fn build_cube_map_from_images(
+        &self,
+        images: &[image::DynamicImage],
+    ) -> Result<CubeMap, TextureError> {
+        if images.len() != 6 {
+            return Err(TextureError::FaceCount {
+                faces: images.len(),
+            });
+        }
+        let mut faces = images
+            .iter()
+            .map(|image| self.unflipped_image_data(image.clone()));
+        let (width, height, mut data) = faces.next().unwrap();
+        if width != height {
+            return Err(TextureError::FaceNotSquare { width, height });
+        }
+        for (index, (face_width, face_height, face_data)) in faces.enumerate() {
+            if (face_width, face_height) != (width, height) {
+                return Err(TextureError::FaceSize { face: index + 1 });
+            }
+            data.extend(face_data);
+        }
+        self.build_cube_map_from_bytes(width, &data)
+    }
+
+    /// Upload raw pixel data for all six faces of a cube map, `size` texels square, one face
+    /// after another in `CubeMap` order.
+    pub fn build_cube_map_from_bytes(
+        &self,
+        size: u32,
+        data: &[u8],
+    ) -> Result<CubeMap, TextureError> {
+        self.check_data_size(size, size, 6, data)?;
+        self.build_cube_map(size, data)
+    }
+
+    /// Load an equirectangular (latitude-longitude) panorama, either a Radiance `.hdr` file or
+    /// any other image, and resample it into cube map faces `face_size` texels square. HDR
+    /// values above 1 only survive in a float format.
+    pub fn build_cube_map_from_equirect_file(
+        &self,
+        file_path: &std::path::Path,
+        face_size: u32,
+    ) -> Result<CubeMap, TextureError> {
+        let is_hdr = file_path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("hdr"));
+        if !is_hdr {
+            let image = image::open(file_path)?;
+            return self.build_cube_map_from_equirect_image(&image, face_size);
+        }
+        let file = std::fs::File::open(file_path).map_err(image::ImageError::IoError)?;
+        let decoder = image::hdr::HdrDecoder::new(std::io::BufReader::new(file))?;
+        let metadata = decoder.metadata();
+        let pixels: Vec<glm::Vec3> = decoder
+            .read_image_hdr()?
+            .iter()
+            .map(|p| glm::vec3(p.0[0], p.0[1], p.0[2]))
+            .collect();
+        self.build_cube_map_from_equirect(metadata.width, metadata.height, &pixels, face_size)
+    }
+
+    /// Resample a decoded equirectangular panorama into cube map faces, see
+    /// `build_cube_map_from_equirect_file`. Texels are copied as they are, so an sRGB image
+    /// wants an sRGB format.
+    pub fn build_cube_map_from_equirect_image(
+        &self,
+        image: &image::DynamicImage,
+        face_size: u32,
+    ) -> Result<CubeMap, TextureError> {
+        let rgb = image.to_rgb();
+        let pixels: Vec<glm::Vec3> = rgb
+            .pixels()
+            .map(|p| glm::vec3(p.0[0] as f32, p.0[1] as f32, p.0[2] as f32) / 255.0)
+            .collect();
+        self.build_cube_map_from_equirect(rgb.width(), rgb.height(), &pixels, face_size)
+    }
+
+    /// Resample linear RGB pixels of an equirectangular panorama, row by row from the top left,
+    /// into cube map faces `face_size` texels square.
+    pub fn build_cube_map_from_equirect(
+        &self,
+        width: u32,
+        height: u32,
+        pixels: &[glm::Vec3],
+        face_size: u32,
+    ) -> Result<CubeMap, TextureError> {
+        TextureBuilder::check_dimensions(width, height)?;
+        TextureBuilder::check_dimensions(face_size, face_size)?;
+        let expected = width as usize * height as usize;
+        if pixels.len() != expected {
+            return Err(TextureError::DataSize {
+                expected,
+                actual: pixels.len(),
+            });
+        }
+        let size = face_size as usize;
+        let faces: Vec<glm::Vec3> = (0..6 * size * size)
+            .into_par_iter()
+            .map(|index| {
+                let (face, texel) = (index / (size * size), index % (size * size));
+                let s = ((texel % size) as f32 + 0.5) / size as f32 * 2.0 - 1.0;
+                let t = ((texel / size) as f32 + 0.5) / size as f32 * 2.0 - 1.0;
+                sample_equirect(width, height, pixels, &cube_map_direction(face, s, t))
+            })
+            .collect();
+        let channels = self.format.channels();
+        let data: Vec<u8> = faces
+            .iter()
+            .flat_map(|texel| {
+                let rgba = [texel.x, texel.y, texel.z, 1.0];
+                let texel = &rgba[..channels];
+                if self.format.is_float() {
+                    texel
+                        .iter()
+                        .flat_map(|c| c.to_ne_bytes().to_vec())
+                        .collect::<Vec<_>>()
+                } else {
+                    texel
+                        .iter()
+                        .map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8)
+                        .collect()
+                }
+            })
+            .collect();
+        self.build_cube_map_from_bytes(face_size, &data)
+    }
+
+    /// An image's size and pixels flipped and converted for the builder's format.
+    fn image_data(&self, image: &image::DynamicImage) -> (u32, u32, Vec<u8>) {
+        self.unflipped_image_data(image.flipv())
+    }
+
+    /// An image's size and pixels converted for the builder's format, top row first.
+    fn unflipped_image_data(&self, image: image::DynamicImage) -> (u32, u32, Vec<u8>) {
+        let (width, height) = image::GenericImageView::dimensions(&image);
+        let data: Vec<u8> = match self.format.channels() {
+            channels if self.format.is_float() => {
+                // the same channels as the byte formats, normalized to [0, 1]
+                let rgba = image.into_rgba();
+                let floats: Vec<f32> = rgba
+                    .pixels()
+                    .flat_map(|p| p.0[..channels].iter().map(|c| *c as f32 / 255.0))
+                    .collect();
+                floats
+                    .iter()
+                    .flat_map(|f| f.to_ne_bytes().to_vec())
+                    .collect()
+            }
+            1 => image.into_luma().into_raw(),
+            2 => image.into_luma_alpha().into_raw(),
+            3 => image.into_rgb().into_raw(),
+            _ => image.into_rgba().into_raw(),
+        };
+        (width, height, data)
+    }
+
+    fn check_data_size(
+        &self,
+        width: u32,
+        height: u32,
+        layers: u32,
+        data: &[u8],
+    ) -> Result<(), TextureError> {
+        let expected =
+            width as usize * height as usize * layers as usize * self.format.bytes_per_pixel();
+        if data.len() != expected {
+            return Err(TextureError::DataSize {
+                expected,
+                actual: data.len(),
+            });
+        }
+        Ok(())
+    }
+
+    fn check_dimensions(width: u32, height: u32) -> Result<(), TextureError> {
+        let mut max_size = 0;
+        gl_call!(gl::GetIntegerv(gl::MAX_TEXTURE_SIZE, &mut max_size));
+        let max_size = u32::try_from(max_size).unwrap_or(0);
+        if width == 0 || height == 0 || width > max_size || height > max_size {
+            return Err(TextureError::Dimensions { width, height });
+        }
+        Ok(())
+    }
+
+    fn build(&self, width: u32, height: u32, data: *const u8) -> Result<Texture, TextureError> {
+        TextureBuilder::check_dimensions(width, height)?;
         let mut id = 0;
         gl_call!(gl::GenTextures(1, &mut id));
         gl_call!(gl::BindTexture(gl::TEXTURE_2D, id));
-        gl_call!(gl::TexParameteri(
-            gl::TEXTURE_2D,
-            gl::TEXTURE_WRAP_S,
-            gl::REPEAT as gl::types::GLint
-        ));
-        gl_call!(gl::TexParameteri(
+        // rows of RGB bytes aren't necessarily 4 byte aligned
+        gl_call!(gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1));
+        gl_call!(gl::TexImage2D(
             gl::TEXTURE_2D,
-            gl::TEXTURE_WRAP_T,
-            gl::REPEAT as gl::types::GLint
+            0,
+            self.format.gl_internal_format() as gl::types::GLint,
+            width as gl::types::GLint,
+            height as gl::types::GLint,
+            0,
+            self.format.gl_format(),
+            self.format.gl_type(),
+            data as *const gl::types::GLvoid
         ));
-        gl_call!(gl::TexParameteri(
-            gl::TEXTURE_2D,
-            gl::TEXTURE_MIN_FILTER,
-            gl::LINEAR as gl::types::GLint
+        gl_call!(gl::PixelStorei(gl::UNPACK_ALIGNMENT, 4));
+        self.set_params(gl::TEXTURE_2D);
+        gl_call!(gl::BindTexture(gl::TEXTURE_2D, 0));
+        Ok(Texture {
+            id,
+            width,
+            height,
+            format: self.format,
+        })
+    }
+
+    fn build_array(
+        &self,
+        width: u32,
+        height: u32,
+        layers: u32,
+        data: *const u8,
+    ) -> Result<TextureArray, TextureError> {
+        TextureBuilder::check_dimensions(width, height)?;
+        let mut max_layers = 0;
+        gl_call!(gl::GetIntegerv(
+            gl::MAX_ARRAY_TEXTURE_LAYERS,
+            &mut max_layers
         ));
-        gl_call!(gl::TexParameteri(
-            gl::TEXTURE_2D,
-            gl::TEXTURE_MAG_FILTER,
-            gl::LINEAR as gl::types::GLint
+        if layers == 0 || layers > u32::try_from(max_layers).unwrap_or(0) {
+            return Err(TextureError::Layers { layers });
+        }
+        let mut id = 0;
+        gl_call!(gl::GenTextures(1, &mut id));
+        gl_call!(gl::BindTexture(gl::TEXTURE_2D_ARRAY, id));
+        gl_call!(gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1));
+        gl_call!(gl::TexImage3D(
+            gl::TEXTURE_2D_ARRAY,
+            0,
+            self.format.gl_internal_format() as gl::types::GLint,
+            width as gl::types::GLint,
+            height as gl::types::GLint,
+            layers as gl::types::GLint,
+            0,
+            self.format.gl_format(),
+            self.format.gl_type(),
+            data as *const gl::types::GLvoid
         ));
+        gl_call!(gl::PixelStorei(gl::UNPACK_ALIGNMENT, 4));
+        self.set_params(gl::TEXTURE_2D_ARRAY);
+        gl_call!(gl::BindTexture(gl::TEXTURE_2D_ARRAY, 0));
+        Ok(TextureArray {
+            id,
+            width,
+            height,
+            layers,
+            format: self.format,
+        })
+    }
+
+    fn build_cube_map(&self, size: u32, data: &[u8]) -> Result<CubeMap, TextureError> {
+        TextureBuilder::check_dimensions(size, size)?;
+        let mut id = 0;
+        gl_call!(gl::GenTextures(1, &mut id));
+        gl_call!(gl::BindTexture(gl::TEXTURE_CUBE_MAP, id));
+        gl_call!(gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1));
+        let face_bytes = size as usize * size as usize * self.format.bytes_per_pixel();
+        for (face, face_data) in data.chunks_exact(face_bytes).enumerate() {
+            gl_call!(gl::TexImage2D(
+                gl::TEXTURE_CUBE_MAP_POSITIVE_X + face as u32,
+                0,
+                self.format.gl_internal_format() as gl::types::GLint,
+                size as gl::types::GLint,
+                size as gl::types::GLint,
+                0,
+                self.format.gl_format(),
+                self.format.gl_type(),
+                face_data.as_ptr() as *const gl::types::GLvoid
+            ));
+        }
+        gl_call!(gl::PixelStorei(gl::UNPACK_ALIGNMENT, 4));
+        self.set_params(gl::TEXTURE_CUBE_MAP);
+        // the builder's wrapping would show seams between faces
+        for wrap in &[gl::TEXTURE_WRAP_S, gl::TEXTURE_WRAP_T, gl::TEXTURE_WRAP_R] {
+            gl_call!(gl::TexParameteri(
+                gl::TEXTURE_CUBE_MAP,
+                *wrap,
+                gl::CLAMP_TO_EDGE as gl::types::GLint
+            ));
+        }
+        gl_call!(gl::BindTexture(gl::TEXTURE_CUBE_MAP, 0));
+        Ok(CubeMap {
+            id,
+            size,
+            format: self.format,
+        })
+    }
+
+    /// Generate mipmaps and set the sampling parameters of the texture bound to `target`.
+    fn set_params(&self, target: gl::types::GLenum) {
+        if self.mipmap_filter.is_some() {
+            gl_call!(gl::GenerateMipmap(target));
+        }
+        set_wrap(target, self.wrap_s, self.wrap_t);
+        set_filter(target, self.min_filter, self.mag_filter, self.mipmap_filter);
+        if self.anisotropy > 1.0 {
+            set_anisotropy(target, self.anisotropy);
+        }
+    }
+}
+
+impl Default for TextureBuilder {
+    fn default() -> Self {
+        TextureBuilder::new()
+    }
+}
+
+#[derive(Debug)]
+pub struct Texture {
+    id: u32,
+    width: u32,
+    height: u32,
+    format: TextureFormat,
+}
+
+impl Texture {
+    /// Load an image file with the `TextureBuilder` defaults.
+    pub fn new(file_path: &std::path::Path) -> Result<Self, TextureError> {
+        TextureBuilder::new().build_from_file(file_path)
+    }
+
+    /// Upload a decoded image with the `TextureBuilder` defaults.
+    pub fn from_image(image: &image::DynamicImage) -> Result<Self, TextureError> {
+        TextureBuilder::new().build_from_image(image)
+    }
+
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+    pub fn format(&self) -> TextureFormat {
+        self.format
+    }
+
+    /// Reallocate the texture at a new size, without any data. Only for textures that are
+    /// rendered into, any mipmaps are left as they were.
+    pub(crate) fn resize(&mut self, width: u32, height: u32) {
+        self.width = width;
+        self.height = height;
+        gl_call!(gl::BindTexture(gl::TEXTURE_2D, self.id));
         gl_call!(gl::TexImage2D(
             gl::TEXTURE_2D,
             0,
-            gl::RGB as gl::types::GLint,
+            self.format.gl_internal_format() as gl::types::GLint,
             width as gl::types::GLint,
             height as gl::types::GLint,
             0,
-            gl::RGB,
-            gl::UNSIGNED_BYTE,
-            image_buf.as_ptr() as *const gl::types::GLvoid
+            self.format.gl_format(),
+            self.format.gl_type(),
+            std::ptr::null()
         ));
-        gl_call!(gl::GenerateMipmap(gl::TEXTURE_2D));
         gl_call!(gl::BindTexture(gl::TEXTURE_2D, 0));
-        // Texture { id, width, height }
-        Texture { id }
     }
 
-    pub fn bind(&self) {
-        gl_call!(gl::ActiveTexture(gl::TEXTURE0));
+    pub fn set_wrap(&self, wrap_s: TextureWrap, wrap_t: TextureWrap) {
+        gl_call!(gl::BindTexture(gl::TEXTURE_2D, self.id));
+        set_wrap(gl::TEXTURE_2D, wrap_s, wrap_t);
+        gl_call!(gl::BindTexture(gl::TEXTURE_2D, 0));
+    }
+
+    /// Set the filtering, a mipmap filter only has an effect if the texture was built with
+    /// mipmaps.
+    pub fn set_filter(
+        &self,
+        min_filter: TextureFilter,
+        mag_filter: TextureFilter,
+        mipmap_filter: Option<TextureFilter>,
+    ) {
         gl_call!(gl::BindTexture(gl::TEXTURE_2D, self.id));
+        set_filter(gl::TEXTURE_2D, min_filter, mag_filter, mipmap_filter);
+        gl_call!(gl::BindTexture(gl::TEXTURE_2D, 0));
     }
-    pub fn unbind(&self) {
+
+    /// Clamped to what the driver supports, and ignored without anisotropic filtering support.
+    pub fn set_anisotropy(&self, anisotropy: f32) {
+        gl_call!(gl::BindTexture(gl::TEXTURE_2D, self.id));
+        set_anisotropy(gl::TEXTURE_2D, anisotropy);
+        gl_call!(gl::BindTexture(gl::TEXTURE_2D, 0));
+    }
+
+    /// The most anisotropic filtering samples the driver supports, if it supports them at all.
+    pub fn max_anisotropy() -> Option<f32> {
+        let mut count = 0;
+        gl_call!(gl::GetIntegerv(gl::NUM_EXTENSIONS, &mut count));
+        let supported = (0..count as u32).any(|index| {
+            let name = gl_call!(gl::GetStringi(gl::EXTENSIONS, index));
+            if name.is_null() {
+                return false;
+            }
+            let name = unsafe { std::ffi::CStr::from_ptr(name as *const std::os::raw::c_char) };
+            let name = name.to_bytes();
+            name == b"GL_EXT_texture_filter_anisotropic"
+                || name == b"GL_ARB_texture_filter_anisotropic"
+        });
+        if !supported {
+            return None;
+        }
+        let mut max_anisotropy = 1.0;
+        gl_call!(gl::GetFloatv(
+            MAX_TEXTURE_MAX_ANISOTROPY,
+            &mut max_anisotropy
+        ));
+        Some(max_anisotropy)
+    }
+
+    /// Bind to texture unit `unit`, e.g. `1` for `GL_TEXTURE1`.
+    pub fn bind(&self, unit: u32) {
+        gl_call!(gl::ActiveTexture(gl::TEXTURE0 + unit));
+        gl_call!(gl::BindTexture(gl::TEXTURE_2D, self.id));
         gl_call!(gl::ActiveTexture(gl::TEXTURE0));
+    }
+    pub fn unbind(&self, unit: u32) {
+        gl_call!(gl::ActiveTexture(gl::TEXTURE0 + unit));
         gl_call!(gl::BindTexture(gl::TEXTURE_2D, 0));
+        gl_call!(gl::ActiveTexture(gl::TEXTURE0));
     }
 }
 
@@ -62,3 +826,314 @@ impl Drop for Texture {
         gl_call!(gl::DeleteTextures(1, &self.id));
     }
 }
+
+/// A 2D texture array, read in GLSL as a `sampler2DArray`. Every layer is the same size, and
+/// is picked by the third texture coordinate.
+#[derive(Debug)]
+pub struct TextureArray {
+    id: u32,
+    width: u32,
+    height: u32,
+    layers: u32,
+    format: TextureFormat,
+}
+
+impl TextureArray {
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+    pub fn layers(&self) -> u32 {
+        self.layers
+    }
+    pub fn format(&self) -> TextureFormat {
+        self.format
+    }
+
+    pub fn set_wrap(&self, wrap_s: TextureWrap, wrap_t: TextureWrap) {
+        gl_call!(gl::BindTexture(gl::TEXTURE_2D_ARRAY, self.id));
+        set_wrap(gl::TEXTURE_2D_ARRAY, wrap_s, wrap_t);
+        gl_call!(gl::BindTexture(gl::TEXTURE_2D_ARRAY, 0));
+    }
+
+    /// Set the filtering, a mipmap filter only has an effect if the array was built with
+    /// mipmaps.
+    pub fn set_filter(
+        &self,
+        min_filter: TextureFilter,
+        mag_filter: TextureFilter,
+        mipmap_filter: Option<TextureFilter>,
+    ) {
+        gl_call!(gl::BindTexture(gl::TEXTURE_2D_ARRAY, self.id));
+        set_filter(gl::TEXTURE_2D_ARRAY, min_filter, mag_filter, mipmap_filter);
+        gl_call!(gl::BindTexture(gl::TEXTURE_2D_ARRAY, 0));
+    }
+
+    /// Clamped to what the driver supports, and ignored without anisotropic filtering support.
+    pub fn set_anisotropy(&self, anisotropy: f32) {
+        gl_call!(gl::BindTexture(gl::TEXTURE_2D_ARRAY, self.id));
+        set_anisotropy(gl::TEXTURE_2D_ARRAY, anisotropy);
+        gl_call!(gl::BindTexture(gl::TEXTURE_2D_ARRAY, 0));
+    }
+
+    /// Bind to texture unit `unit`, e.g. `1` for `GL_TEXTURE1`.
+    pub fn bind(&self, unit: u32) {
+        gl_call!(gl::ActiveTexture(gl::TEXTURE0 + unit));
+        gl_call!(gl::BindTexture(gl::TEXTURE_2D_ARRAY, self.id));
+        gl_call!(gl::ActiveTexture(gl::TEXTURE0));
+    }
+    pub fn unbind(&self, unit: u32) {
+        gl_call!(gl::ActiveTexture(gl::TEXTURE0 + unit));
+        gl_call!(gl::BindTexture(gl::TEXTURE_2D_ARRAY, 0));
+        gl_call!(gl::ActiveTexture(gl::TEXTURE0));
+    }
+}
+
+impl Drop for TextureArray {
+    fn drop(&mut self) {
+        gl_call!(gl::DeleteTextures(1, &self.id));
+    }
+}
+
+/// A cube map, read in GLSL as a `samplerCube` with a direction, e.g. an environment map. Its
+/// six square faces are in GL's order: +X, -X, +Y, -Y, +Z, -Z.
+#[derive(Debug)]
+pub struct CubeMap {
+    id: u32,
+    size: u32,
+    format: TextureFormat,
+}
+
+impl CubeMap {
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+    /// Width and height of each face.
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+    pub fn format(&self) -> TextureFormat {
+        self.format
+    }
+
+    /// Bind to texture unit `unit`, e.g. `1` for `GL_TEXTURE1`.
+    pub fn bind(&self, unit: u32) {
+        gl_call!(gl::ActiveTexture(gl::TEXTURE0 + unit));
+        gl_call!(gl::BindTexture(gl::TEXTURE_CUBE_MAP, self.id));
+        gl_call!(gl::ActiveTexture(gl::TEXTURE0));
+    }
+    pub fn unbind(&self, unit: u32) {
+        gl_call!(gl::ActiveTexture(gl::TEXTURE0 + unit));
+        gl_call!(gl::BindTexture(gl::TEXTURE_CUBE_MAP, 0));
+        gl_call!(gl::ActiveTexture(gl::TEXTURE0));
+    }
+}
+
+impl Drop for CubeMap {
+    fn drop(&mut self) {
+        gl_call!(gl::DeleteTextures(1, &self.id));
+    }
+}
+
+/// The direction through a cube map face at `s` and `t` in `[-1, 1]`, with `t` going down the
+/// face, following the face selection table in the GL spec.
+fn cube_map_direction(face: usize, s: f32, t: f32) -> glm::Vec3 {
+    let direction = match face {
+        0 => glm::vec3(1.0, -t, -s),
+        1 => glm::vec3(-1.0, -t, s),
+        2 => glm::vec3(s, 1.0, t),
+        3 => glm::vec3(s, -1.0, -t),
+        4 => glm::vec3(s, -t, 1.0),
+        _ => glm::vec3(-s, -t, -1.0),
+    };
+    glm::normalize(&direction)
+}
+
+/// Bilinearly sample an equirectangular panorama, stored top row first, in a direction.
+/// Longitude wraps around, latitude is clamped at the poles.
+fn sample_equirect(
+    width: u32,
+    height: u32,
+    pixels: &[glm::Vec3],
+    direction: &glm::Vec3,
+) -> glm::Vec3 {
+    let (width, height) = (width as usize, height as usize);
+    let u = direction.z.atan2(direction.x) / (2.0 * std::f32::consts::PI) + 0.5;
+    let v = 0.5 - direction.y.clamp(-1.0, 1.0).asin() / std::f32::consts::PI;
+    let x = u * width as f32 - 0.5;
+    let y = (v * height as f32 - 0.5).clamp(0.0, (height - 1) as f32);
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+    let column = |x: f32| (x as isize).rem_euclid(width as isize) as usize;
+    let (x0, x1) = (column(x0), column(x0 + 1.0));
+    let (y0, y1) = (y0 as usize, (y0 as usize + 1).min(height - 1));
+    let texel = |x: usize, y: usize| pixels[y * width + x];
+    let top = glm::lerp(&texel(x0, y0), &texel(x1, y0), fx);
+    let bottom = glm::lerp(&texel(x0, y1), &texel(x1, y1), fx);
+    glm::lerp(&top, &bottom, fy)
+}
+
+// sampling parameters of whichever texture is bound to `target`
+
+pub(crate) fn set_wrap(target: gl::types::GLenum, wrap_s: TextureWrap, wrap_t: TextureWrap) {
+    gl_call!(gl::TexParameteri(
+        target,
+        gl::TEXTURE_WRAP_S,
+        wrap_s.gl_wrap() as gl::types::GLint
+    ));
+    gl_call!(gl::TexParameteri(
+        target,
+        gl::TEXTURE_WRAP_T,
+        wrap_t.gl_wrap() as gl::types::GLint
+    ));
+}
+
+pub(crate) fn set_filter(
+    target: gl::types::GLenum,
+    min_filter: TextureFilter,
+    mag_filter: TextureFilter,
+    mipmap_filter: Option<TextureFilter>,
+) {
+    gl_call!(gl::TexParameteri(
+        target,
+        gl::TEXTURE_MIN_FILTER,
+        min_filter.gl_min_filter(mipmap_filter) as gl::types::GLint
+    ));
+    gl_call!(gl::TexParameteri(
+        target,
+        gl::TEXTURE_MAG_FILTER,
+        mag_filter.gl_filter() as gl::types::GLint
+    ));
+}
+
+fn set_anisotropy(target: gl::types::GLenum, anisotropy: f32) {
+    if let Some(max_anisotropy) = Texture::max_anisotropy() {
+        gl_call!(gl::TexParameterf(
+            target,
+            TEXTURE_MAX_ANISOTROPY,
+            anisotropy.clamp(1.0, max_anisotropy.max(1.0))
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the face a direction selects, and its `s` and `t` in `[-1, 1]`, straight from the face
+    // selection table in the GL spec
+    fn select_face(direction: &glm::Vec3) -> (usize, f32, f32) {
+        let (x, y, z) = (direction.x, direction.y, direction.z);
+        let (face, sc, tc, ma) = if x.abs() >= y.abs() && x.abs() >= z.abs() {
+            if x > 0.0 {
+                (0, -z, -y, x)
+            } else {
+                (1, z, -y, x)
+            }
+        } else if y.abs() >= z.abs() {
+            if y > 0.0 {
+                (2, x, z, y)
+            } else {
+                (3, x, -z, y)
+            }
+        } else if z > 0.0 {
+            (4, x, -y, z)
+        } else {
+            (5, -x, -y, z)
+        };
+        (face, sc / ma.abs(), tc / ma.abs())
+    }
+
+    fn approx_eq(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1.0e-5
+    }
+
+    #[test]
+    fn cube_map_direction_matches_face_selection() {
+        let coords = [(0.0, 0.0), (0.5, -0.25), (-0.75, 0.9), (0.9, 0.9)];
+        for face in 0..6 {
+            for &(s, t) in &coords {
+                let direction = cube_map_direction(face, s, t);
+                assert!(approx_eq(glm::length(&direction), 1.0));
+                let (selected, sc, tc) = select_face(&direction);
+                assert_eq!(selected, face, "face of ({}, {})", s, t);
+                assert!(approx_eq(sc, s), "face {} s {} != {}", face, sc, s);
+                assert!(approx_eq(tc, t), "face {} t {} != {}", face, tc, t);
+            }
+        }
+    }
+
+    #[test]
+    fn cube_map_direction_face_centres() {
+        let centres = [
+            glm::vec3(1.0, 0.0, 0.0),
+            glm::vec3(-1.0, 0.0, 0.0),
+            glm::vec3(0.0, 1.0, 0.0),
+            glm::vec3(0.0, -1.0, 0.0),
+            glm::vec3(0.0, 0.0, 1.0),
+            glm::vec3(0.0, 0.0, -1.0),
+        ];
+        for (face, centre) in centres.iter().enumerate() {
+            assert!(glm::distance(&cube_map_direction(face, 0.0, 0.0), centre) < 1.0e-6);
+        }
+    }
+
+    // a 4 x 2 panorama, each texel set by its column and row
+    fn panorama<F: Fn(usize, usize) -> f32>(value: F) -> Vec<glm::Vec3> {
+        (0..2)
+            .flat_map(|y| (0..4).map(move |x| (x, y)))
+            .map(|(x, y)| glm::vec3(value(x, y), 0.0, 0.0))
+            .collect()
+    }
+
+    #[test]
+    fn sample_equirect_column_centre() {
+        let pixels = panorama(|x, _| x as f32);
+        // longitude through the middle of column 1
+        let direction = glm::normalize(&glm::vec3(1.0, 0.0, -1.0));
+        let color = sample_equirect(4, 2, &pixels, &direction);
+        assert!(approx_eq(color.x, 1.0));
+    }
+
+    #[test]
+    fn sample_equirect_wraps_longitude() {
+        let pixels = panorama(|x, _| x as f32);
+        // the seam between the last column and the first, from either side
+        for &z in &[0.0, -0.0] {
+            let color = sample_equirect(4, 2, &pixels, &glm::vec3(-1.0, 0.0, z));
+            assert!(approx_eq(color.x, 1.5), "{} at z = {}", color.x, z);
+        }
+    }
+
+    #[test]
+    fn sample_equirect_clamps_poles() {
+        let pixels = panorama(|_, y| y as f32);
+        let up = sample_equirect(4, 2, &pixels, &glm::vec3(0.0, 1.0, 0.0));
+        assert!(approx_eq(up.x, 0.0));
+        let down = sample_equirect(4, 2, &pixels, &glm::vec3(0.0, -1.0, 0.0));
+        assert!(approx_eq(down.x, 1.0));
+        // slightly past the pole, as unnormalized directions can be
+        let past = sample_equirect(4, 2, &pixels, &glm::vec3(1.0e-7, 1.000_001, 0.0));
+        assert!(approx_eq(past.x, 0.0));
+    }
+
+    #[test]
+    fn cube_map_needs_six_faces() {
+        let face = image::DynamicImage::new_rgba8(1, 1);
+        let result = TextureBuilder::new().build_cube_map_from_images(&[face.clone(), face]);
+        assert!(matches!(result, Err(TextureError::FaceCount { faces: 2 })));
+    }
+
+    #[test]
+    fn cube_map_faces_match_the_first() {
+        let mut faces = vec![image::DynamicImage::new_rgba8(1, 1); 6];
+        faces[3] = image::DynamicImage::new_rgba8(2, 2);
+        let result = TextureBuilder::new().build_cube_map_from_images(&faces);
+        assert!(matches!(result, Err(TextureError::FaceSize { face: 3 })));
+    }
+}
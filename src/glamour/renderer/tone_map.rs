@@ -0,0 +1,45 @@
+/// How HDR colours are mapped into the `[0, 1]` range of the screen, after being scaled by the
+/// exposure.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum ToneMap {
+    /// Clamp, so anything brighter than 1 saturates.
+    None,
+    /// `c / (1 + c)`, which never quite reaches white.
+    #[default]
+    Reinhard,
+    /// Narkowicz's fit of the ACES filmic curve, with more contrast than Reinhard.
+    Aces,
+    /// `1 - exp(-c)`, so the exposure sets how quickly colours approach white.
+    Exposure,
+}
+
+impl ToneMap {
+    pub const ALL: [ToneMap; 4] = [
+        ToneMap::None,
+        ToneMap::Reinhard,
+        ToneMap::Aces,
+        ToneMap::Exposure,
+    ];
+
+    /// Matches the `TONE_MAP_*` defines in the tone mapping shader.
+    pub(crate) fn shader_index(&self) -> i32 {
+        match self {
+            ToneMap::None => 0,
+            ToneMap::Reinhard => 1,
+            ToneMap::Aces => 2,
+            ToneMap::Exposure => 3,
+        }
+    }
+}
+
+impl std::fmt::Display for ToneMap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            ToneMap::None => "none",
+            ToneMap::Reinhard => "Reinhard",
+            ToneMap::Aces => "ACES",
+            ToneMap::Exposure => "exposure",
+        };
+        f.write_str(name)
+    }
+}
@@ -3,6 +3,8 @@ use std::convert::TryFrom;
 pub struct VertArray {
     id: u32,
     vert_attr_index: u32,
+    // first attribute index of each vertex buffer passed to `new`
+    buf_attr_indices: Vec<u32>,
     index_buf: IndexBuf,
 }
 
@@ -14,80 +16,106 @@ impl VertArray {
         gl_call!(gl::BindVertexArray(id));
         index_buf.bind();
         let mut vert_attr_index = 0u32;
+        let mut buf_attr_indices = Vec::with_capacity(vert_bufs.len());
         for vert_buf in vert_bufs {
-            vert_buf.bind();
-            let layout = vert_buf.layout();
-            // TODO: check if layout is empty and assert error if so.
-            for attr in layout.attrs.iter() {
-                match attr.attr_type {
-                    VertAttrType::Mat4 => {
-                        for index in 0..4 {
-                            let attr_index = vert_attr_index + index;
-                            gl_call!(gl::EnableVertexAttribArray(attr_index));
-                            // TODO: seemingly, the component count is always 4, could hardwire it: https://stackoverflow.com/questions/23629776/setup-of-matrix-for-instance-shader/23639372#23639372
-                            let component_count = 4;
-                            gl_call!(gl::VertexAttribPointer(
-                                attr_index,
-                                component_count as i32,
-                                attr.gl_data_type(),
-                                if attr.normalized { gl::TRUE } else { gl::FALSE },
-                                layout.stride as i32,
-                                (attr.offset + attr.gl_data_type_size() * 4 * index as usize)
-                                    as *const gl::types::GLvoid,
-                            ));
-                            // FIXME: this should only run if attr is for instancing
-                            gl_call!(gl::VertexAttribDivisor(attr_index, 1));
-                        }
-                        vert_attr_index += 4;
-                    }
-                    VertAttrType::Mat3 => {
-                        for index in 0..3 {
-                            let attr_index = vert_attr_index + index;
-                            gl_call!(gl::EnableVertexAttribArray(attr_index));
-                            let component_count = 4;
-                            // XXX: seems like mat3 is interpreted as literally:
-                            // layout(location = 7) in vec3 in_norm_mat[0];
-                            // layout(location = 8) in vec3 in_norm_mat[3];
-                            // layout(location = 9) in vec3 in_norm_mat[6];
-                            // so offsets needs to be [3, 3, 3], not [4, 4, 1]
-                            let offset =
-                                attr.offset + attr.gl_data_type_size() * 3 * index as usize;
-                            gl_call!(gl::VertexAttribPointer(
-                                attr_index,
-                                component_count as i32,
-                                attr.gl_data_type(),
-                                if attr.normalized { gl::TRUE } else { gl::FALSE },
-                                layout.stride as i32,
-                                offset as *const gl::types::GLvoid,
-                            ));
-                            // FIXME: this should only run if attr is for instancing
-                            gl_call!(gl::VertexAttribDivisor(attr_index, 1));
-                        }
-                        vert_attr_index += 3;
+            buf_attr_indices.push(vert_attr_index);
+            vert_attr_index = VertArray::specify_attrs(*vert_buf, vert_attr_index, 0);
+        }
+        gl_call!(gl::BindVertexArray(0));
+        index_buf.unbind();
+        VertArray {
+            id,
+            vert_attr_index,
+            buf_attr_indices,
+            index_buf,
+        }
+    }
+
+    /// Point a buffer's attributes at `first_vert` onwards, e.g. to draw a range of instances
+    /// without `glDrawElementsInstancedBaseInstance`, which needs GL 4.2.
+    /// `buf_index` is the buffer's position in the slice passed to `new`.
+    pub fn set_buf_offset(&self, buf_index: usize, vert_buf: &dyn VertBuffer, first_vert: usize) {
+        self.bind();
+        let base_offset = first_vert * vert_buf.layout().stride as usize;
+        VertArray::specify_attrs(vert_buf, self.buf_attr_indices[buf_index], base_offset);
+        self.unbind();
+    }
+
+    /// Enable and point the attributes of `vert_buf`, starting at `vert_attr_index` and
+    /// `base_offset` bytes into the buffer. Returns the next free attribute index.
+    fn specify_attrs(
+        vert_buf: &dyn VertBuffer,
+        mut vert_attr_index: u32,
+        base_offset: usize,
+    ) -> u32 {
+        vert_buf.bind();
+        let layout = vert_buf.layout();
+        // TODO: check if layout is empty and assert error if so.
+        for attr in layout.attrs.iter() {
+            match attr.attr_type {
+                VertAttrType::Mat4 => {
+                    for index in 0..4 {
+                        let attr_index = vert_attr_index + index;
+                        gl_call!(gl::EnableVertexAttribArray(attr_index));
+                        // TODO: seemingly, the component count is always 4, could hardwire it: https://stackoverflow.com/questions/23629776/setup-of-matrix-for-instance-shader/23639372#23639372
+                        let component_count = 4;
+                        gl_call!(gl::VertexAttribPointer(
+                            attr_index,
+                            component_count as i32,
+                            attr.gl_data_type(),
+                            if attr.normalized { gl::TRUE } else { gl::FALSE },
+                            layout.stride as i32,
+                            (base_offset
+                                + attr.offset
+                                + attr.gl_data_type_size() * 4 * index as usize)
+                                as *const gl::types::GLvoid,
+                        ));
+                        gl_call!(gl::VertexAttribDivisor(attr_index, layout.divisor));
                     }
-                    _ => {
-                        gl_call!(gl::EnableVertexAttribArray(vert_attr_index));
+                    vert_attr_index += 4;
+                }
+                VertAttrType::Mat3 => {
+                    for index in 0..3 {
+                        let attr_index = vert_attr_index + index;
+                        gl_call!(gl::EnableVertexAttribArray(attr_index));
+                        let component_count = 4;
+                        // XXX: seems like mat3 is interpreted as literally:
+                        // layout(location = 7) in vec3 in_norm_mat[0];
+                        // layout(location = 8) in vec3 in_norm_mat[3];
+                        // layout(location = 9) in vec3 in_norm_mat[6];
+                        // so offsets needs to be [3, 3, 3], not [4, 4, 1]
+                        let offset = base_offset
+                            + attr.offset
+                            + attr.gl_data_type_size() * 3 * index as usize;
                         gl_call!(gl::VertexAttribPointer(
-                            vert_attr_index,
-                            attr.count() as i32,
+                            attr_index,
+                            component_count as i32,
                             attr.gl_data_type(),
                             if attr.normalized { gl::TRUE } else { gl::FALSE },
                             layout.stride as i32,
-                            attr.offset as *const gl::types::GLvoid,
+                            offset as *const gl::types::GLvoid,
                         ));
-                        vert_attr_index += 1;
+                        gl_call!(gl::VertexAttribDivisor(attr_index, layout.divisor));
                     }
+                    vert_attr_index += 3;
+                }
+                _ => {
+                    gl_call!(gl::EnableVertexAttribArray(vert_attr_index));
+                    gl_call!(gl::VertexAttribPointer(
+                        vert_attr_index,
+                        attr.count() as i32,
+                        attr.gl_data_type(),
+                        if attr.normalized { gl::TRUE } else { gl::FALSE },
+                        layout.stride as i32,
+                        (base_offset + attr.offset) as *const gl::types::GLvoid,
+                    ));
+                    gl_call!(gl::VertexAttribDivisor(vert_attr_index, layout.divisor));
+                    vert_attr_index += 1;
                 }
             }
-            vert_buf.unbind();
-        }
-        gl_call!(gl::BindVertexArray(0));
-        index_buf.unbind();
-        VertArray {
-            id,
-            vert_attr_index,
-            index_buf,
         }
+        vert_buf.unbind();
+        vert_attr_index
     }
     pub fn bind(&self) {
         gl_call!(gl::BindVertexArray(self.id));
@@ -122,6 +150,10 @@ impl VertArray {
                 layout.stride as i32,
                 attr.offset as *const gl::types::GLvoid,
             ));
+            gl_call!(gl::VertexAttribDivisor(
+                self.vert_attr_index,
+                layout.divisor
+            ));
             self.vert_attr_index += 1;
         }
         self.unbind();
@@ -169,6 +201,9 @@ impl IndexBuf {
     pub fn len(&self) -> usize {
         self.indices.len()
     }
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
     pub fn indices(&self) -> &[u32] {
         &self.indices
     }
@@ -209,42 +244,184 @@ impl<T: Vert> VertBuffer for VertBuf<T> {
     }
 }
 
+// segments in a `StreamMode::PersistentRing` buffer, so the CPU can write one while the GPU
+// still reads the last two frames'
+const RING_SEGMENTS: usize = 3;
+// nanoseconds to wait on a segment's fence before asking again
+const RING_FENCE_TIMEOUT: u64 = 1_000_000;
+
+/// How `VertBuf::set_data` uploads vertices that change every frame, e.g. instance transforms.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum StreamMode {
+    /// `glBufferSubData` into the same storage, which can stall until the GPU has finished
+    /// reading the last frame's vertices.
+    #[default]
+    SubData,
+    /// Orphan the storage with `glBufferData` before each upload, so the driver can hand out
+    /// fresh memory while the old one is still being read.
+    Orphan,
+    /// A persistently mapped buffer with room for a few frames, written a segment at a time
+    /// once a fence says the GPU is done with it. Needs GL 4.4, see `is_supported`.
+    PersistentRing,
+}
+
+impl StreamMode {
+    pub const ALL: [StreamMode; 3] = [
+        StreamMode::SubData,
+        StreamMode::Orphan,
+        StreamMode::PersistentRing,
+    ];
+
+    /// Whether the context can stream this way, `PersistentRing` needs `glBufferStorage`.
+    pub fn is_supported(self) -> bool {
+        match self {
+            StreamMode::SubData | StreamMode::Orphan => true,
+            StreamMode::PersistentRing => {
+                let mut major = 0;
+                let mut minor = 0;
+                gl_call!(gl::GetIntegerv(gl::MAJOR_VERSION, &mut major));
+                gl_call!(gl::GetIntegerv(gl::MINOR_VERSION, &mut minor));
+                (major, minor) >= (4, 4)
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for StreamMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            StreamMode::SubData => "sub data",
+            StreamMode::Orphan => "orphan",
+            StreamMode::PersistentRing => "persistent ring",
+        };
+        f.write_str(name)
+    }
+}
+
+// the mapped storage of a `StreamMode::PersistentRing` buffer
+struct Ring {
+    ptr: *mut u8,
+    segment: usize,
+    fences: [gl::types::GLsync; RING_SEGMENTS],
+}
+
+impl Ring {
+    fn delete_fences(&mut self) {
+        for fence in self.fences.iter_mut().filter(|fence| !fence.is_null()) {
+            gl_call!(gl::DeleteSync(*fence));
+            *fence = std::ptr::null();
+        }
+    }
+}
+
 pub struct VertBuf<T: Vert> {
     id: u32,
     vertices: Vec<T>,
     layout: VertLayout,
+    mode: StreamMode,
+    // vertices the storage has room for, per segment with a ring
+    capacity: usize,
+    ring: Option<Ring>,
 }
 
 impl<T: Vert> VertBuf<T> {
     pub fn new(vertices: Vec<T>) -> Self {
-        let mut id = 0;
-        gl_call!(gl::GenBuffers(1, &mut id));
-        // select the buffer as an simple array
-        gl_call!(gl::BindBuffer(gl::ARRAY_BUFFER, id));
-        // TODO: maybe use the layout sizes for this...
-        let size = gl::types::GLsizeiptr::try_from(vertices.capacity() * std::mem::size_of::<T>())
-            .unwrap();
-        let ptr = vertices.as_ptr() as *const gl::types::GLvoid;
-        // fill selected buffer with data
-        gl_call!(gl::BufferData(
-            gl::ARRAY_BUFFER, // target buffer type
-            size,             // size of data in bytes
-            ptr,              // pointer to data
-            gl::DYNAMIC_DRAW, // usage hint
-        ));
-        gl_call!(gl::BindBuffer(gl::ARRAY_BUFFER, 0));
-        VertBuf {
-            id,
+        VertBuf::with_stream_mode(vertices, StreamMode::SubData)
+    }
+
+    /// Storage for `vertices.capacity()` vertices, uploaded by `set_data` the way `mode` says.
+    ///
+    /// # Panics
+    /// If `mode` isn't supported by the context, see `StreamMode::is_supported`.
+    pub fn with_stream_mode(vertices: Vec<T>, mode: StreamMode) -> Self {
+        assert!(mode.is_supported(), "{} streaming isn't supported", mode);
+        let capacity = vertices.capacity();
+        let mut vert_buf = VertBuf {
+            id: 0,
             vertices,
             layout: T::layout(),
+            mode,
+            capacity: 0,
+            ring: None,
+        };
+        vert_buf.allocate(capacity);
+        vert_buf.bind();
+        match &vert_buf.ring {
+            Some(ring) => VertBuf::write_segment(ring, 0, vert_buf.capacity, &vert_buf.vertices),
+            None => vert_buf.sub_data(),
         }
+        vert_buf.unbind();
+        vert_buf
     }
+
+    /// (Re)create the storage with room for `capacity` vertices, leaving it bound. A ring is
+    /// immutable storage, so it gets a new buffer, everything else keeps its id.
+    fn allocate(&mut self, capacity: usize) {
+        // empty storage can't be mapped
+        let capacity = capacity.max(1);
+        // TODO: maybe use the layout sizes for this...
+        let size = gl::types::GLsizeiptr::try_from(capacity * std::mem::size_of::<T>()).unwrap();
+        if self.id == 0 || self.ring.is_some() {
+            self.delete();
+            gl_call!(gl::GenBuffers(1, &mut self.id));
+        }
+        // select the buffer as an simple array
+        self.bind();
+        self.capacity = capacity;
+        self.ring = match self.mode {
+            StreamMode::SubData | StreamMode::Orphan => {
+                gl_call!(gl::BufferData(
+                    gl::ARRAY_BUFFER, // target buffer type
+                    size,             // size of data in bytes
+                    std::ptr::null(), // filled in by `set_data`
+                    if self.mode == StreamMode::Orphan {
+                        gl::STREAM_DRAW
+                    } else {
+                        gl::DYNAMIC_DRAW
+                    }, // usage hint
+                ));
+                None
+            }
+            StreamMode::PersistentRing => {
+                let flags = gl::MAP_WRITE_BIT | gl::MAP_PERSISTENT_BIT | gl::MAP_COHERENT_BIT;
+                let ring_size = size * RING_SEGMENTS as gl::types::GLsizeiptr;
+                gl_call!(gl::BufferStorage(
+                    gl::ARRAY_BUFFER,
+                    ring_size,
+                    std::ptr::null(),
+                    flags
+                ));
+                let ptr = gl_call!(gl::MapBufferRange(gl::ARRAY_BUFFER, 0, ring_size, flags));
+                assert!(!ptr.is_null(), "failed to map ring buffer");
+                Some(Ring {
+                    ptr: ptr as *mut u8,
+                    segment: 0,
+                    fences: [std::ptr::null(); RING_SEGMENTS],
+                })
+            }
+        };
+    }
+
     pub fn id(&self) -> u32 {
         self.id
     }
     pub fn layout(&self) -> &VertLayout {
         &self.layout
     }
+    pub fn stream_mode(&self) -> StreamMode {
+        self.mode
+    }
+    /// Vertices the storage has room for before `set_data` has to grow it.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+    /// Where the last `set_data` put the first vertex in the buffer. Always 0 unless the buffer
+    /// is a ring, whose attributes need pointing at the current segment before drawing.
+    pub fn first_vert(&self) -> usize {
+        self.ring
+            .as_ref()
+            .map_or(0, |ring| ring.segment * self.capacity)
+    }
     pub fn bind(&self) {
         gl_call!(gl::BindBuffer(gl::ARRAY_BUFFER, self.id))
     }
@@ -257,21 +434,93 @@ impl<T: Vert> VertBuf<T> {
     pub fn vertices_mut(&mut self) -> &mut Vec<T> {
         &mut self.vertices
     }
-    pub fn set_data(&self) {
+    /// Upload all the vertices, growing the buffer if they no longer fit. A ring gets a new
+    /// `id` when it grows, so anything pointing at it has to be pointed again.
+    pub fn set_data(&mut self) {
+        if self.vertices.len() > self.capacity {
+            self.allocate(self.vertices.len().next_power_of_two());
+            self.unbind();
+        }
+        if let Some(ring) = &mut self.ring {
+            // the GPU is done with the current segment once it passes everything queued so far
+            ring.fences[ring.segment] = gl_call!(gl::FenceSync(gl::SYNC_GPU_COMMANDS_COMPLETE, 0));
+            ring.segment = (ring.segment + 1) % RING_SEGMENTS;
+            let fence = std::mem::replace(&mut ring.fences[ring.segment], std::ptr::null());
+            if !fence.is_null() {
+                loop {
+                    let status = gl_call!(gl::ClientWaitSync(
+                        fence,
+                        gl::SYNC_FLUSH_COMMANDS_BIT,
+                        RING_FENCE_TIMEOUT
+                    ));
+                    if status != gl::TIMEOUT_EXPIRED {
+                        break;
+                    }
+                }
+                gl_call!(gl::DeleteSync(fence));
+            }
+            VertBuf::write_segment(ring, ring.segment, self.capacity, &self.vertices);
+            return;
+        }
         self.bind();
+        if self.mode == StreamMode::Orphan {
+            let size =
+                gl::types::GLsizeiptr::try_from(self.capacity * std::mem::size_of::<T>()).unwrap();
+            gl_call!(gl::BufferData(
+                gl::ARRAY_BUFFER,
+                size,
+                std::ptr::null(),
+                gl::STREAM_DRAW
+            ));
+        }
+        self.sub_data();
+        self.unbind();
+    }
+
+    // with the buffer bound
+    fn sub_data(&self) {
         let size = gl::types::GLsizeiptr::try_from(self.vertices.len() * std::mem::size_of::<T>())
             .unwrap();
         let ptr = self.vertices.as_ptr() as *const gl::types::GLvoid;
         // fill selected buffer with data
         gl_call!(gl::BufferSubData(gl::ARRAY_BUFFER, 0, size, ptr));
-        self.unbind();
-        // self.vertices = vertices;
+    }
+
+    fn write_segment(ring: &Ring, segment: usize, capacity: usize, vertices: &[T]) {
+        assert!(vertices.len() <= capacity, "segment overflow");
+        // safe as long as the segment is in the mapping and the GPU isn't reading it, which the
+        // fences take care of
+        unsafe {
+            let dst = ring.ptr.add(segment * capacity * std::mem::size_of::<T>());
+            std::ptr::copy_nonoverlapping(
+                vertices.as_ptr() as *const u8,
+                dst,
+                std::mem::size_of_val(vertices),
+            );
+        }
+    }
+
+    // the storage and anything that goes with it, the buffer may still be in use by the GPU,
+    // which GL copes with
+    fn delete(&mut self) {
+        if self.id == 0 {
+            return;
+        }
+        if let Some(ring) = &mut self.ring {
+            ring.delete_fences();
+            self.bind();
+            gl_call!(gl::UnmapBuffer(gl::ARRAY_BUFFER));
+            self.unbind();
+        }
+        self.ring = None;
+        gl_call!(gl::DeleteBuffers(1, &self.id));
+        self.id = 0;
     }
 }
 
 impl<T: Vert> Drop for VertBuf<T> {
     fn drop(&mut self) {
-        gl_call!(gl::DeleteBuffers(1, &self.id));
+        self.delete();
     }
 }
 
@@ -279,9 +528,18 @@ impl<T: Vert> Drop for VertBuf<T> {
 pub struct VertLayout {
     attrs: Vec<VertAttr>,
     stride: u32,
+    // 0 to advance per vertex, 1 to advance per instance
+    divisor: u32,
 }
 
 impl VertLayout {
+    /// A layout for per-instance data, e.g. transforms for instanced draws.
+    pub fn new_instanced(attrs: Vec<VertAttr>) -> Self {
+        VertLayout {
+            divisor: 1,
+            ..VertLayout::new(attrs)
+        }
+    }
     pub fn new(mut attrs: Vec<VertAttr>) -> Self {
         let mut offset: usize = 0;
         let mut stride = 0;
@@ -299,6 +557,7 @@ impl VertLayout {
         VertLayout {
             attrs,
             stride: stride as u32,
+            divisor: 0,
         }
     }
 }
@@ -320,6 +579,7 @@ impl VertAttr {
     }
     pub fn count(&self) -> usize {
         match self.attr_type {
+            VertAttrType::Float => 1,
             VertAttrType::Float2 => 2,
             VertAttrType::Float3 => 3,
             VertAttrType::Mat3 => 3 * 3,
@@ -332,6 +592,7 @@ impl VertAttr {
     }
     pub fn gl_data_type(&self) -> u32 {
         match self.attr_type {
+            VertAttrType::Float => gl::FLOAT,
             VertAttrType::Float2 => gl::FLOAT,
             VertAttrType::Float3 => gl::FLOAT,
             VertAttrType::Mat3 => gl::FLOAT,
@@ -348,6 +609,7 @@ impl VertAttr {
 
 #[derive(Debug, Copy, Clone)]
 pub enum VertAttrType {
+    Float,
     Float2,
     Float3,
     Mat3,
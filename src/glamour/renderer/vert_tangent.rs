@@ -0,0 +1,205 @@
+use crate::{glm, Vert, VertAttr, VertAttrType, VertBasic, VertLayout};
+
+/// The tangent space of a vertex, for normal mapping. It goes alongside a `VertBasic` buffer,
+/// with the normal making up the third axis.
+#[derive(Debug, Copy, Clone)]
+#[repr(C)]
+pub struct VertTangent {
+    /// Points along the increasing `u` texture coordinate.
+    pub tangent: glm::Vec3,
+    /// Points along the increasing `v` texture coordinate.
+    pub bitangent: glm::Vec3,
+}
+
+impl Vert for VertTangent {
+    fn layout() -> VertLayout {
+        VertLayout::new(vec![
+            VertAttr::new(VertAttrType::Float3, false),
+            VertAttr::new(VertAttrType::Float3, false),
+        ])
+    }
+}
+
+impl Default for VertTangent {
+    fn default() -> Self {
+        VertTangent {
+            tangent: glm::vec3(1.0, 0.0, 0.0),
+            bitangent: glm::vec3(0.0, 1.0, 0.0),
+        }
+    }
+}
+
+/// Generate per-vertex tangents from the texture coordinates of each triangle, averaged across
+/// shared vertices and made perpendicular to the vertex normals. With no indices, every three
+/// vertices are a triangle.
+///
+/// Faces that refer to missing vertices are skipped. Vertices without usable texture coordinates
+/// or normals still get an orthogonal tangent space, just an arbitrary one.
+///
+/// ```
+/// # use glamour::{generate_tangents, glm, meshes};
+/// let tangents = generate_tangents(&meshes::quad::quad_verts(), &meshes::quad::quad_inds());
+/// for t in tangents {
+///     assert!(glm::distance(&t.tangent, &glm::vec3(1.0, 0.0, 0.0)) < 1e-5);
+///     assert!(glm::distance(&t.bitangent, &glm::vec3(0.0, 1.0, 0.0)) < 1e-5);
+/// }
+/// ```
+pub fn generate_tangents(vertices: &[VertBasic], indices: &[u32]) -> Vec<VertTangent> {
+    let zero = glm::vec3(0.0, 0.0, 0.0);
+    let mut tangents = vec![(zero, zero); vertices.len()];
+    let sequential: Vec<u32>;
+    let indices = if indices.is_empty() {
+        sequential = (0..vertices.len() as u32).collect();
+        &sequential
+    } else {
+        indices
+    };
+    for face in indices.chunks_exact(3) {
+        let (a, b, c) = (face[0] as usize, face[1] as usize, face[2] as usize);
+        if a.max(b).max(c) >= vertices.len() {
+            continue;
+        }
+        let edge1 = vertices[b].position - vertices[a].position;
+        let edge2 = vertices[c].position - vertices[a].position;
+        let delta1 = vertices[b].tex_coords - vertices[a].tex_coords;
+        let delta2 = vertices[c].tex_coords - vertices[a].tex_coords;
+        let det = delta1.x * delta2.y - delta2.x * delta1.y;
+        // no texture coordinates, or they're degenerate
+        if det.is_nan() || det.abs() < f32::EPSILON {
+            continue;
+        }
+        let tangent = (edge1 * delta2.y - edge2 * delta1.y) / det;
+        let bitangent = (edge2 * delta1.x - edge1 * delta2.x) / det;
+        for &i in &[a, b, c] {
+            tangents[i].0 += tangent;
+            tangents[i].1 += bitangent;
+        }
+    }
+    vertices
+        .iter()
+        .zip(tangents)
+        .map(|(v, (tangent, bitangent))| {
+            let length = glm::length(&v.normal);
+            if !(length.is_finite() && length > 1e-6) {
+                // no normal to build a tangent space around
+                return VertTangent::default();
+            }
+            let normal = v.normal / length;
+            // Gram-Schmidt, so the tangent space is orthogonal
+            let mut tangent = tangent - normal * glm::dot(&normal, &tangent);
+            let tangent_length = glm::length(&tangent);
+            if !(tangent_length.is_finite() && tangent_length > 1e-6) {
+                // anything perpendicular to the normal will do
+                let axis = if normal.x.abs() < 0.9 {
+                    glm::vec3(1.0, 0.0, 0.0)
+                } else {
+                    glm::vec3(0.0, 1.0, 0.0)
+                };
+                tangent = glm::cross(&axis, &normal);
+            }
+            let tangent = glm::normalize(&tangent);
+            // keep the bitangent's handedness, in case the texture is mirrored
+            let handedness = if glm::dot(&glm::cross(&normal, &tangent), &bitangent) < 0.0 {
+                -1.0
+            } else {
+                1.0
+            };
+            VertTangent {
+                tangent,
+                bitangent: glm::cross(&normal, &tangent) * handedness,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::meshes;
+
+    fn assert_orthonormal(normal: &glm::Vec3, t: &VertTangent) {
+        let normal = glm::normalize(normal);
+        assert!((glm::length(&t.tangent) - 1.0).abs() < 1e-5);
+        assert!((glm::length(&t.bitangent) - 1.0).abs() < 1e-5);
+        assert!(glm::dot(&t.tangent, &normal).abs() < 1e-5);
+        assert!(glm::dot(&t.bitangent, &normal).abs() < 1e-5);
+        assert!(glm::dot(&t.tangent, &t.bitangent).abs() < 1e-5);
+    }
+
+    #[test]
+    fn quad_tangents_follow_its_texture_coordinates() {
+        let vertices = meshes::quad::quad_verts();
+        let tangents = generate_tangents(&vertices, &meshes::quad::quad_inds());
+        assert_eq!(tangents.len(), vertices.len());
+        for (v, t) in vertices.iter().zip(&tangents) {
+            assert_orthonormal(&v.normal, t);
+            assert!(glm::distance(&t.tangent, &glm::vec3(1.0, 0.0, 0.0)) < 1e-5);
+            assert!(glm::distance(&t.bitangent, &glm::vec3(0.0, 1.0, 0.0)) < 1e-5);
+        }
+    }
+
+    #[test]
+    fn cube_tangents_are_orthonormal_and_follow_uvs() {
+        let vertices = meshes::cube::tex_cube_verts();
+        let tangents = meshes::cube::tex_cube_tangents();
+        assert_eq!(tangents.len(), vertices.len());
+        for (v, t) in vertices.iter().zip(&tangents) {
+            assert_orthonormal(&v.normal, t);
+        }
+        for face in meshes::cube::tex_cube_inds().chunks_exact(3) {
+            let (a, b) = (&vertices[face[0] as usize], &vertices[face[1] as usize]);
+            let t = &tangents[face[0] as usize];
+            // moving along an edge moves along the texture by the tangent space's projection
+            let edge = b.position - a.position;
+            let delta = b.tex_coords - a.tex_coords;
+            let along = glm::vec2(glm::dot(&edge, &t.tangent), glm::dot(&edge, &t.bitangent));
+            assert!(glm::distance(&along, &delta) < 1e-5);
+        }
+    }
+
+    #[test]
+    fn degenerate_triangles_fall_back_to_an_orthogonal_tangent() {
+        let vertex = |x: f32, y: f32, normal: glm::Vec3| VertBasic {
+            position: glm::vec3(x, y, 0.0),
+            normal,
+            tex_coords: glm::vec2(0.0, 0.0),
+        };
+        let up = glm::vec3(0.0, 0.0, 1.0);
+        // every vertex shares the same texture coordinates
+        let vertices = vec![
+            vertex(0.0, 0.0, up),
+            vertex(1.0, 0.0, up),
+            vertex(0.0, 1.0, up),
+        ];
+        for t in generate_tangents(&vertices, &[]) {
+            assert_orthonormal(&up, &t);
+        }
+
+        let zero = glm::vec3(0.0, 0.0, 0.0);
+        let vertices = vec![
+            vertex(0.0, 0.0, zero),
+            vertex(1.0, 0.0, zero),
+            vertex(0.0, 1.0, zero),
+        ];
+        for t in generate_tangents(&vertices, &[0, 1, 2]) {
+            assert!(t
+                .tangent
+                .iter()
+                .chain(t.bitangent.iter())
+                .all(|x| x.is_finite()));
+            assert!(glm::dot(&t.tangent, &t.bitangent).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn faces_with_missing_vertices_are_skipped() {
+        let vertices = meshes::quad::quad_verts();
+        let mut indices = meshes::quad::quad_inds();
+        indices.extend_from_slice(&[0, 1, vertices.len() as u32]);
+        let tangents = generate_tangents(&vertices, &indices);
+        assert_eq!(tangents.len(), vertices.len());
+        for t in tangents {
+            assert!(glm::distance(&t.tangent, &glm::vec3(1.0, 0.0, 0.0)) < 1e-5);
+        }
+    }
+}
@@ -6,6 +6,9 @@ use std::default::Default;
 pub struct VertTrans {
     pub transform: glm::Mat4,
     pub normal: glm::Mat3,
+    /// Which layer of the material's albedo texture array to sample, as a float so it can be
+    /// read as a regular vertex attribute.
+    pub layer: f32,
 }
 
 impl VertTrans {
@@ -14,6 +17,7 @@ impl VertTrans {
         VertTrans {
             transform: matrix,
             normal: Transform::normal_matrix(&matrix),
+            layer: 0.0,
         }
     }
     pub fn set(&mut self, transform: &Transform) {
@@ -25,9 +29,10 @@ impl VertTrans {
 
 impl Vert for VertTrans {
     fn layout() -> crate::VertLayout {
-        VertLayout::new(vec![
+        VertLayout::new_instanced(vec![
             VertAttr::new(VertAttrType::Mat4, false),
             VertAttr::new(VertAttrType::Mat3, false),
+            VertAttr::new(VertAttrType::Float, false),
         ])
     }
 }
@@ -37,6 +42,7 @@ impl Default for VertTrans {
         VertTrans {
             transform: glm::identity(),
             normal: glm::identity(),
+            layer: 0.0,
         }
     }
 }
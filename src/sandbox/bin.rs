@@ -1,12 +1,30 @@
-use glamour::App;
+use glamour::{App, PngSequence};
 
 mod sandbox_layer;
 use sandbox_layer::SandboxLayer;
 
 fn main() {
     let resolution = (512, 490);
-    let mut app = App::new("Glamour Sandbox", resolution.0, resolution.1);
-    let sandbox_layer = SandboxLayer::new("SandboxLayer", resolution);
+    // `sandbox --headless <frames>` renders offscreen for a number of frames, then exits
+    let args: Vec<String> = std::env::args().collect();
+    let mut app = match args.iter().position(|a| a == "--headless") {
+        Some(index) => {
+            let frames = args
+                .get(index + 1)
+                .map(|f| f.parse().expect("frames must be a number"))
+                .unwrap_or(100);
+            let mut app = App::new_headless(resolution.0, resolution.1);
+            app.set_frame_limit(Some(frames));
+            app
+        }
+        None => App::new("Glamour Sandbox", resolution.0, resolution.1),
+    };
+    let mut sandbox_layer = SandboxLayer::new("SandboxLayer", resolution);
+    // `--capture <dir>` saves every frame as a PNG sequence
+    if let Some(index) = args.iter().position(|a| a == "--capture") {
+        let dir = std::path::PathBuf::from(args.get(index + 1).expect("missing capture dir"));
+        sandbox_layer.capture_to(PngSequence::new(&dir, "frame").expect("create failed"));
+    }
     app.push_layer(Box::new(sandbox_layer));
     app.run();
 }
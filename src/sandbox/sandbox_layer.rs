@@ -1,8 +1,13 @@
 use bracket_noise::prelude::*;
-use glamour::{glm, Camera, Layer, Renderer, Transform};
+use glamour::{
+    glm, Camera, CubeMap, DirectionalLight, GBufLayout, Layer, Material, MaterialHandle,
+    PngSequence, PointLight, Renderer, SpotLight, StreamMode, TextureBuilder, TextureFormat,
+    ToneMap, Transform, SSAO_MAX_KERNEL_SIZE,
+};
 use rand::{Rng, SeedableRng};
 use rayon::prelude::*;
 use std::ffi::CString;
+use std::rc::Rc;
 
 pub struct SandboxLayer {
     max_cubes: usize,
@@ -15,18 +20,40 @@ pub struct SandboxLayer {
     cube_distribution: rand::distributions::Uniform<f32>,
     cube_transforms: Vec<Transform>,
     light_count: usize,
-    light_transforms: Vec<Transform>,
+    lights: Vec<PointLight>,
+    spot_light_count: usize,
+    spot_lights: Vec<SpotLight>,
+    sun: bool,
+    shadows: bool,
+    // the last tenth of the cubes are drawn with the glass material
+    glass: bool,
+    glass_material: MaterialHandle,
+    // a generated sky, set as the renderer's environment map while `environment` is on
+    environment: bool,
+    sky: Rc<CubeMap>,
     rng: rand_chacha::ChaCha8Rng,
     noise: FastNoise,
     selected_resolution: usize,
     selected_renderer: usize,
+    selected_tone_map: usize,
+    selected_g_buf_layout: usize,
+    selected_msaa: usize,
+    selected_stream_mode: usize,
+    capture: Option<PngSequence>,
 }
 
 impl SandboxLayer {
     pub fn new(name: &str, resolution: (u32, u32)) -> Self {
         let max_cubes = 200_000;
-        let max_lights = 1019;
-        let fr = Renderer::new(resolution, max_cubes, max_lights);
+        let max_lights = 10_000;
+        let mut fr = Renderer::new(resolution, max_cubes, max_lights);
+        let glass_material = fr.register_material(Material {
+            albedo_color: glm::vec4(0.6, 0.8, 1.0, 0.3),
+            specular_strength: 1.0,
+            transparent: true,
+            ..Material::new()
+        });
+        let sky = Rc::new(sky_cube_map());
 
         let seed = 912;
         let rng = rand_chacha::ChaCha8Rng::seed_from_u64(seed);
@@ -44,19 +71,36 @@ impl SandboxLayer {
             cube_distribution: rand::distributions::Uniform::from(-100.0..100.0),
             cube_transforms: Vec::new(),
             light_count: 32,
-            light_transforms: Vec::new(),
+            lights: Vec::new(),
+            spot_light_count: 0,
+            spot_lights: Vec::new(),
+            sun: false,
+            shadows: false,
+            glass: false,
+            glass_material,
+            environment: false,
+            sky,
             rng,
             noise,
             selected_resolution: 0,
             selected_renderer: 0,
+            selected_tone_map: 1,
+            selected_g_buf_layout: 0,
+            selected_msaa: 0,
+            selected_stream_mode: 0,
+            capture: None,
         }
     }
+    pub fn capture_to(&mut self, sequence: PngSequence) {
+        self.capture = Some(sequence);
+    }
 }
 
 impl Layer for SandboxLayer {
     fn init(&mut self, app_context: &mut glamour::AppContext) {
-        let size = app_context.windowed_context().window().inner_size();
+        let size = app_context.size();
         self.renderer.resize(size.width, size.height);
+        self.camera.aspect = size.width as f32 / size.height as f32;
     }
 
     fn on_frame_update(&mut self, app_context: &mut glamour::AppContext) {
@@ -84,12 +128,27 @@ impl Layer for SandboxLayer {
             ))
         });
 
-        self.light_transforms.resize_with(self.light_count, || {
-            Transform::from_pos(glm::vec3(
-                rng.sample(range),
-                rng.sample(range),
-                rng.sample(range),
-            ))
+        self.lights.resize_with(self.light_count, || {
+            let color: glm::Vec3 = glm::vec3(rng.gen(), rng.gen(), rng.gen());
+            PointLight {
+                color: color / color.max(),
+                ..PointLight::from_pos(glm::vec3(
+                    rng.sample(range),
+                    rng.sample(range),
+                    rng.sample(range),
+                ))
+            }
+        });
+
+        // spot lights stay put, pointing at the centre of the cubes
+        self.spot_lights.resize_with(self.spot_light_count, || {
+            let color: glm::Vec3 = glm::vec3(rng.gen(), rng.gen(), rng.gen());
+            let position = glm::vec3(rng.sample(range), rng.sample(range), rng.sample(range));
+            SpotLight {
+                color: color / color.max(),
+                intensity: 4.0,
+                ..SpotLight::from_pos_dir(position, -position)
+            }
         });
 
         self.renderer.begin_draw(&self.camera);
@@ -104,24 +163,51 @@ impl Layer for SandboxLayer {
                     &glm::vec3(0.5, 1.0, 0.0),
                 );
             });
-        self.renderer.set_cubes(&self.cube_transforms);
+        if self.glass {
+            let (opaque, glass) = self.cube_transforms.split_at(self.cube_count * 9 / 10);
+            self.renderer.set_cubes(opaque);
+            let cube_mesh = self.renderer.cube_mesh();
+            self.renderer
+                .draw_mesh_with_material(cube_mesh, self.glass_material, glass);
+        } else {
+            self.renderer.set_cubes(&self.cube_transforms);
+        }
 
         let distance = 50.0;
         let noise = &self.noise;
-        self.light_transforms
+        let shadows = self.shadows;
+        self.lights
             .par_iter_mut()
             .enumerate()
-            .for_each(|(index, transform)| {
+            .for_each(|(index, light)| {
                 let offset = index as f32;
-                transform.position = glm::vec3(
+                light.position = glm::vec3(
                     noise.get_noise3d(time + offset, 0.0, 0.0),
                     noise.get_noise3d(0.0, time + offset, 0.0),
                     noise.get_noise3d(0.0, 0.0, time + offset),
-                ) * distance
+                ) * distance;
+                // only the first `Renderer::max_point_shadows` get them
+                light.cast_shadows = shadows;
             });
-        self.renderer.set_lights(&self.light_transforms);
+        self.renderer.set_lights(&self.lights);
+        self.renderer.set_spot_lights(&self.spot_lights);
+        if self.sun {
+            self.renderer.set_directional_lights(&[DirectionalLight {
+                direction: glm::vec3(-0.3, -1.0, -0.5),
+                color: glm::vec3(1.0, 0.95, 0.8),
+                intensity: 0.5,
+                cast_shadows: self.shadows,
+            }]);
+        } else {
+            self.renderer.set_directional_lights(&[]);
+        }
 
         self.renderer.end_draw();
+
+        if let Some(capture) = &mut self.capture {
+            let image = self.renderer.capture();
+            capture.save(&image).expect("write failed");
+        }
     }
     fn name(&self) -> &String {
         &self.name
@@ -136,7 +222,7 @@ impl Layer for SandboxLayer {
             .always_auto_resize(true)
             .save_settings(false)
             .collapsed(false, imgui::Condition::FirstUseEver)
-            .build(&ui, || {
+            .build(ui, || {
                 // cube slider
                 {
                     let mut cube_count = self.cube_count as i32;
@@ -152,6 +238,34 @@ impl Layer for SandboxLayer {
                         }
                     }
                 }
+                // frustum culling
+                {
+                    let mut frustum_culling = self.renderer.frustum_culling();
+                    if ui.checkbox(imgui::im_str!("Frustum Culling"), &mut frustum_culling) {
+                        self.renderer.set_frustum_culling(frustum_culling);
+                    }
+                    if glamour::gpu_culling_supported() {
+                        let mut gpu_culling = self.renderer.gpu_culling();
+                        if ui.checkbox(imgui::im_str!("GPU Culling"), &mut gpu_culling) {
+                            self.renderer.set_gpu_culling(gpu_culling);
+                        }
+                    }
+                    let stats = self.renderer.cull_stats();
+                    ui.text(format!("Drawn: {} Culled: {}", stats.drawn, stats.culled));
+                    if imgui::ComboBox::new(imgui::im_str!("Instance Streaming")).build_simple(
+                        ui,
+                        &mut self.selected_stream_mode,
+                        &StreamMode::ALL,
+                        &|m| std::borrow::Cow::from(imgui::ImString::new(m.to_string())),
+                    ) {
+                        self.renderer
+                            .set_stream_mode(StreamMode::ALL[self.selected_stream_mode]);
+                        // unsupported modes fall back
+                        let mode = self.renderer.stream_mode();
+                        self.selected_stream_mode =
+                            StreamMode::ALL.iter().position(|m| *m == mode).unwrap();
+                    }
+                }
                 // light slider
                 {
                     let mut light_slider = self.light_count as i32;
@@ -168,6 +282,54 @@ impl Layer for SandboxLayer {
                         }
                     }
                 }
+                // spot light slider
+                {
+                    let mut spot_slider = self.spot_light_count as i32;
+                    unsafe {
+                        if imgui::sys::igSliderInt(
+                            CString::new("Spot Light Count").unwrap().as_ptr(),
+                            &mut spot_slider,
+                            0,
+                            self.max_lights as i32,
+                            CString::new("%d").unwrap().as_ptr(),
+                        ) {
+                            self.spot_light_count =
+                                spot_slider.max(0).min(self.max_lights as i32) as usize;
+                        }
+                    }
+                }
+                ui.checkbox(imgui::im_str!("Sun"), &mut self.sun);
+                ui.checkbox(imgui::im_str!("Shadows"), &mut self.shadows);
+                ui.checkbox(imgui::im_str!("Glass"), &mut self.glass);
+                // environment map, skybox and reflections
+                {
+                    if ui.checkbox(imgui::im_str!("Environment"), &mut self.environment) {
+                        let environment = if self.environment {
+                            Some(self.sky.clone())
+                        } else {
+                            None
+                        };
+                        self.renderer.set_environment_map(environment);
+                    }
+                    let mut skybox = self.renderer.skybox_enabled();
+                    if ui.checkbox(imgui::im_str!("Skybox"), &mut skybox) {
+                        self.renderer.set_skybox_enabled(skybox);
+                    }
+                    let material = self.renderer.default_material();
+                    let mut reflectivity = self.renderer.material(material).reflectivity;
+                    unsafe {
+                        if imgui::sys::igSliderFloat(
+                            CString::new("Reflectivity").unwrap().as_ptr(),
+                            &mut reflectivity,
+                            0.0,
+                            1.0,
+                            CString::new("%.2f").unwrap().as_ptr(),
+                            1.0,
+                        ) {
+                            self.renderer.material_mut(material).reflectivity = reflectivity;
+                        }
+                    }
+                }
                 // resolution combo box
                 {
                     let resolutions: Vec<(u32, u32)> =
@@ -192,17 +354,169 @@ impl Layer for SandboxLayer {
                 }
                 // forward/deferred combo box
                 {
-                    let values: Vec<(&str, bool)> = vec![("deferred", true), ("forward", false)];
+                    // (name, deferred, light volumes)
+                    let values: Vec<(&str, bool, bool)> = vec![
+                        ("deferred", true, false),
+                        ("deferred (light volumes)", true, true),
+                        ("forward", false, false),
+                    ];
                     if imgui::ComboBox::new(imgui::im_str!("Renderer")).build_simple(
                         ui,
                         &mut self.selected_renderer,
                         &values,
-                        &|v| std::borrow::Cow::from(imgui::ImString::new(format!("{}", v.0))),
+                        &|v| std::borrow::Cow::from(imgui::ImString::new(v.0)),
                     ) {
                         let def = values[self.selected_renderer];
                         self.renderer.set_deferred(def.1);
+                        self.renderer.set_light_volumes(def.2);
+                    }
+                    if imgui::ComboBox::new(imgui::im_str!("G-Buffer")).build_simple(
+                        ui,
+                        &mut self.selected_g_buf_layout,
+                        &GBufLayout::ALL,
+                        &|l| std::borrow::Cow::from(imgui::ImString::new(l.to_string())),
+                    ) {
+                        let layout = GBufLayout::ALL[self.selected_g_buf_layout];
+                        if self.renderer.set_g_buf_layout(layout).is_err() {
+                            // keep showing the layout that's still in use
+                            let current = self.renderer.g_buf_layout();
+                            self.selected_g_buf_layout =
+                                GBufLayout::ALL.iter().position(|l| *l == current).unwrap();
+                        }
+                    }
+                    // forward only
+                    let samples: [u32; 4] = [0, 2, 4, 8];
+                    if imgui::ComboBox::new(imgui::im_str!("MSAA")).build_simple(
+                        ui,
+                        &mut self.selected_msaa,
+                        &samples,
+                        &|s| std::borrow::Cow::from(imgui::ImString::new(format!("{}x", s))),
+                    ) {
+                        self.renderer.set_msaa_samples(samples[self.selected_msaa]);
+                    }
+                }
+                // HDR and tone mapping
+                {
+                    let mut hdr = self.renderer.hdr();
+                    if ui.checkbox(imgui::im_str!("HDR"), &mut hdr) {
+                        self.renderer.set_hdr(hdr);
+                    }
+                    if imgui::ComboBox::new(imgui::im_str!("Tone Mapping")).build_simple(
+                        ui,
+                        &mut self.selected_tone_map,
+                        &ToneMap::ALL,
+                        &|t| std::borrow::Cow::from(imgui::ImString::new(t.to_string())),
+                    ) {
+                        self.renderer
+                            .set_tone_map(ToneMap::ALL[self.selected_tone_map]);
+                    }
+                    let mut exposure = self.renderer.exposure();
+                    unsafe {
+                        if imgui::sys::igSliderFloat(
+                            CString::new("Exposure").unwrap().as_ptr(),
+                            &mut exposure,
+                            0.05,
+                            8.0,
+                            CString::new("%.2f").unwrap().as_ptr(),
+                            2.0,
+                        ) {
+                            self.renderer.set_exposure(exposure);
+                        }
+                    }
+                    let mut srgb_output = self.renderer.srgb_output();
+                    if ui.checkbox(imgui::im_str!("sRGB Output"), &mut srgb_output) {
+                        self.renderer.set_srgb_output(srgb_output);
+                    }
+                }
+                // ambient occlusion, deferred only
+                {
+                    let mut ssao = self.renderer.ssao_enabled();
+                    if ui.checkbox(imgui::im_str!("SSAO"), &mut ssao) {
+                        self.renderer.set_ssao_enabled(ssao);
+                    }
+                    let ssao = self.renderer.ssao_mut();
+                    let mut radius = ssao.radius();
+                    let mut bias = ssao.bias();
+                    let mut kernel_size = ssao.kernel_size() as i32;
+                    unsafe {
+                        if imgui::sys::igSliderFloat(
+                            CString::new("SSAO Radius").unwrap().as_ptr(),
+                            &mut radius,
+                            0.05,
+                            4.0,
+                            CString::new("%.2f").unwrap().as_ptr(),
+                            1.0,
+                        ) {
+                            ssao.set_radius(radius);
+                        }
+                        if imgui::sys::igSliderFloat(
+                            CString::new("SSAO Bias").unwrap().as_ptr(),
+                            &mut bias,
+                            0.0,
+                            0.2,
+                            CString::new("%.3f").unwrap().as_ptr(),
+                            1.0,
+                        ) {
+                            ssao.set_bias(bias);
+                        }
+                        if imgui::sys::igSliderInt(
+                            CString::new("SSAO Kernel Size").unwrap().as_ptr(),
+                            &mut kernel_size,
+                            1,
+                            SSAO_MAX_KERNEL_SIZE as i32,
+                            CString::new("%d").unwrap().as_ptr(),
+                        ) {
+                            ssao.set_kernel_size(kernel_size as usize);
+                        }
+                    }
+                }
+                // post-processing toggles, in the order they're applied
+                {
+                    let effects = self.renderer.post_process().order().to_vec();
+                    for effect in effects {
+                        let post_process = self.renderer.post_process_mut();
+                        let mut enabled = post_process.enabled(effect);
+                        let label = imgui::ImString::new(post_process.effect_name(effect));
+                        if ui.checkbox(&label, &mut enabled) {
+                            post_process.set_enabled(effect, enabled);
+                        }
                     }
                 }
             });
     }
 }
+
+/// A sky fading from the horizon up to deep blue, over a dark ground, resampled from an
+/// equirectangular gradient. The sun is brighter than 1, so it blooms with HDR on.
+fn sky_cube_map() -> CubeMap {
+    let (width, height) = (512, 256);
+    let sun = glm::normalize(&glm::vec3(0.4, 0.3, -1.0));
+    let pixels: Vec<glm::Vec3> = (0..width * height)
+        .into_par_iter()
+        .map(|i| {
+            let longitude = ((i % width) as f32 + 0.5) / width as f32 - 0.5;
+            let latitude = 0.5 - ((i / width) as f32 + 0.5) / height as f32;
+            let (longitude, latitude) = (
+                longitude * 2.0 * std::f32::consts::PI,
+                latitude * std::f32::consts::PI,
+            );
+            let direction = glm::vec3(
+                latitude.cos() * longitude.cos(),
+                latitude.sin(),
+                latitude.cos() * longitude.sin(),
+            );
+            if direction.y < 0.0 {
+                return glm::vec3(0.12, 0.1, 0.08);
+            }
+            let horizon = glm::vec3(0.8, 0.85, 0.9);
+            let zenith = glm::vec3(0.15, 0.3, 0.7);
+            let sky = glm::lerp(&horizon, &zenith, direction.y.sqrt());
+            let glow = glm::dot(&direction, &sun).max(0.0).powf(512.0) * 20.0;
+            sky + glm::vec3(1.0, 0.9, 0.7) * glow
+        })
+        .collect();
+    TextureBuilder::new()
+        .with_format(TextureFormat::Rgb16F)
+        .build_cube_map_from_equirect(width as u32, height as u32, &pixels, 512)
+        .expect("failed to create sky cube map")
+}